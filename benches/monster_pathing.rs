@@ -0,0 +1,91 @@
+//! Compares one monster turn of pathing towards the player on a crowded 80x50 map:
+//! every mob running the game's A* search, against all of them descending the shared flow field.
+
+use criterion::{criterion_group, criterion_main, Criterion};
+use rltk::Point;
+
+// The game is a binary, so the map module is pulled in directly
+#[allow(dead_code)]
+#[path = "../src/map/mod.rs"]
+mod map;
+use map::{a_star_search, tile_walkable, FlowField, Map, TileType};
+
+const WIDTH: i32 = 80;
+const HEIGHT: i32 = 50;
+const MOBS: usize = 60;
+
+/// A walled arena broken up by rows of pillars, so paths have to bend
+fn arena() -> Map {
+    let mut map = Map::new(1, WIDTH, HEIGHT, "Bench Arena");
+    for y in 1..HEIGHT - 1 {
+        for x in 1..WIDTH - 1 {
+            let pillar = x % 6 == 3 && y % 8 != 4;
+            if !pillar {
+                let idx = map.xy_idx(x, y);
+                map.tiles[idx] = TileType::Floor;
+            }
+        }
+    }
+    map.populate_blocked();
+    map
+}
+
+/// Spreads the mobs over the open tiles on the far side of the map from the player
+fn mob_positions(map: &Map) -> Vec<Point> {
+    (0..WIDTH * HEIGHT)
+        .map(|idx| Point::new(idx % WIDTH, idx / WIDTH))
+        .filter(|p| p.x > WIDTH / 2 && !map.blocked[map.xy_idx(p.x, p.y)])
+        .step_by(17)
+        .take(MOBS)
+        .collect()
+}
+
+/// The walls, as the flow field system hands them over each turn
+fn obstacles(map: &Map) -> Vec<bool> {
+    map.tiles.iter().map(|tile| !tile_walkable(*tile)).collect()
+}
+
+fn monster_turn(c: &mut Criterion) {
+    let mut map = arena();
+    let player = Point::new(2, HEIGHT / 2);
+    let mobs = mob_positions(&map);
+    for mob in mobs.iter() {
+        let idx = map.xy_idx(mob.x, mob.y);
+        map.blocked[idx] = true;
+    }
+
+    let mut group = c.benchmark_group("monster_turn");
+    group.bench_function("a_star_per_mob", |b| {
+        b.iter(|| {
+            mobs.iter()
+                .filter_map(|mob| a_star_search(*mob, player, 2.0, &map))
+                .count()
+        })
+    });
+    group.bench_function("flow_field_rebuilt", |b| {
+        b.iter(|| {
+            let walls = obstacles(&map);
+            let mut field = FlowField::new();
+            field.refresh(&mut map, player, walls);
+            mobs.iter()
+                .filter_map(|mob| field.next_step(&map, *mob))
+                .count()
+        })
+    });
+    group.bench_function("flow_field_cached", |b| {
+        let mut field = FlowField::new();
+        let walls = obstacles(&map);
+        field.refresh(&mut map, player, walls);
+        b.iter(|| {
+            let walls = obstacles(&map);
+            field.refresh(&mut map, player, walls);
+            mobs.iter()
+                .filter_map(|mob| field.next_step(&map, *mob))
+                .count()
+        })
+    });
+    group.finish();
+}
+
+criterion_group!(benches, monster_turn);
+criterion_main!(benches);
@@ -1,33 +1,80 @@
-use super::{Carnivore, Herbivore, Item, Map, Point, Position, RunState, Viewshed, WantsToMelee};
+use super::{
+    gamelog::GameLog,
+    monster_ai_system::{idle_movement, pack_leader, surround_target, HUNT_TURNS},
+    AiState, Carnivore, Faction, Guarding, Herbivore, Map, Modifiers, MonsterMemory, Morale, Name,
+    Pack, Point, Position, Reputation, RunState, Viewshed, Wandering, WantsToMelee,
+};
+use crate::prefabs::{reaction_towards, Reaction, PREFABS};
 use legion::prelude::*;
+use rltk::RandomNumberGenerator;
+use std::collections::{HashMap, HashSet};
 
 pub fn build() -> Box<(dyn Schedulable + 'static)> {
     SystemBuilder::new("animal_ai")
         .with_query(<(Write<Viewshed>, Write<Position>)>::query().filter(tag::<Herbivore>()))
         .with_query(<(Write<Viewshed>, Write<Position>)>::query().filter(tag::<Carnivore>()))
+        .with_query(<(Read<Pack>, Read<MonsterMemory>, Read<Position>)>::query())
         .write_resource::<Map>()
         .read_resource::<RunState>()
-        .read_resource::<Entity>()
-        .read_component::<Item>()
+        .write_resource::<GameLog>()
+        .write_resource::<RandomNumberGenerator>()
+        .read_component::<Faction>()
+        .read_component::<Reputation>()
+        .read_component::<Modifiers>()
+        .read_component::<Pack>()
+        .read_component::<Name>()
+        .write_component::<MonsterMemory>()
+        .write_component::<Morale>()
+        .write_component::<Wandering>()
+        .read_component::<Guarding>()
         .build(
             |command_buffer,
              world,
-             (map, runstate, player_entity),
-             (query_herbivore, query_carnivore)| unsafe {
+             (map, runstate, log, rng),
+             (query_herbivore, query_carnivore, pack_query)| unsafe {
                 if **runstate != RunState::MonsterTurn {
                     return;
                 }
+                let pm = PREFABS.lock().unwrap();
+
+                // What each hunting pack saw last turn, and where its leaders are
+                let mut pack_sightings: HashMap<i32, Point> = HashMap::new();
+                let mut pack_leaders: HashMap<i32, Point> = HashMap::new();
+                for (pack, memory, pos) in pack_query.iter(world) {
+                    if memory.state == AiState::Hunting && memory.turns_since_seen == 0 {
+                        pack_sightings.insert(pack.id, memory.last_seen);
+                    }
+                    if pack.leader {
+                        pack_leaders.insert(pack.id, Point::new(pos.x, pos.y));
+                    }
+                }
+                let mut claimed_slots: HashSet<usize> = HashSet::new();
 
                 // Herbivores run away a lot
-                for (mut viewshed, mut pos) in query_herbivore.iter_unchecked(world) {
+                for (entity, (mut viewshed, mut pos)) in
+                    query_herbivore.iter_entities_unchecked(world)
+                {
+                    let my_faction = match world.get_component::<Faction>(entity) {
+                        Some(faction) => faction.name.clone(),
+                        None => continue,
+                    };
                     let mut run_away_from = Vec::new();
                     for other_tile in viewshed.visible_tiles.iter() {
                         let view_idx = map.xy_idx(other_tile.x, other_tile.y);
                         for other_entity in map.tile_content[view_idx].iter() {
-                            // They don't run away from items
-                            if world.get_component::<Item>(*other_entity).is_none() {
-                                // TODO: log.debug(entity is running away from other_entity)
-                                run_away_from.push(view_idx);
+                            if let Some(faction) = world.get_component::<Faction>(*other_entity) {
+                                let reputation = world.get_component::<Reputation>(*other_entity);
+                                let modifiers = world.get_component::<Modifiers>(*other_entity);
+                                if reaction_towards(
+                                    &my_faction,
+                                    &faction.name,
+                                    reputation.as_deref(),
+                                    modifiers.as_deref(),
+                                    &pm,
+                                ) == Reaction::Flee
+                                {
+                                    run_away_from.push(view_idx);
+                                }
                             }
                         }
                     }
@@ -56,28 +103,50 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                     }
                 }
 
-                // Carnivores just want to eat everything
+                // Carnivores go after anything their faction hates
                 for (entity, (mut viewshed, mut pos)) in
                     query_carnivore.iter_entities_unchecked(world)
                 {
+                    let my_faction = match world.get_component::<Faction>(entity) {
+                        Some(faction) => faction.name.clone(),
+                        None => continue,
+                    };
+
+                    // Sleeping carnivores lie still until a noise or a wound wakes them
+                    if world
+                        .get_component::<MonsterMemory>(entity)
+                        .map_or(false, |memory| memory.state == AiState::Asleep)
+                    {
+                        continue;
+                    }
+
+                    let pack = world.get_component::<Pack>(entity).map(|pack| *pack);
                     let mut run_towards = Vec::new();
-                    let mut attacked = false;
+                    let mut enemy_tiles = Vec::new();
+                    let mut adjacent_prey = None;
+                    let mut prey_seen = None;
                     for other_tile in viewshed.visible_tiles.iter() {
                         let view_idx = map.xy_idx(other_tile.x, other_tile.y);
                         for other_entity in map.tile_content[view_idx].iter() {
-                            if world.get_tag::<Herbivore>(*other_entity).is_some()
-                                || *other_entity == **player_entity
-                            {
+                            let reputation = world.get_component::<Reputation>(*other_entity);
+                            let modifiers = world.get_component::<Modifiers>(*other_entity);
+                            let reaction = match world.get_component::<Faction>(*other_entity) {
+                                Some(faction) => reaction_towards(
+                                    &my_faction,
+                                    &faction.name,
+                                    reputation.as_deref(),
+                                    modifiers.as_deref(),
+                                    &pm,
+                                ),
+                                None => Reaction::Ignore,
+                            };
+                            if reaction == Reaction::Attack {
+                                prey_seen.get_or_insert(*other_tile);
+                                enemy_tiles.push(view_idx);
                                 let distance = rltk::DistanceAlg::Pythagoras
                                     .distance2d(Point::new(pos.x, pos.y), *other_tile);
                                 if distance < 1.5 {
-                                    command_buffer.add_component(
-                                        entity,
-                                        WantsToMelee {
-                                            target: *other_entity,
-                                        },
-                                    );
-                                    attacked = true;
+                                    adjacent_prey = Some(*other_entity);
                                 } else {
                                     run_towards.push(view_idx);
                                 }
@@ -85,7 +154,141 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                         }
                     }
 
-                    if !run_towards.is_empty() && !attacked {
+                    // Wounds break a carnivore's nerve, and it comes back once nothing is in sight
+                    let mut fleeing = false;
+                    if let Some(mut morale) = world.get_component_mut_unchecked::<Morale>(entity) {
+                        if prey_seen.is_none() {
+                            morale.current = i32::min(morale.max, morale.current + 1);
+                        }
+                        let was_fleeing;
+                        match world.get_component_mut_unchecked::<MonsterMemory>(entity) {
+                            Some(mut memory) => {
+                                was_fleeing = memory.state == AiState::Fleeing;
+                                if was_fleeing && morale.current >= morale.max / 2 {
+                                    memory.state = AiState::Idle;
+                                } else if morale.current <= 0 {
+                                    memory.state = AiState::Fleeing;
+                                }
+                                fleeing = memory.state == AiState::Fleeing;
+                            }
+                            None => {
+                                was_fleeing = false;
+                                fleeing = morale.current <= 0;
+                            }
+                        }
+                        let idx = map.xy_idx(pos.x, pos.y);
+                        if fleeing && !was_fleeing && map.visible_tiles[idx] {
+                            if let Some(name) = world.get_component::<Name>(entity) {
+                                log.entries.push(format!("{} turns and flees!", name.name));
+                            }
+                        }
+                    }
+
+                    // Run from every enemy in sight, fighting back only when cornered
+                    if fleeing {
+                        let mut moved = false;
+                        if !enemy_tiles.is_empty() {
+                            let entity_idx = map.xy_idx(pos.x, pos.y);
+                            map.populate_blocked();
+                            let flee_map = rltk::DijkstraMap::new(
+                                map.width as usize,
+                                map.height as usize,
+                                &enemy_tiles,
+                                &**map,
+                                10.0,
+                            );
+                            let flee_target =
+                                rltk::DijkstraMap::find_highest_exit(&flee_map, entity_idx, &**map);
+                            if let Some(flee_target) = flee_target {
+                                if !map.blocked[flee_target] {
+                                    map.blocked[entity_idx] = false;
+                                    map.blocked[flee_target] = true;
+                                    viewshed.dirty = true;
+                                    pos.x = flee_target as i32 % map.width;
+                                    pos.y = flee_target as i32 / map.width;
+                                    moved = true;
+                                }
+                            }
+                        }
+                        if let (false, Some(target)) = (moved, adjacent_prey) {
+                            command_buffer.add_component(entity, WantsToMelee { target });
+                        }
+                        continue;
+                    }
+
+                    if let Some(target) = adjacent_prey {
+                        command_buffer.add_component(entity, WantsToMelee { target });
+                    }
+
+                    // Pack hunters keep after prey they, or their packmates, have lost sight of
+                    let mut hunting = false;
+                    if let Some(mut memory) =
+                        world.get_component_mut_unchecked::<MonsterMemory>(entity)
+                    {
+                        match prey_seen {
+                            Some(prey) => memory.hunt(prey),
+                            None => {
+                                memory.turns_since_seen += 1;
+                                if let Some(sighting) =
+                                    pack.and_then(|pack| pack_sightings.get(&pack.id))
+                                {
+                                    memory.share(*sighting);
+                                }
+                                if memory.state == AiState::Hunting {
+                                    if memory.turns_since_seen > HUNT_TURNS
+                                        || (pos.x == memory.last_seen.x
+                                            && pos.y == memory.last_seen.y)
+                                    {
+                                        memory.state = AiState::Idle;
+                                    } else {
+                                        run_towards.push(
+                                            map.xy_idx(memory.last_seen.x, memory.last_seen.y),
+                                        );
+                                    }
+                                }
+                            }
+                        }
+                        hunting = memory.state == AiState::Hunting;
+                    }
+
+                    if adjacent_prey.is_some() {
+                        continue;
+                    }
+
+                    // A pack spreads out around its prey rather than queueing up behind each other
+                    if let (Some(_), Some(prey)) = (pack, prey_seen) {
+                        if surround_target(
+                            &mut **map,
+                            &mut *pos,
+                            &mut *viewshed,
+                            prey,
+                            &mut claimed_slots,
+                        )
+                        .is_some()
+                        {
+                            continue;
+                        }
+                    }
+
+                    // With nothing to chase, keep up with the pack or go about its business
+                    if run_towards.is_empty() && !hunting {
+                        let mut wander = world.get_component_mut_unchecked::<Wandering>(entity);
+                        let post = world
+                            .get_component::<Guarding>(entity)
+                            .map(|guard| guard.post);
+                        idle_movement(
+                            &mut **map,
+                            &mut *pos,
+                            &mut *viewshed,
+                            &mut **rng,
+                            pack_leader(pack, &pack_leaders),
+                            wander.as_deref_mut(),
+                            post,
+                        );
+                        continue;
+                    }
+
+                    if !run_towards.is_empty() {
                         let entity_idx = map.xy_idx(pos.x, pos.y);
                         map.populate_blocked();
                         let chase_map = rltk::DijkstraMap::new(
@@ -1,22 +1,48 @@
-use super::{gamelog::GameLog, Bystander, Map, Name, Point, Position, Quips, RunState, Viewshed};
+use super::{
+    a_star_search, clock_system::GameClock, gamelog::GameLog, BlocksTile, BlocksVisibility,
+    Bystander, DailyRoutine, Door, FlowField, Lock, Map, Name, Point, Position, Quips, Renderable,
+    RunState, Vendor, Viewshed,
+};
 use legion::prelude::*;
 
 pub fn build() -> Box<(dyn Schedulable + 'static)> {
     SystemBuilder::new("bystander_ai")
-        .with_query(<(Write<Viewshed>, Write<Position>)>::query().filter(tag::<Bystander>()))
+        .with_query(
+            <(Write<Viewshed>, Write<Position>)>::query()
+                .filter(tag::<Bystander>() | tag::<Vendor>()),
+        )
+        .with_query(<(Read<Door>, Read<Position>)>::query())
         .write_resource::<Map>()
         .read_resource::<RunState>()
         .write_resource::<rltk::RandomNumberGenerator>()
         .read_resource::<Point>()
         .write_resource::<GameLog>()
+        .read_resource::<GameClock>()
+        .write_resource::<FlowField>()
         .write_component::<Quips>()
         .read_component::<Name>()
+        .read_component::<DailyRoutine>()
+        .read_component::<Lock>()
+        .write_component::<Door>()
+        .write_component::<Renderable>()
         .build(
-            |_, world, (map, runstate, rng, player_pos, gamelog), query| unsafe {
+            |command_buffer,
+             world,
+             (map, runstate, rng, player_pos, gamelog, clock, flow_field),
+             (query, door_query)| unsafe {
                 if **runstate != RunState::MonsterTurn {
                     return;
                 }
 
+                // Townsfolk can let themselves through unlocked doors
+                let mut closed_doors: Vec<usize> = door_query
+                    .iter_entities(world)
+                    .filter(|(entity, (door, _pos))| {
+                        !door.open && world.get_component::<Lock>(*entity).is_none()
+                    })
+                    .map(|(_entity, (_door, pos))| map.xy_idx(pos.x, pos.y))
+                    .collect();
+
                 for (entity, (mut viewshed, mut pos)) in query.iter_entities_unchecked(world) {
                     // Possibly quip
                     if let Some(mut quips) = world.get_component_mut_unchecked::<Quips>(entity) {
@@ -39,6 +65,71 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                         }
                     }
 
+                    // Be wherever the time of day calls for
+                    let area = world
+                        .get_component::<DailyRoutine>(entity)
+                        .and_then(|routine| {
+                            if clock.is_night() {
+                                routine.night
+                            } else {
+                                routine.day
+                            }
+                        });
+                    if let Some(area) = area {
+                        if !area.contains(pos.x, pos.y) {
+                            let (x, y) = area.center();
+                            for idx in closed_doors.iter() {
+                                map.blocked[*idx] = false;
+                            }
+                            let path = a_star_search(
+                                Point::new(pos.x, pos.y),
+                                Point::new(x, y),
+                                2.0,
+                                &**map,
+                            );
+                            for idx in closed_doors.iter() {
+                                map.blocked[*idx] = true;
+                            }
+
+                            if let Some((path, _cost)) = path {
+                                if path.len() > 1 {
+                                    let dest_idx = map.xy_idx(path[1].x, path[1].y);
+                                    if closed_doors.contains(&dest_idx) {
+                                        for door_entity in map.tile_content[dest_idx].iter() {
+                                            if let Some(mut door) = world
+                                                .get_component_mut_unchecked::<Door>(*door_entity)
+                                            {
+                                                door.open = true;
+                                                command_buffer
+                                                    .remove_tag::<BlocksVisibility>(*door_entity);
+                                                command_buffer
+                                                    .remove_tag::<BlocksTile>(*door_entity);
+                                                flow_field.invalidate();
+                                                if let Some(mut glyph) = world
+                                                    .get_component_mut_unchecked::<Renderable>(
+                                                        *door_entity,
+                                                    )
+                                                {
+                                                    glyph.glyph = rltk::to_cp437('/');
+                                                }
+                                            }
+                                        }
+                                        closed_doors.retain(|idx| *idx != dest_idx);
+                                        map.blocked[dest_idx] = false;
+                                    } else if !map.blocked[dest_idx] {
+                                        let idx = map.xy_idx(pos.x, pos.y);
+                                        map.blocked[idx] = false;
+                                        pos.x = path[1].x;
+                                        pos.y = path[1].y;
+                                        map.blocked[dest_idx] = true;
+                                        viewshed.dirty = true;
+                                    }
+                                }
+                            }
+                            continue;
+                        }
+                    }
+
                     // Try to move randomly
                     let mut x = pos.x;
                     let mut y = pos.y;
@@ -51,7 +142,10 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                         _ => {}
                     }
 
-                    if x >= 0 && x < map.width - 1 && y >= 0 && y < map.height - 1 {
+                    // ...without straying from where they should be
+                    let in_area = area.map_or(true, |area| area.contains(x, y));
+
+                    if in_area && x >= 0 && x < map.width - 1 && y >= 0 && y < map.height - 1 {
                         let dest_idx = map.xy_idx(x, y);
                         if !map.blocked[dest_idx] {
                             let idx = map.xy_idx(pos.x, pos.y);
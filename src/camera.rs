@@ -21,6 +21,17 @@ pub fn get_screen_bounds(resources: &Resources, _ctx: &Rltk) -> (i32, i32, i32,
     (min_x, max_x, min_y, max_y)
 }
 
+// Even unlit tiles keep some of their color
+const MIN_BRIGHTNESS: f32 = 0.3;
+
+fn apply_light(color: RGB, light: RGB) -> RGB {
+    RGB::from_f32(
+        color.r * f32::max(MIN_BRIGHTNESS, light.r),
+        color.g * f32::max(MIN_BRIGHTNESS, light.g),
+        color.b * f32::max(MIN_BRIGHTNESS, light.b),
+    )
+}
+
 pub fn render_camera(world: &World, resources: &Resources, ctx: &mut Rltk) {
     let map = resources.get::<Map>().unwrap();
     let (min_x, max_x, min_y, max_y) = get_screen_bounds(resources, ctx);
@@ -33,7 +44,11 @@ pub fn render_camera(world: &World, resources: &Resources, ctx: &mut Rltk) {
             if tx >= 0 && tx < map.width && ty >= 0 && ty < map.height {
                 let idx = map.xy_idx(tx, ty);
                 if map.revealed_tiles[idx] {
-                    let (glyph, fg, bg) = tile_glyph(idx, &*map);
+                    let (glyph, mut fg, mut bg) = tile_glyph(idx, &*map);
+                    if map.visible_tiles[idx] {
+                        fg = apply_light(fg, map.light[idx]);
+                        bg = apply_light(bg, map.light[idx]);
+                    }
                     ctx.set(x, y, fg, bg, glyph);
                 }
             } else if SHOW_BOUNDARIES {
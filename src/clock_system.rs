@@ -0,0 +1,72 @@
+use super::RunState;
+use legion::prelude::*;
+use serde::{Deserialize, Serialize};
+use type_uuid::TypeUuid;
+
+pub const TURNS_PER_HOUR: i32 = 30;
+const HOURS_PER_DAY: i32 = 24;
+const START_HOUR: i32 = 8;
+
+// Ambient light under the stars
+const NIGHT_LIGHT: f32 = 0.1;
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "e496dfe1-6832-4000-b0ab-f143759ddd89"]
+pub struct GameClock {
+    pub turn: i32,
+}
+
+impl GameClock {
+    pub fn new() -> Self {
+        GameClock {
+            turn: START_HOUR * TURNS_PER_HOUR,
+        }
+    }
+
+    /// Turns taken since the first morning
+    pub fn elapsed(&self) -> i32 {
+        self.turn - START_HOUR * TURNS_PER_HOUR
+    }
+
+    pub fn day(&self) -> i32 {
+        self.turn / (TURNS_PER_HOUR * HOURS_PER_DAY) + 1
+    }
+
+    pub fn hour(&self) -> i32 {
+        (self.turn / TURNS_PER_HOUR) % HOURS_PER_DAY
+    }
+
+    pub fn minute(&self) -> i32 {
+        (self.turn % TURNS_PER_HOUR) * 60 / TURNS_PER_HOUR
+    }
+
+    pub fn is_night(&self) -> bool {
+        self.hour() >= 20 || self.hour() < 6
+    }
+
+    /// Shops open in the morning and close in the evening
+    pub fn is_business_hours(&self) -> bool {
+        self.hour() >= 8 && self.hour() < 18
+    }
+
+    /// How bright the open sky is, rising at dawn (5-7) and falling at dusk (19-21)
+    pub fn daylight(&self) -> f32 {
+        let hour = (self.turn % (TURNS_PER_HOUR * HOURS_PER_DAY)) as f32 / TURNS_PER_HOUR as f32;
+        let dawn = (hour - 5.0) / 2.0;
+        let dusk = (21.0 - hour) / 2.0;
+        let sun = f32::max(0.0, f32::min(1.0, f32::min(dawn, dusk)));
+        NIGHT_LIGHT + (1.0 - NIGHT_LIGHT) * sun
+    }
+}
+
+pub fn build() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("clock")
+        .write_resource::<GameClock>()
+        .read_resource::<RunState>()
+        .build(|_, _, (clock, runstate), _| {
+            // One tick for each full round of turns
+            if **runstate == RunState::MonsterTurn {
+                clock.turn += 1;
+            }
+        })
+}
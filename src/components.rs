@@ -1,3 +1,5 @@
+use super::Rect;
+use crate::prefabs::Reaction;
 use legion::prelude::*;
 use rltk::{FontCharType, Point, RGB};
 use serde::{Deserialize, Serialize};
@@ -32,6 +34,19 @@ pub struct Viewshed {
     pub dirty: bool,
 }
 
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "ed24fdcf-403e-442b-ac35-07225e3e9298"]
+pub struct Darkvision {
+    pub range: i32,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "b14161db-330c-4d9b-ba89-641bf434ea4d"]
+pub struct LightSource {
+    pub color: RGB,
+    pub range: i32,
+}
+
 #[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 #[uuid = "b2f34c7c-63fa-4e6d-96a2-bbbeca8bedac"]
 pub struct Monster;
@@ -46,6 +61,12 @@ pub struct Name {
 #[uuid = "8b2e566c-2e72-48b0-954b-dffb83051683"]
 pub struct BlocksTile;
 
+#[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "adf00502-c4d0-4b50-a904-bf61917b1b5e"]
+pub struct Faction {
+    pub name: String,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct WantsToMelee {
     pub target: Entity,
@@ -65,18 +86,106 @@ impl SufferDamage {
         from_player: bool,
     ) {
         command_buffer.exec_mut(move |world| {
-            let mut dmg = if let Some(suffering) = world.get_component::<SufferDamage>(victim) {
-                (*suffering).clone()
-            } else {
-                SufferDamage { amount: Vec::new() }
-            };
-
-            dmg.amount.push((amount, from_player));
-            world
-                .add_component(victim, dmg)
-                .expect("Unable to insert damage");
+            SufferDamage::inflict(world, victim, amount, from_player);
         });
     }
+
+    /// As `new_damage`, for when we already have the whole world to hand
+    pub fn inflict(world: &mut World, victim: Entity, amount: i32, from_player: bool) {
+        let mut dmg = if let Some(suffering) = world.get_component::<SufferDamage>(victim) {
+            (*suffering).clone()
+        } else {
+            SufferDamage { amount: Vec::new() }
+        };
+
+        dmg.amount.push((amount, from_player));
+        world
+            .add_component(victim, dmg)
+            .expect("Unable to insert damage");
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AiState {
+    Asleep,
+    Idle,
+    Hunting,
+    Searching,
+    Fleeing,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "b7603ea6-774d-4f8b-9b7e-dcdc01ac302f"]
+pub struct MonsterMemory {
+    pub state: AiState,
+    pub last_seen: Point,
+    pub turns_since_seen: i32,
+}
+
+impl MonsterMemory {
+    pub fn new() -> Self {
+        MonsterMemory {
+            state: AiState::Idle,
+            last_seen: Point::new(0, 0),
+            turns_since_seen: 0,
+        }
+    }
+
+    /// Start hunting towards a known target position
+    pub fn hunt(&mut self, target: Point) {
+        self.state = AiState::Hunting;
+        self.last_seen = target;
+        self.turns_since_seen = 0;
+    }
+
+    /// Take up the hunt on a packmate's word, without having seen the target
+    pub fn share(&mut self, target: Point) {
+        if self.state == AiState::Asleep
+            || self.state == AiState::Fleeing
+            || (self.state == AiState::Hunting && self.turns_since_seen == 0)
+        {
+            return;
+        }
+        self.state = AiState::Hunting;
+        self.last_seen = target;
+        self.turns_since_seen = 1;
+    }
+
+    /// Go and check out a noise, unless a target is in plain sight or it is running away
+    pub fn investigate(&mut self, source: Point) {
+        if (self.state == AiState::Hunting && self.turns_since_seen == 0)
+            || self.state == AiState::Fleeing
+        {
+            return;
+        }
+        self.hunt(source);
+    }
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "e37e644d-e46a-47b1-b4a3-43a3f949dd98"]
+pub struct Morale {
+    pub max: i32,
+    pub current: i32,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "12aa77ec-8700-4c0b-bba0-5152db7f45fa"]
+pub struct Pack {
+    pub id: i32,
+    pub leader: bool,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "ec4466c8-5410-4e73-b27e-fede19b6c7e1"]
+pub struct Wandering {
+    pub destination: Option<Point>,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "ab2329b5-414c-4db7-bdc3-70ef916bdc33"]
+pub struct Guarding {
+    pub post: Point,
 }
 
 #[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -93,6 +202,22 @@ pub struct InBackpack {
     pub owner: Entity,
 }
 
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "29da2c11-5fd1-4a6d-ac9b-95d60dcbf526"]
+pub struct Container {
+    pub loot_rolls: i32,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq)]
+#[uuid = "14a686f2-fc14-4a97-a16f-26eb697b94bd"]
+pub struct InContainer {
+    pub owner: Entity,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "ed79895b-fa34-4f40-bd8a-6d6057d1808e"]
+pub struct Corpse;
+
 #[derive(Clone, Copy, Debug, PartialEq)]
 pub struct WantsToPickupItem {
     pub collected_by: Entity,
@@ -115,6 +240,18 @@ pub struct WantsToRemoveItem {
     pub item: Entity,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WantsToTakeItem {
+    pub container: Entity,
+    pub item: Option<Entity>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WantsToPutItem {
+    pub container: Entity,
+    pub item: Entity,
+}
+
 #[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 #[uuid = "fde630bf-14fc-46e6-8cd9-36a2cbb0734a"]
 pub struct ProvidesHealing {
@@ -127,6 +264,13 @@ pub struct Ranged {
     pub range: i32,
 }
 
+/// Reading this teaches the reader a spell
+#[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "86cd6e18-0e8e-458c-a2b8-91eb3acd7139"]
+pub struct TeachesSpell {
+    pub spell: String,
+}
+
 #[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 #[uuid = "707b602d-12ed-4f49-8f1a-94adea423f71"]
 pub struct InflictsDamage {
@@ -194,6 +338,15 @@ pub struct HungerClock {
 #[uuid = "0457c4c2-e26a-4d05-a4cb-d4322a41e876"]
 pub struct ProvidesFood;
 
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "53c6d9f7-cc9e-42b6-a1a7-ce6431c76cc0"]
+pub struct Scroll;
+
+/// The prize at the bottom of the dungeon; bringing it back to town wins the game
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "630ac257-577e-435a-b905-4b71d3ea34ca"]
+pub struct MacGuffin;
+
 #[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 #[uuid = "74ea7770-fd58-43ce-a0b5-8ef6f8610d48"]
 pub struct MagicMapper;
@@ -210,6 +363,49 @@ pub struct EntryTrigger;
 #[uuid = "bfd72dfa-446e-49bc-99ab-259eb6e0cbf3"]
 pub struct SingleActivation;
 
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "4678b44a-6e09-4d1c-9376-93f050197333"]
+pub struct TeleportsEntity;
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "77631013-d73e-47e1-9a1e-09f5e6e791ad"]
+pub struct Trapdoor;
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "5fe33503-ad38-4a03-959b-f654083bec24"]
+pub struct Alarm {
+    pub radius: i32,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum GasType {
+    Confusion,
+    Poison,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "6878cd9e-7300-414d-aef8-9885a076cfbb"]
+pub struct SpawnsGas {
+    pub gas: GasType,
+    pub potency: i32,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "c9068130-8ebf-49df-a1f0-5fc5392dcebb"]
+pub struct Gas {
+    pub gas: GasType,
+    pub potency: i32,
+    pub spread: i32,
+    pub lifetime: i32,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "e0c890be-322a-4b86-8fe7-f0203d4c9093"]
+pub struct Poisoned {
+    pub turns: i32,
+    pub damage: i32,
+}
+
 #[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 #[uuid = "4e87fd9c-ce64-4464-bf0f-ea1bca2d3827"]
 pub struct BlocksVisibility;
@@ -220,6 +416,26 @@ pub struct Door {
     pub open: bool,
 }
 
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "8077109d-0377-43bb-8c42-7f8328705d3b"]
+pub struct Lock {
+    pub key_id: i32,
+    pub difficulty: i32,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "d745527b-6e8b-4d8e-aed2-fc93fc6c250b"]
+pub struct Key {
+    pub key_id: i32,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "bcf794a0-3170-49e2-a71c-a81841021ad6"]
+pub struct Lockpick {
+    pub bonus: i32,
+    pub break_chance: i32,
+}
+
 #[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 #[uuid = "8937d655-3173-4646-9ff2-de4cce96285f"]
 pub struct Bystander;
@@ -228,12 +444,48 @@ pub struct Bystander;
 #[uuid = "401102d1-3cbb-451f-8989-c5b9aa7539bb"]
 pub struct Vendor;
 
+/// Where a townsperson spends the day and the night; `None` means wandering about town
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "c10c5057-838b-4b27-96f8-c077a9e2bff3"]
+pub struct DailyRoutine {
+    pub day: Option<Rect>,
+    pub night: Option<Rect>,
+}
+
 #[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[uuid = "a15abace-8292-4203-88e8-c2ba0093e789"]
 pub struct Quips {
     pub available: Vec<String>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum QuestStatus {
+    Active,
+    Complete,
+    TurnedIn,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct QuestProgress {
+    pub name: String,
+    pub progress: i32,
+    pub status: QuestStatus,
+}
+
+/// The quests the player has taken on, and how far along each of them is
+#[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "61f1b843-afa5-4472-9854-aa611d4bbf0d"]
+pub struct QuestLog {
+    pub quests: Vec<QuestProgress>,
+}
+
+/// Factions the player has talked into treating them differently
+#[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "d7e1c88e-ac12-41c1-a0f4-dd21b29cace5"]
+pub struct Reputation {
+    pub reactions: HashMap<String, Reaction>,
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 pub struct Attribute {
     pub base: i32,
@@ -255,6 +507,8 @@ pub enum Skill {
     Melee,
     Defense,
     Magic,
+    Lockpicking,
+    Perception,
 }
 
 #[derive(TypeUuid, Clone, Debug, Serialize, Deserialize)]
@@ -275,6 +529,109 @@ pub struct Pools {
     pub mana: Pool,
     pub experience: i32,
     pub level: i32,
+    pub gold: i32,
+}
+
+/// Points the player has earned by levelling up but not yet spent
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "97d08efb-2b8e-4c74-93c6-6485ce1daee8"]
+pub struct UnspentPoints {
+    pub attributes: i32,
+    pub skills: i32,
+    pub perks: i32,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct KnownSpell {
+    pub name: String,
+    pub mana_cost: i32,
+}
+
+/// The spells the player can cast, in the order they were learned
+#[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "99544a35-7340-424b-b6dc-012604e2af73"]
+pub struct KnownSpells {
+    pub spells: Vec<KnownSpell>,
+}
+
+/// The perks the player has taken
+#[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "d4c21929-21fa-4916-827e-a4ef9ef9183e"]
+pub struct Perks {
+    pub taken: Vec<String>,
+}
+
+/// A tally of how the run has gone, written to the morgue file when it ends
+#[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "a2fcc9b5-e7ae-4ea6-9e07-0a2c0855f0fc"]
+pub struct RunStats {
+    pub max_depth: i32,
+    pub kills: HashMap<String, i32>,
+    pub damage_dealt: i32,
+    pub damage_taken: i32,
+    pub items_used: HashMap<String, i32>,
+    pub last_hurt_by: Option<String>,
+    pub seed: u64,
+    pub victory: bool,
+}
+
+impl RunStats {
+    pub fn new() -> Self {
+        RunStats {
+            max_depth: 1,
+            kills: HashMap::new(),
+            damage_dealt: 0,
+            damage_taken: 0,
+            items_used: HashMap::new(),
+            last_hurt_by: None,
+            seed: 0,
+            victory: false,
+        }
+    }
+
+    /// Remembers what last hurt the victim, in case it turns out to be the cause of death
+    pub fn hurt_by(command_buffer: &CommandBuffer, victim: Entity, cause: String) {
+        command_buffer.exec_mut(move |world| {
+            RunStats::note_hurt(world, victim, cause);
+        });
+    }
+
+    /// As `hurt_by`, for when we already have the whole world to hand
+    pub fn note_hurt(world: &mut World, victim: Entity, cause: String) {
+        if let Some(mut stats) = world.get_component_mut::<RunStats>(victim) {
+            stats.last_hurt_by = Some(cause);
+        }
+    }
+}
+
+/// How far each skill has been practised towards its next rank
+#[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "cc9fe31d-5d0e-438b-bb22-7009e90e5e27"]
+pub struct SkillProgress {
+    pub practice: HashMap<Skill, i32>,
+}
+
+/// Bonuses from all taken perks, totalled up front so systems can just look them up
+#[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "b47ed88d-7e96-443b-8d66-43ab6f00dfcc"]
+pub struct Modifiers {
+    pub melee_hit: HashMap<WeaponCategory, i32>,
+    pub keep_scroll_chance: i32,
+    pub hunger_duration: i32,
+    pub light_radius: i32,
+    pub reactions: HashMap<String, Reaction>,
+}
+
+impl Modifiers {
+    pub fn new() -> Self {
+        Modifiers {
+            melee_hit: HashMap::new(),
+            keep_scroll_chance: 0,
+            hunger_duration: 0,
+            light_radius: 0,
+            reactions: HashMap::new(),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -283,6 +640,13 @@ pub enum WeaponAttribute {
     Quickness,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub enum WeaponCategory {
+    Blade,
+    Axe,
+    Blunt,
+}
+
 #[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
 #[uuid = "43a4e3d5-dc45-465a-9c42-4672dfca6c16"]
 pub struct MeleeWeapon {
@@ -291,6 +655,7 @@ pub struct MeleeWeapon {
     pub damage_die_type: i32,
     pub damage_bonus: i32,
     pub hit_bonus: i32,
+    pub category: Option<WeaponCategory>,
 }
 
 #[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
@@ -316,6 +681,39 @@ pub struct NaturalAttackDefense {
     pub attacks: Vec<NaturalAttack>,
 }
 
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum AbilityKind {
+    Missile,
+    Spell,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct SpecialAbility {
+    pub name: String,
+    pub kind: AbilityKind,
+    pub range: f32,
+    pub min_range: f32,
+    pub mana_cost: i32,
+    pub cooldown: i32,
+    pub cooldown_left: i32,
+    pub damage_n_dice: i32,
+    pub damage_die_type: i32,
+    pub damage_bonus: i32,
+    pub confusion_turns: i32,
+}
+
+#[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "3796b38b-a1d4-4d68-99ca-5bedb9882352"]
+pub struct SpecialAbilities {
+    pub abilities: Vec<SpecialAbility>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct WantsToUseAbility {
+    pub ability: usize,
+    pub target: Entity,
+}
+
 #[derive(TypeUuid, Clone, Debug, PartialEq, Serialize, Deserialize)]
 #[uuid = "4c71d15e-1263-4505-ab49-e4e18abbac22"]
 pub struct LootTable {
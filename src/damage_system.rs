@@ -1,42 +1,106 @@
 use super::{
-    gamelog::GameLog, mana_at_level, particle_system::ParticleBuilder, player_hp_at_level,
-    Attributes, Equipped, InBackpack, LootTable, Map, Name, Player, Point, Pools, Position,
-    RunState, SufferDamage,
+    gamelog::GameLog,
+    hall_of_fame,
+    level_up::{ATTRIBUTE_POINTS_PER_LEVEL, PERK_LEVEL_INTERVAL, SKILL_POINTS_PER_LEVEL},
+    mana_at_level, morgue,
+    particle_system::ParticleBuilder,
+    player_hp_at_level,
+    quest_system::{QuestEvent, QuestEvents},
+    AiState, Attributes, Corpse, Equipped, Faction, InBackpack, InContainer, LootTable, Map,
+    MonsterMemory, Morale, Name, Pack, Player, Point, Pools, Position, RunState, RunStats,
+    SufferDamage, UnspentPoints, Viewshed,
 };
-use crate::prefabs::{get_item_drop, spawn_named_item, SpawnType, PREFABS};
+use crate::prefabs::{get_item_drop, spawn_named_item, spawn_named_prop, SpawnType, PREFABS};
 use legion::prelude::*;
 
+// Morale lost for taking a mob's full hit points in damage, so that a mob with
+// the default morale breaks at about a third of its health
+const WOUND_MORALE: i32 = 15;
+// Morale lost for watching an ally die
+const ALLY_DEATH_MORALE: i32 = 3;
+
 pub fn build() -> Box<(dyn Schedulable + 'static)> {
     SystemBuilder::new("damage")
         .with_query(Write::<SufferDamage>::query())
         .write_component::<Pools>()
+        .write_component::<MonsterMemory>()
+        .write_component::<Morale>()
         .read_component::<Position>()
         .write_resource::<Map>()
         .read_resource::<Entity>()
         .write_resource::<GameLog>()
         .read_component::<Attributes>()
+        .write_component::<UnspentPoints>()
         .write_resource::<ParticleBuilder>()
         .read_resource::<Point>()
+        .read_component::<Name>()
+        .write_resource::<QuestEvents>()
+        .write_component::<RunStats>()
         .build(
             |command_buffer,
              world,
-             (map, player_entity, log, particles, player_pos),
+             (map, player_entity, log, particles, player_pos, quest_events),
              query| unsafe {
                 let mut xp_gain = 0;
                 for (entity, mut damage) in query.iter_entities_unchecked(world) {
+                    let mut run_stats =
+                        world.get_component_mut_unchecked::<RunStats>(**player_entity);
                     if let Some(mut stats) = world.get_component_mut_unchecked::<Pools>(entity) {
+                        let was_alive = stats.hit_points.current > 0;
                         for (dmg, from_player) in damage.amount.iter() {
                             stats.hit_points.current -= dmg;
+                            if let Some(run_stats) = run_stats.as_mut() {
+                                if entity == **player_entity {
+                                    run_stats.damage_taken += dmg;
+                                } else if *from_player {
+                                    run_stats.damage_dealt += dmg;
+                                }
+                            }
 
                             if stats.hit_points.current < 1 && *from_player {
                                 xp_gain += stats.level * 100;
                             }
                         }
 
+                        // Count the kill towards any quests
+                        if was_alive
+                            && stats.hit_points.current < 1
+                            && damage.amount.iter().any(|(_dmg, from_player)| *from_player)
+                        {
+                            if let Some(name) = world.get_component::<Name>(entity) {
+                                quest_events.record(QuestEvent::Kill(name.name.clone()));
+                                if let Some(run_stats) = run_stats.as_mut() {
+                                    *run_stats.kills.entry(name.name.clone()).or_insert(0) += 1;
+                                }
+                            }
+                        }
+
                         if let Some(pos) = world.get_component::<Position>(entity) {
                             let idx = map.xy_idx(pos.x, pos.y);
                             map.bloodstains.insert(idx);
                         }
+
+                        // Wounds wear down the will to fight
+                        if let Some(mut morale) =
+                            world.get_component_mut_unchecked::<Morale>(entity)
+                        {
+                            let total: i32 = damage.amount.iter().map(|(dmg, _)| dmg).sum();
+                            morale.current -=
+                                total * WOUND_MORALE / i32::max(1, stats.hit_points.max);
+                        }
+                    }
+
+                    // Getting hurt is a rude awakening
+                    if let Some(mut memory) =
+                        world.get_component_mut_unchecked::<MonsterMemory>(entity)
+                    {
+                        if memory.state == AiState::Asleep {
+                            if damage.amount.iter().any(|(_dmg, from_player)| *from_player) {
+                                memory.hunt(**player_pos);
+                            } else {
+                                memory.state = AiState::Idle;
+                            }
+                        }
                     }
 
                     damage.amount.clear();
@@ -46,99 +110,200 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                 if xp_gain != 0 {
                     let player_attributes =
                         *(world.get_component::<Attributes>(**player_entity).unwrap());
+                    let mut points = *(world
+                        .get_component::<UnspentPoints>(**player_entity)
+                        .unwrap());
                     let mut player_stats =
                         world.get_component_mut::<Pools>(**player_entity).unwrap();
-                    player_stats.experience += xp_gain;
-                    if player_stats.experience >= player_stats.level * 1000 {
-                        // We've gone up a level!
-                        player_stats.level = player_stats.experience / 1000 + 1;
-                        log.entries.push(format!(
-                            "Congratulations, you are now level {}",
-                            player_stats.level
-                        ));
-                        player_stats.hit_points.max = player_hp_at_level(
-                            player_attributes.fitness.base + player_attributes.fitness.modifiers,
-                            player_stats.level,
-                        );
-                        player_stats.hit_points.current = player_stats.hit_points.max;
-                        player_stats.mana.max = mana_at_level(
-                            player_attributes.intelligence.base
-                                + player_attributes.intelligence.modifiers,
-                            player_stats.level,
-                        );
-                        player_stats.mana.current = player_stats.mana.max;
-
-                        for i in 0..10 {
-                            if player_pos.y - i > 1 {
-                                particles.request(
-                                    player_pos.x,
-                                    player_pos.y - i,
-                                    rltk::RGB::named(rltk::GOLD),
-                                    rltk::RGB::named(rltk::BLACK),
-                                    rltk::to_cp437('░'),
-                                    400.0,
-                                );
-                            }
-                        }
+                    if gain_experience(
+                        &mut player_stats,
+                        &player_attributes,
+                        &mut points,
+                        xp_gain,
+                        log,
+                    ) {
+                        std::mem::drop(player_stats);
+                        *world
+                            .get_component_mut::<UnspentPoints>(**player_entity)
+                            .unwrap() = points;
+                        level_up_particles(particles, **player_pos);
                     }
                 }
             },
         )
 }
 
+/// Adds experience to the player, returning true if it was enough to go up a level
+pub fn gain_experience(
+    stats: &mut Pools,
+    attributes: &Attributes,
+    points: &mut UnspentPoints,
+    amount: i32,
+    log: &mut GameLog,
+) -> bool {
+    stats.experience += amount;
+    if stats.experience < stats.level * 1000 {
+        return false;
+    }
+
+    // We've gone up a level, and get something to spend for each one gained
+    let new_level = stats.experience / 1000 + 1;
+    points.attributes += (new_level - stats.level) * ATTRIBUTE_POINTS_PER_LEVEL;
+    points.skills += (new_level - stats.level) * SKILL_POINTS_PER_LEVEL;
+    points.perks += new_level / PERK_LEVEL_INTERVAL - stats.level / PERK_LEVEL_INTERVAL;
+    stats.level = new_level;
+    log.entries.push(format!(
+        "Congratulations, you are now level {}",
+        stats.level
+    ));
+    stats.hit_points.max = player_hp_at_level(
+        attributes.fitness.base + attributes.fitness.modifiers,
+        stats.level,
+    );
+    stats.hit_points.current = stats.hit_points.max;
+    stats.mana.max = mana_at_level(
+        attributes.intelligence.base + attributes.intelligence.modifiers,
+        stats.level,
+    );
+    stats.mana.current = stats.mana.max;
+    true
+}
+
+pub fn level_up_particles(particles: &mut ParticleBuilder, player_pos: Point) {
+    for i in 0..10 {
+        if player_pos.y - i > 1 {
+            particles.request(
+                player_pos.x,
+                player_pos.y - i,
+                rltk::RGB::named(rltk::GOLD),
+                rltk::RGB::named(rltk::BLACK),
+                rltk::to_cp437('░'),
+                400.0,
+            );
+        }
+    }
+}
+
 pub fn delete_the_dead(world: &mut World, resources: &mut Resources) {
     let mut dead = Vec::new();
+    let mut player_died = false;
     for (victim, stats) in Read::<Pools>::query().iter_entities(world) {
         if stats.hit_points.current < 1 {
             if let Some(_player) = world.get_tag::<Player>(victim) {
+                // The dead player stays put, so only the first time counts
+                player_died = *resources.get::<RunState>().unwrap() != RunState::GameOver;
                 resources.insert(RunState::GameOver);
             } else {
                 dead.push(victim);
             }
         }
     }
+    if player_died {
+        morgue::write_morgue_file(world, resources);
+        hall_of_fame::record(world, resources);
+    }
 
-    // Drop everything held by dead people
-    let mut to_drop = Vec::new();
-    let mut to_spawn = Vec::new();
+    // Leave a corpse holding everything the dead carried
+    let mut corpses = Vec::new();
     for victim in dead.iter() {
         if let Some(pos) = world.get_component::<Position>(*victim) {
-            // Drop their stuff
+            let mut contents = Vec::new();
             for (entity, equipped) in Read::<Equipped>::query().iter_entities(world) {
                 if equipped.owner == *victim {
-                    to_drop.push((entity, (*pos).clone()));
+                    contents.push(entity);
                 }
             }
             for (entity, backpack) in Read::<InBackpack>::query().iter_entities(world) {
                 if backpack.owner == *victim {
-                    to_drop.push((entity, (*pos).clone()));
+                    contents.push(entity);
                 }
             }
 
-            if let Some(loot) = world.get_component::<LootTable>(*victim) {
+            let mut loot = None;
+            if let Some(loot_table) = world.get_component::<LootTable>(*victim) {
                 let mut rng = resources.get_mut::<rltk::RandomNumberGenerator>().unwrap();
-                if let Some(drop) = get_item_drop(&PREFABS.lock().unwrap(), &mut rng, &loot.table) {
-                    to_spawn.push((drop, (*pos).clone()));
-                }
+                loot = get_item_drop(&PREFABS.lock().unwrap(), &mut rng, &loot_table.table);
+            }
+
+            if !contents.is_empty() || loot.is_some() {
+                corpses.push((*victim, *pos, contents, loot));
             }
         }
     }
-    for (entity, pos) in to_drop.drain(..) {
-        world
-            .remove_components::<(Equipped, InBackpack)>(entity)
-            .expect("Dropping item failed");
-        world
-            .add_component(entity, pos)
-            .expect("Positioning dropped item failed");
-    }
 
-    for (tag, pos) in to_spawn.drain(..) {
-        spawn_named_item(
-            &PREFABS.lock().unwrap(),
+    for (victim, pos, contents, loot) in corpses.drain(..) {
+        let prefabs = PREFABS.lock().unwrap();
+        if let Some(corpse) = spawn_named_prop(
+            &prefabs,
             world,
-            &tag,
+            "Corpse",
             SpawnType::AtPosition { x: pos.x, y: pos.y },
-        );
+        ) {
+            world.add_tag(corpse, Corpse {}).expect("Cannot add tag");
+            let victim_name = world
+                .get_component::<Name>(victim)
+                .map(|name| name.name.clone());
+            if let Some(victim_name) = victim_name {
+                if let Some(mut name) = world.get_component_mut::<Name>(corpse) {
+                    name.name = format!("{} corpse", victim_name);
+                }
+            }
+
+            for entity in contents.iter() {
+                world
+                    .remove_components::<(Equipped, InBackpack)>(*entity)
+                    .expect("Dropping item failed");
+                world
+                    .add_component(*entity, InContainer { owner: corpse })
+                    .expect("Storing dropped item failed");
+            }
+
+            if let Some(tag) = loot {
+                spawn_named_item(&prefabs, world, &tag, SpawnType::Contained { by: corpse });
+            }
+        }
+    }
+
+    // Seeing a friend fall is bad for morale
+    let fallen: Vec<(Point, String)> = dead
+        .iter()
+        .filter_map(|victim| {
+            match (
+                world.get_component::<Position>(*victim),
+                world.get_component::<Faction>(*victim),
+            ) {
+                (Some(pos), Some(faction)) => {
+                    Some((Point::new(pos.x, pos.y), faction.name.clone()))
+                }
+                _ => None,
+            }
+        })
+        .collect();
+    if !fallen.is_empty() {
+        let query = <(Read<Viewshed>, Read<Faction>, Write<Morale>)>::query();
+        for (viewshed, faction, mut morale) in query.iter_mut(world) {
+            for (pos, fallen_faction) in fallen.iter() {
+                if *fallen_faction == faction.name && viewshed.visible_tiles.contains(pos) {
+                    morale.current -= ALLY_DEATH_MORALE;
+                }
+            }
+        }
+    }
+
+    // A pack without its leader scatters
+    let broken: Vec<i32> = dead
+        .iter()
+        .filter_map(|victim| world.get_component::<Pack>(*victim).map(|pack| *pack))
+        .filter(|pack| pack.leader)
+        .map(|pack| pack.id)
+        .collect();
+    if !broken.is_empty() {
+        let query = <(Read<Pack>, Write<Morale>)>::query();
+        for (pack, mut morale) in query.iter_mut(world) {
+            if broken.contains(&pack.id) {
+                morale.current = 0;
+            }
+        }
     }
 
     let mut log = resources.get_mut::<GameLog>().unwrap();
@@ -0,0 +1,152 @@
+use super::{
+    attribute_score,
+    gamelog::GameLog,
+    gui::VendorMode,
+    quest_system::{accept_quest, turn_in_quest},
+    Attributes, InBackpack, Modifiers, Name, QuestLog, QuestStatus, Reputation, RunState,
+};
+use crate::prefabs::{
+    dialogue_node, get_dialogue, parse_reaction, reaction_towards, spawn_named_item,
+    DialogueChoice, DialogueCondition, DialogueEffect, PrefabMaster, SpawnType, PREFABS,
+};
+use legion::prelude::*;
+
+/// Whether the player meets every condition placed on a choice
+pub fn choice_available(
+    world: &World,
+    player: Entity,
+    choice: &DialogueChoice,
+    pm: &PrefabMaster,
+) -> bool {
+    choice
+        .conditions
+        .iter()
+        .flatten()
+        .all(|condition| match condition {
+            DialogueCondition::Attribute {
+                attribute,
+                at_least,
+            } => {
+                let attributes = match world.get_component::<Attributes>(player) {
+                    Some(attributes) => *attributes,
+                    None => return false,
+                };
+                match attribute_score(&attributes, attribute) {
+                    Some(score) => score >= *at_least,
+                    None => false,
+                }
+            }
+            DialogueCondition::HasItem(item) => {
+                let query = <(Read<InBackpack>, Read<Name>)>::query();
+                query
+                    .iter(world)
+                    .any(|(backpack, name)| backpack.owner == player && name.name == *item)
+            }
+            DialogueCondition::Quest { quest, status } => {
+                let current = world
+                    .get_component::<QuestLog>(player)
+                    .and_then(|quest_log| {
+                        quest_log
+                            .quests
+                            .iter()
+                            .find(|progress| progress.name == *quest)
+                            .map(|progress| progress.status)
+                    });
+                match (status.to_lowercase().as_str(), current) {
+                    ("none", None) => true,
+                    ("active", Some(QuestStatus::Active)) => true,
+                    ("complete", Some(QuestStatus::Complete)) => true,
+                    ("turned in", Some(QuestStatus::TurnedIn)) => true,
+                    _ => false,
+                }
+            }
+            DialogueCondition::Reaction { faction, reaction } => {
+                let reputation = world.get_component::<Reputation>(player);
+                let modifiers = world.get_component::<Modifiers>(player);
+                reaction_towards(
+                    faction,
+                    "Player",
+                    reputation.as_deref(),
+                    modifiers.as_deref(),
+                    pm,
+                ) == parse_reaction(reaction)
+            }
+        })
+}
+
+/// Carries out a choice, returning where the conversation goes next
+pub fn choose(
+    world: &mut World,
+    resources: &Resources,
+    speaker: Entity,
+    node: usize,
+    choice: usize,
+) -> RunState {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let speaker_name = match world.get_component::<Name>(speaker) {
+        Some(name) => name.name.clone(),
+        None => return RunState::PlayerTurn,
+    };
+
+    // Quests take the prefab lock themselves, so copy out what we need first
+    let (effects, next) = {
+        let pm = PREFABS.lock().unwrap();
+        let dialogue = match get_dialogue(&pm, &speaker_name) {
+            Some(dialogue) => dialogue,
+            None => return RunState::PlayerTurn,
+        };
+        let choice = match dialogue
+            .nodes
+            .get(node)
+            .and_then(|node| node.choices.get(choice))
+        {
+            Some(choice) => choice,
+            None => return RunState::PlayerTurn,
+        };
+        let next = match &choice.next {
+            Some(next) => dialogue_node(dialogue, next),
+            None => None,
+        };
+        (choice.effects.clone().unwrap_or_default(), next)
+    };
+
+    let mut trading = false;
+    for effect in effects.iter() {
+        match effect {
+            DialogueEffect::GiveItem(item) => {
+                spawn_named_item(
+                    &PREFABS.lock().unwrap(),
+                    world,
+                    item,
+                    SpawnType::Carried { by: player_entity },
+                );
+                resources
+                    .get_mut::<GameLog>()
+                    .unwrap()
+                    .entries
+                    .push(format!("The {} gives you the {}.", speaker_name, item));
+            }
+            DialogueEffect::StartQuest(quest) => accept_quest(world, resources, quest),
+            DialogueEffect::TurnInQuest(quest) => turn_in_quest(world, resources, quest),
+            DialogueEffect::SetReaction { faction, reaction } => {
+                if let Some(mut reputation) = world.get_component_mut::<Reputation>(player_entity) {
+                    reputation
+                        .reactions
+                        .insert(faction.clone(), parse_reaction(reaction));
+                }
+            }
+            DialogueEffect::Trade => trading = true,
+        }
+    }
+
+    if trading {
+        return RunState::ShowVendor {
+            vendor: speaker,
+            mode: VendorMode::Buy,
+        };
+    }
+    match next {
+        Some(node) => RunState::ShowDialogue { speaker, node },
+        None => RunState::PlayerTurn,
+    }
+}
@@ -0,0 +1,562 @@
+use super::{
+    components::*,
+    gamelog::GameLog,
+    gas_system::spawn_gas_cloud,
+    noise_system::{NoiseBuilder, TRAP_NOISE},
+    particle_system::ParticleBuilder,
+    tile_walkable, Map, RunState,
+};
+use crate::prefabs::{get_spell, PREFABS};
+use legion::prelude::*;
+use rltk::{Point, RandomNumberGenerator};
+use std::collections::VecDeque;
+
+/// What an effect does once it lands
+#[derive(Clone, Debug, PartialEq)]
+pub enum EffectType {
+    /// An item (or a cast spell) being used, which expands into the effects it carries
+    ItemUse {
+        item: Entity,
+    },
+    /// A trap going off, which expands into the effects it carries
+    TriggerFire {
+        trigger: Entity,
+    },
+    Damage {
+        amount: i32,
+    },
+    Healing {
+        amount: i32,
+    },
+    Confusion {
+        turns: i32,
+    },
+    WellFed,
+    MagicMapping,
+    LearnSpell {
+        spell: String,
+    },
+    Gas {
+        gas: GasType,
+        potency: i32,
+    },
+    Teleport,
+    Trapdoor,
+}
+
+/// Who or where an effect lands
+#[derive(Clone, Debug, PartialEq)]
+pub enum Targets {
+    Caster,
+    Single { target: Entity },
+    Tile { tile: Point },
+    Area { tiles: Vec<Point> },
+}
+
+pub struct EffectSpawner {
+    pub creator: Option<Entity>,
+    pub effect_type: EffectType,
+    pub targets: Targets,
+}
+
+pub struct EffectQueue {
+    effects: VecDeque<EffectSpawner>,
+}
+
+impl EffectQueue {
+    pub fn new() -> Self {
+        EffectQueue {
+            effects: VecDeque::new(),
+        }
+    }
+
+    pub fn add_effect(
+        &mut self,
+        creator: Option<Entity>,
+        effect_type: EffectType,
+        targets: Targets,
+    ) {
+        self.effects.push_back(EffectSpawner {
+            creator,
+            effect_type,
+            targets,
+        });
+    }
+}
+
+/// Applies everything queued up so far, in the order it was queued
+pub fn run_effects_queue() -> Box<dyn Fn(&mut World, &mut Resources) -> ()> {
+    Box::new(|world: &mut World, resources: &mut Resources| loop {
+        let effect = resources
+            .get_mut::<EffectQueue>()
+            .unwrap()
+            .effects
+            .pop_front();
+        match effect {
+            Some(effect) => apply_effect(world, resources, &effect),
+            None => break,
+        }
+    })
+}
+
+fn apply_effect(world: &mut World, resources: &Resources, effect: &EffectSpawner) {
+    match &effect.effect_type {
+        EffectType::ItemUse { item } => use_item(world, resources, effect, *item),
+        EffectType::TriggerFire { trigger } => fire_trigger(world, resources, effect, *trigger),
+        effect_type => {
+            affect_targets(world, resources, effect, effect_type, "");
+        }
+    }
+}
+
+/// Applies one effect to everyone it targets, returning true if anyone was affected
+fn affect_targets(
+    world: &mut World,
+    resources: &Resources,
+    effect: &EffectSpawner,
+    effect_type: &EffectType,
+    source: &str,
+) -> bool {
+    // Gas fills the space rather than anyone standing in it
+    if let EffectType::Gas { gas, potency } = effect_type {
+        for tile in target_tiles(world, effect).iter() {
+            spawn_gas_cloud(world, tile.x, tile.y, *gas, *potency);
+        }
+        return true;
+    }
+
+    let targets = target_entities(world, resources, effect);
+    if targets.is_empty() {
+        // Wasted on thin air, but used all the same
+        return true;
+    }
+    let mut affected = false;
+    for target in targets.iter() {
+        affected |= affect_entity(
+            world,
+            resources,
+            effect.creator,
+            source,
+            effect_type,
+            *target,
+        );
+    }
+    affected
+}
+
+fn target_tiles(world: &World, effect: &EffectSpawner) -> Vec<Point> {
+    let entity_tile = |entity: Entity| {
+        world
+            .get_component::<Position>(entity)
+            .map(|pos| Point::new(pos.x, pos.y))
+    };
+    match &effect.targets {
+        Targets::Caster => effect.creator.and_then(entity_tile).into_iter().collect(),
+        Targets::Single { target } => entity_tile(*target).into_iter().collect(),
+        Targets::Tile { tile } => vec![*tile],
+        Targets::Area { tiles } => tiles.clone(),
+    }
+}
+
+fn target_entities(world: &World, resources: &Resources, effect: &EffectSpawner) -> Vec<Entity> {
+    match &effect.targets {
+        Targets::Caster => effect.creator.into_iter().collect(),
+        Targets::Single { target } => vec![*target],
+        Targets::Tile { .. } | Targets::Area { .. } => {
+            let map = resources.get::<Map>().unwrap();
+            target_tiles(world, effect)
+                .iter()
+                .flat_map(|tile| map.tile_content[map.xy_idx(tile.x, tile.y)].iter())
+                .copied()
+                .collect()
+        }
+    }
+}
+
+fn entity_name(world: &World, entity: Entity) -> String {
+    match world.get_component::<Name>(entity) {
+        Some(name) => name.name.clone(),
+        None => "-Unnamed-".to_string(),
+    }
+}
+
+fn particle_at(world: &World, resources: &Resources, target: Entity, fg: rltk::RGB, glyph: char) {
+    if let Some(pos) = world.get_component::<Position>(target) {
+        resources.get_mut::<ParticleBuilder>().unwrap().request(
+            pos.x,
+            pos.y,
+            fg,
+            rltk::RGB::named(rltk::BLACK),
+            rltk::to_cp437(glyph),
+            200.0,
+        );
+    }
+}
+
+/// The effects an item or trap carries, in the order they are applied
+fn effects_of(world: &World, source: Entity) -> Vec<EffectType> {
+    let mut effects = Vec::new();
+    if let Some(spell) = world.get_component::<TeachesSpell>(source) {
+        effects.push(EffectType::LearnSpell {
+            spell: spell.spell.clone(),
+        });
+    }
+    if world.get_tag::<ProvidesFood>(source).is_some() {
+        effects.push(EffectType::WellFed);
+    }
+    if world.get_tag::<MagicMapper>(source).is_some() {
+        effects.push(EffectType::MagicMapping);
+    }
+    if let Some(healing) = world.get_component::<ProvidesHealing>(source) {
+        effects.push(EffectType::Healing {
+            amount: healing.heal_amount,
+        });
+    }
+    if let Some(damage) = world.get_component::<InflictsDamage>(source) {
+        effects.push(EffectType::Damage {
+            amount: damage.damage,
+        });
+    }
+    if let Some(confusion) = world.get_component::<Confusion>(source) {
+        effects.push(EffectType::Confusion {
+            turns: confusion.turns,
+        });
+    }
+    if let Some(gas) = world.get_component::<SpawnsGas>(source) {
+        effects.push(EffectType::Gas {
+            gas: gas.gas,
+            potency: gas.potency,
+        });
+    }
+    if world.get_tag::<TeleportsEntity>(source).is_some() {
+        effects.push(EffectType::Teleport);
+    }
+    if world.get_tag::<Trapdoor>(source).is_some() {
+        effects.push(EffectType::Trapdoor);
+    }
+    effects
+}
+
+fn use_item(world: &mut World, resources: &Resources, effect: &EffectSpawner, item: Entity) {
+    let item_name = entity_name(world, item);
+    let mut used_item = false;
+    for effect_type in effects_of(world, item).iter() {
+        used_item |= affect_targets(world, resources, effect, effect_type, &item_name);
+    }
+
+    if used_item
+        && world.get_tag::<Item>(item).is_some()
+        && effect.creator == Some(*resources.get::<Entity>().unwrap())
+    {
+        if let Some(mut stats) = world.get_component_mut::<RunStats>(effect.creator.unwrap()) {
+            *stats.items_used.entry(item_name.clone()).or_insert(0) += 1;
+        }
+    }
+
+    // If it's a consumable, we delete it on use
+    if used_item && world.get_tag::<Consumable>(item).is_some() {
+        // A careful reader can sometimes get through a scroll without using it up
+        let keep_chance = match effect
+            .creator
+            .and_then(|creator| world.get_component::<Modifiers>(creator))
+        {
+            Some(modifiers) => modifiers.keep_scroll_chance,
+            None => 0,
+        };
+        if world.get_tag::<Scroll>(item).is_some()
+            && resources
+                .get_mut::<RandomNumberGenerator>()
+                .unwrap()
+                .roll_dice(1, 100)
+                <= keep_chance
+        {
+            if effect.creator == Some(*resources.get::<Entity>().unwrap()) {
+                resources
+                    .get_mut::<GameLog>()
+                    .unwrap()
+                    .entries
+                    .push(format!("The {} is still legible afterwards!", item_name));
+            }
+        } else {
+            world.delete(item);
+        }
+    }
+}
+
+fn fire_trigger(world: &mut World, resources: &Resources, effect: &EffectSpawner, trigger: Entity) {
+    let trigger_name = entity_name(world, trigger);
+    // The trap is no longer hidden
+    if world.get_tag::<Hidden>(trigger).is_some() {
+        world
+            .remove_tag::<Hidden>(trigger)
+            .expect("Cannot remove Hidden tag");
+    }
+    resources
+        .get_mut::<GameLog>()
+        .unwrap()
+        .entries
+        .push(format!("{} triggers!", trigger_name));
+
+    // Alarms rouse the whole neighbourhood, other traps just make a clatter
+    let alarm_radius = world
+        .get_component::<Alarm>(trigger)
+        .map(|alarm| alarm.radius);
+    if let Some(tile) = target_tiles(world, effect).first() {
+        match alarm_radius {
+            Some(radius) => {
+                resources
+                    .get_mut::<GameLog>()
+                    .unwrap()
+                    .entries
+                    .push("A loud alarm rings out!".to_string());
+                sound_alarm(world, resources, *tile, radius);
+            }
+            None => resources
+                .get_mut::<NoiseBuilder>()
+                .unwrap()
+                .request(tile.x, tile.y, TRAP_NOISE),
+        }
+    }
+
+    for effect_type in effects_of(world, trigger).iter() {
+        affect_targets(world, resources, effect, effect_type, &trigger_name);
+    }
+
+    // If it is single activation, it needs to be removed
+    if world.get_tag::<SingleActivation>(trigger).is_some() {
+        world.delete(trigger);
+    }
+}
+
+/// Wakes every mob within earshot of an alarm and sends it after the player, wherever the
+/// player is and whoever set the alarm off
+fn sound_alarm(world: &mut World, resources: &Resources, origin: Point, radius: i32) {
+    let player_pos = *resources.get::<Point>().unwrap();
+    let query = <(Write<MonsterMemory>, Read<Position>)>::query();
+    for (mut memory, pos) in query.iter_mut(world) {
+        let distance = rltk::DistanceAlg::Pythagoras.distance2d(origin, Point::new(pos.x, pos.y));
+        if distance <= radius as f32 && memory.state != AiState::Fleeing {
+            memory.hunt(player_pos);
+        }
+    }
+}
+
+/// Applies a single effect to a single entity, returning true if it took
+fn affect_entity(
+    world: &mut World,
+    resources: &Resources,
+    creator: Option<Entity>,
+    source: &str,
+    effect_type: &EffectType,
+    target: Entity,
+) -> bool {
+    // The target may have been deleted since it was picked, e.g. by an earlier effect
+    if !world.is_alive(target) {
+        return false;
+    }
+
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let by_player = creator == Some(player_entity);
+    let target_name = entity_name(world, target);
+
+    match effect_type {
+        EffectType::Damage { amount } => {
+            SufferDamage::inflict(world, target, *amount, by_player);
+            if target == player_entity {
+                let cause = if by_player {
+                    format!("their own {}", source)
+                } else {
+                    source.to_string()
+                };
+                RunStats::note_hurt(world, target, cause);
+            }
+            particle_at(world, resources, target, rltk::RGB::named(rltk::RED), '‼');
+            if by_player {
+                resources
+                    .get_mut::<GameLog>()
+                    .unwrap()
+                    .entries
+                    .push(format!(
+                        "You use {} on {}, inflicting {} hp.",
+                        source, target_name, amount
+                    ));
+            }
+        }
+        EffectType::Healing { amount } => {
+            if let Some(mut stats) = world.get_component_mut::<Pools>(target) {
+                stats.hit_points.current =
+                    i32::min(stats.hit_points.max, stats.hit_points.current + amount);
+            }
+            particle_at(world, resources, target, rltk::RGB::named(rltk::GREEN), '♥');
+            if by_player {
+                resources
+                    .get_mut::<GameLog>()
+                    .unwrap()
+                    .entries
+                    .push(format!("You use the {}, healing {} hp.", source, amount));
+            }
+        }
+        EffectType::Confusion { turns } => {
+            world
+                .add_component(target, Confusion { turns: *turns })
+                .expect("Cannot add component");
+            particle_at(
+                world,
+                resources,
+                target,
+                rltk::RGB::named(rltk::MAGENTA),
+                '?',
+            );
+            if by_player {
+                resources
+                    .get_mut::<GameLog>()
+                    .unwrap()
+                    .entries
+                    .push(format!(
+                        "You use {} on {}, confusing them.",
+                        source, target_name
+                    ));
+            }
+        }
+        EffectType::WellFed => {
+            match world.get_component_mut::<HungerClock>(target) {
+                Some(mut hc) => {
+                    hc.state = HungerState::WellFed;
+                    hc.duration = 20;
+                }
+                None => return false,
+            }
+            if target == player_entity {
+                resources
+                    .get_mut::<GameLog>()
+                    .unwrap()
+                    .entries
+                    .push(format!("You eat the {}.", source));
+            }
+        }
+        EffectType::MagicMapping => {
+            if target != player_entity {
+                return false;
+            }
+            resources
+                .get_mut::<GameLog>()
+                .unwrap()
+                .entries
+                .push("The map is revealed to you!".to_string());
+            *resources.get_mut::<RunState>().unwrap() = RunState::MagicMapReveal { row: 0 };
+        }
+        EffectType::LearnSpell { spell } => {
+            // Spellbooks teach whoever reads them, unless they know it already
+            let already_known = match world.get_component::<KnownSpells>(target) {
+                Some(known) => known.spells.iter().any(|s| s.name == *spell),
+                None => true,
+            };
+            let learned = get_spell(&PREFABS.lock().unwrap(), spell).map(|spell| KnownSpell {
+                name: spell.name.clone(),
+                mana_cost: spell.mana_cost,
+            });
+            let mut log = resources.get_mut::<GameLog>().unwrap();
+            match learned {
+                Some(learned) if !already_known => {
+                    if target == player_entity {
+                        log.entries
+                            .push(format!("You learn to cast {}!", learned.name));
+                    }
+                    if let Some(mut known) = world.get_component_mut::<KnownSpells>(target) {
+                        known.spells.push(learned);
+                    }
+                }
+                _ => {
+                    if target == player_entity {
+                        log.entries.push(format!("You already know {}.", spell));
+                    }
+                    return false;
+                }
+            }
+        }
+        EffectType::Teleport => teleport(world, resources, target),
+        EffectType::Trapdoor => {
+            if target == player_entity {
+                resources
+                    .get_mut::<GameLog>()
+                    .unwrap()
+                    .entries
+                    .push("You fall through a trapdoor!".to_string());
+                *resources.get_mut::<RunState>().unwrap() = RunState::NextLevel;
+            } else {
+                resources
+                    .get_mut::<GameLog>()
+                    .unwrap()
+                    .entries
+                    .push(format!("{} falls through a trapdoor!", target_name));
+
+                // Whatever it carried falls with it
+                let mut carried: Vec<Entity> = Read::<Equipped>::query()
+                    .iter_entities(world)
+                    .filter(|(_item, equipped)| equipped.owner == target)
+                    .map(|(item, _equipped)| item)
+                    .collect();
+                carried.extend(
+                    Read::<InBackpack>::query()
+                        .iter_entities(world)
+                        .filter(|(_item, backpack)| backpack.owner == target)
+                        .map(|(item, _backpack)| item),
+                );
+                for item in carried.iter() {
+                    world.delete(*item);
+                }
+                world.delete(target);
+            }
+        }
+        EffectType::ItemUse { .. } | EffectType::TriggerFire { .. } | EffectType::Gas { .. } => {
+            return false;
+        }
+    }
+    true
+}
+
+/// Moves the target to a random open tile
+fn teleport(world: &mut World, resources: &Resources, target: Entity) {
+    let destination = {
+        let map = resources.get::<Map>().unwrap();
+        let possible_targets: Vec<usize> = map
+            .tiles
+            .iter()
+            .enumerate()
+            .filter(|(i, tile)| tile_walkable(**tile) && !map.blocked[*i])
+            .map(|(i, _tile)| i)
+            .collect();
+        if possible_targets.is_empty() {
+            return;
+        }
+        let roll = resources
+            .get_mut::<RandomNumberGenerator>()
+            .unwrap()
+            .roll_dice(1, possible_targets.len() as i32);
+        let idx = possible_targets[(roll - 1) as usize];
+        Point::new(idx as i32 % map.width, idx as i32 / map.width)
+    };
+
+    if target == *resources.get::<Entity>().unwrap() {
+        resources
+            .get_mut::<GameLog>()
+            .unwrap()
+            .entries
+            .push("You are teleported away!".to_string());
+        *resources.get_mut::<Point>().unwrap() = destination;
+    } else {
+        let name = entity_name(world, target);
+        resources
+            .get_mut::<GameLog>()
+            .unwrap()
+            .entries
+            .push(format!("{} vanishes!", name));
+    }
+    if let Some(mut pos) = world.get_component_mut::<Position>(target) {
+        pos.x = destination.x;
+        pos.y = destination.y;
+    }
+    if let Some(mut viewshed) = world.get_component_mut::<Viewshed>(target) {
+        viewshed.dirty = true;
+    }
+}
@@ -0,0 +1,28 @@
+use super::{tile_walkable, BlocksTile, FlowField, Map, Position, Viewshed};
+use legion::prelude::*;
+use rltk::Point;
+
+pub fn build() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("flow_field")
+        .write_resource::<FlowField>()
+        .write_resource::<Map>()
+        .read_resource::<Point>()
+        .read_component::<Viewshed>()
+        .with_query(Read::<Position>::query().filter(tag::<BlocksTile>()))
+        .build(|_, world, (flow_field, map, player_pos), query| {
+            if map.tiles.is_empty() {
+                return;
+            }
+
+            // Walls and anything that doesn't move on its own, such as closed doors
+            let mut obstacles: Vec<bool> =
+                map.tiles.iter().map(|tile| !tile_walkable(*tile)).collect();
+            for (entity, pos) in query.iter_entities(world) {
+                if world.get_component::<Viewshed>(entity).is_none() {
+                    obstacles[map.xy_idx(pos.x, pos.y)] = true;
+                }
+            }
+
+            flow_field.refresh(&mut **map, **player_pos, obstacles);
+        })
+}
@@ -1,4 +1,4 @@
-use super::components::{Skill, Skills};
+use super::components::{AbilityKind, Attributes, Skill, Skills, WeaponAttribute};
 
 pub fn attr_bonus(value: i32) -> i32 {
     (value - 10) / 2 // See: https://roll20.net/compendium/dnd5e/Ability%20Scores#content
@@ -28,6 +28,10 @@ pub fn mana_at_level(intelligence: i32, level: i32) -> i32 {
     mana_per_level(intelligence) * level
 }
 
+pub fn perception_bonus(attributes: &Attributes, skills: &Skills) -> i32 {
+    attributes.intelligence.bonus + skill_bonus(Skill::Perception, skills)
+}
+
 pub fn skill_bonus(skill: Skill, skills: &Skills) -> i32 {
     if skills.skills.contains_key(&skill) {
         skills.skills[&skill]
@@ -35,3 +39,51 @@ pub fn skill_bonus(skill: Skill, skills: &Skills) -> i32 {
         -4
     }
 }
+
+/// What an attacker's attributes and training add to a d20 attack roll
+pub fn attack_bonus(
+    attributes: &Attributes,
+    attribute: WeaponAttribute,
+    skill: Skill,
+    skills: &Skills,
+) -> i32 {
+    let attribute_bonus = match attribute {
+        WeaponAttribute::Might => attributes.might.bonus,
+        WeaponAttribute::Quickness => attributes.quickness.bonus,
+    };
+    attribute_bonus + skill_bonus(skill, skills)
+}
+
+/// What a mob's ranged ability adds to its attack roll: a steady aim for missiles, and
+/// the same intelligence and training that power the player's spells for spells
+pub fn ability_attack_bonus(kind: AbilityKind, attributes: &Attributes, skills: &Skills) -> i32 {
+    match kind {
+        AbilityKind::Missile => attributes.quickness.bonus,
+        AbilityKind::Spell => attributes.intelligence.bonus + skill_bonus(Skill::Magic, skills),
+    }
+}
+
+/// What an attack roll has to beat: natural armor (or 10), dodging, training and worn armor
+pub fn armor_class(
+    natural_armor: Option<i32>,
+    attributes: &Attributes,
+    skills: &Skills,
+    worn_armor: f32,
+) -> i32 {
+    natural_armor.unwrap_or(10)
+        + attributes.quickness.bonus
+        + skill_bonus(Skill::Defense, skills)
+        + worn_armor as i32
+}
+
+/// An attribute's current score looked up by name, as written in the prefabs
+pub fn attribute_score(attributes: &Attributes, name: &str) -> Option<i32> {
+    let attribute = match name.to_lowercase().as_str() {
+        "might" => attributes.might,
+        "fitness" => attributes.fitness,
+        "quickness" => attributes.quickness,
+        "intelligence" => attributes.intelligence,
+        _ => return None,
+    };
+    Some(attribute.base + attribute.modifiers)
+}
@@ -0,0 +1,185 @@
+use super::{
+    gamelog::GameLog, tile_walkable, Confusion, Gas, GasType, Map, Name, Poisoned, Pools, Position,
+    Renderable, RunState, RunStats, SufferDamage,
+};
+use legion::prelude::*;
+use std::collections::HashSet;
+
+const GAS_SPREAD: i32 = 3;
+const GAS_LIFETIME: i32 = 6;
+
+fn spawn_gas(world: &mut World, x: i32, y: i32, gas: GasType, potency: i32, spread: i32) {
+    let (name, color) = match gas {
+        GasType::Confusion => ("Confusion Gas", rltk::MAGENTA),
+        GasType::Poison => ("Poison Gas", rltk::GREEN),
+    };
+    world.insert(
+        (),
+        vec![(
+            Position { x, y },
+            Renderable {
+                glyph: rltk::to_cp437('░'),
+                fg: rltk::RGB::named(color),
+                bg: rltk::RGB::named(rltk::BLACK),
+                render_order: 3,
+            },
+            Name {
+                name: name.to_string(),
+            },
+            Gas {
+                gas,
+                potency,
+                spread,
+                lifetime: GAS_LIFETIME,
+            },
+        )],
+    );
+}
+
+pub fn spawn_gas_cloud(world: &mut World, x: i32, y: i32, gas: GasType, potency: i32) {
+    spawn_gas(world, x, y, gas, potency, GAS_SPREAD);
+}
+
+pub fn build() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("gas")
+        .read_resource::<Map>()
+        .read_resource::<RunState>()
+        .read_resource::<Entity>() // The Player
+        .write_resource::<GameLog>()
+        .with_query(<(Read<Gas>, Read<Position>)>::query())
+        .read_component::<Pools>()
+        .read_component::<Confusion>()
+        .read_component::<Poisoned>()
+        .build(
+            |command_buffer, world, (map, runstate, player_entity, log), query| {
+                // Gas drifts and fades once per round
+                if **runstate != RunState::MonsterTurn {
+                    return;
+                }
+
+                let clouds: Vec<(Entity, Gas, Position)> = query
+                    .iter_entities(world)
+                    .map(|(entity, (gas, pos))| (entity, *gas, *pos))
+                    .collect();
+                let mut gas_tiles: HashSet<usize> = clouds
+                    .iter()
+                    .map(|(_entity, _gas, pos)| map.xy_idx(pos.x, pos.y))
+                    .collect();
+
+                let mut new_clouds = Vec::new();
+                for (entity, mut gas, pos) in clouds.into_iter() {
+                    // Everyone breathing it in is affected
+                    let idx = map.xy_idx(pos.x, pos.y);
+                    for victim in map.tile_content[idx].iter() {
+                        let victim = *victim;
+                        if world.get_component::<Pools>(victim).is_none() {
+                            continue;
+                        }
+                        let is_player = victim == **player_entity;
+                        match gas.gas {
+                            GasType::Confusion => {
+                                if is_player && world.get_component::<Confusion>(victim).is_none() {
+                                    log.entries.push("You feel confused!".to_string());
+                                }
+                                command_buffer
+                                    .add_component(victim, Confusion { turns: gas.potency });
+                            }
+                            GasType::Poison => {
+                                if is_player && world.get_component::<Poisoned>(victim).is_none() {
+                                    log.entries.push("You are poisoned!".to_string());
+                                }
+                                command_buffer.add_component(
+                                    victim,
+                                    Poisoned {
+                                        turns: gas.potency,
+                                        damage: 1,
+                                    },
+                                );
+                            }
+                        }
+                    }
+
+                    // Spread into the neighbouring tiles
+                    if gas.spread > 0 {
+                        let neighbours = [
+                            (pos.x - 1, pos.y),
+                            (pos.x + 1, pos.y),
+                            (pos.x, pos.y - 1),
+                            (pos.x, pos.y + 1),
+                        ];
+                        for (x, y) in neighbours.iter() {
+                            if *x < 0 || *x > map.width - 1 || *y < 0 || *y > map.height - 1 {
+                                continue;
+                            }
+                            let n_idx = map.xy_idx(*x, *y);
+                            if tile_walkable(map.tiles[n_idx]) && !gas_tiles.contains(&n_idx) {
+                                gas_tiles.insert(n_idx);
+                                new_clouds.push((*x, *y, gas.gas, gas.potency, gas.spread - 1));
+                            }
+                        }
+                        gas.spread = 0;
+                    }
+
+                    gas.lifetime -= 1;
+                    if gas.lifetime < 1 {
+                        command_buffer.delete(entity);
+                    } else {
+                        command_buffer.add_component(entity, gas);
+                    }
+                }
+
+                for (x, y, gas, potency, spread) in new_clouds.into_iter() {
+                    command_buffer.exec_mut(move |world| {
+                        spawn_gas(world, x, y, gas, potency, spread);
+                    });
+                }
+            },
+        )
+}
+
+pub fn poison() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("poison")
+        .read_resource::<Entity>() // The Player
+        .read_resource::<RunState>()
+        .write_resource::<GameLog>()
+        .with_query(Read::<Poisoned>::query())
+        .build(
+            |command_buffer, world, (player_entity, runstate, log), query| {
+                for (entity, poisoned) in query.iter_entities(world) {
+                    let is_player = entity == **player_entity;
+
+                    let proceed = match **runstate {
+                        RunState::PlayerTurn => is_player,
+                        RunState::MonsterTurn => !is_player,
+                        _ => false,
+                    };
+
+                    if proceed {
+                        if is_player {
+                            log.entries.push(format!(
+                                "The poison burns! You suffer {} hp damage.",
+                                poisoned.damage
+                            ));
+                            RunStats::hurt_by(command_buffer, entity, "poison".to_string());
+                        }
+                        SufferDamage::new_damage(command_buffer, entity, poisoned.damage, false);
+
+                        if poisoned.turns > 1 {
+                            command_buffer.add_component(
+                                entity,
+                                Poisoned {
+                                    turns: poisoned.turns - 1,
+                                    damage: poisoned.damage,
+                                },
+                            );
+                        } else {
+                            command_buffer.remove_component::<Poisoned>(entity);
+                            if is_player {
+                                log.entries.push("The poison wears off.".to_string());
+                            }
+                        }
+                    }
+                }
+            },
+        )
+}
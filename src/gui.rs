@@ -1,6 +1,24 @@
-use super::{camera, components::*, gamelog::GameLog, rex_assets::RexAssets, Map, RunState, State};
+use super::{
+    armor_class, attack_bonus, attr_bonus, camera,
+    clock_system::GameClock,
+    components::*,
+    dialogue,
+    gamelog::GameLog,
+    hall_of_fame,
+    level_up::{self, LevelUpChoices},
+    mana_at_level, morgue, perception_bonus, player_hp_at_level, quest_system,
+    rex_assets::RexAssets,
+    skill_bonus, skill_system,
+    spawner::{CharacterSheet, ATTRIBUTE_NAMES},
+    vendor, Map, RunState, State,
+};
+use crate::prefabs::{
+    backgrounds, get_dialogue, get_quest, get_vendor_items, perks, DialogueChoice, QuestObjective,
+    PREFABS,
+};
 use legion::prelude::*;
-use rltk::{FontCharType, Point, Rltk, VirtualKeyCode, RGB};
+use rltk::{FontCharType, Point, RandomNumberGenerator, Rltk, VirtualKeyCode, RGB};
+use std::collections::HashMap;
 
 pub fn draw_hollow_box(
     console: &mut Rltk,
@@ -56,6 +74,21 @@ pub fn draw_ui(world: &World, resources: &Resources, ctx: &mut Rltk) {
     ctx.print_color(x_pos + 1, 0, white, black, name);
     ctx.set(x_pos + 1 + name_length, 0, box_gray, black, to_cp437('├'));
 
+    // Draw the time of day
+    let clock = resources.get::<GameClock>().unwrap();
+    let time = format!(
+        "Day {}, {:02}:{:02}",
+        clock.day(),
+        clock.hour(),
+        clock.minute()
+    );
+    std::mem::drop(clock);
+    let time_length = time.len() as i32;
+    let x_pos = 48 - time_length - 1;
+    ctx.set(x_pos - 1, 45, box_gray, black, to_cp437('┤'));
+    ctx.print_color(x_pos, 45, white, black, &time);
+    ctx.set(x_pos + time_length, 45, box_gray, black, to_cp437('├'));
+
     // Draw stats
     let player = resources.get::<Entity>().unwrap();
     let stats = world.get_component::<Pools>(*player).unwrap();
@@ -129,6 +162,32 @@ pub fn draw_ui(world: &World, resources: &Resources, ctx: &mut Rltk) {
         }
     }
 
+    // Spells
+    if let Some(known) = world.get_component::<KnownSpells>(*player) {
+        y += 1;
+        let blue = RGB::named(rltk::CYAN);
+        for (i, spell) in known.spells.iter().take(9).enumerate() {
+            ctx.print_color(50, y, yellow, black, &format!("^{}", i + 1));
+            ctx.print_color(
+                53,
+                y,
+                blue,
+                black,
+                &format!("{} ({})", spell.name, spell.mana_cost),
+            );
+            y += 1;
+        }
+    }
+
+    // Purse
+    ctx.print_color(
+        50,
+        43,
+        RGB::named(rltk::GOLD),
+        black,
+        &format!("Gold: {}", stats.gold),
+    );
+
     // Status
     let hunger = world.get_component::<HungerClock>(*player).unwrap();
     match hunger.state {
@@ -353,6 +412,98 @@ pub enum ItemMenuResult {
     Selected,
 }
 
+pub fn show_spells(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<usize>) {
+    let player_entity = gs.resources.get::<Entity>().unwrap();
+    let spells = match gs.world.get_component::<KnownSpells>(*player_entity) {
+        Some(known) => known.spells.clone(),
+        None => Vec::new(),
+    };
+    let mana = gs
+        .world
+        .get_component::<Pools>(*player_entity)
+        .map_or(0, |stats| stats.mana.current);
+
+    let count = spells.len();
+
+    let mut y = (25 - (count / 2)) as i32;
+    ctx.draw_box(
+        15,
+        y - 2,
+        31,
+        (count + 3) as i32,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        18,
+        y - 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Cast which spell?",
+    );
+    ctx.print_color(
+        18,
+        y + count as i32 + 1,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "ESC to cancel",
+    );
+
+    for (j, spell) in spells.iter().enumerate() {
+        ctx.set(
+            17,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437('('),
+        );
+        ctx.set(
+            18,
+            y,
+            RGB::named(rltk::YELLOW),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437('a') + j as FontCharType,
+        );
+        ctx.set(
+            19,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437(')'),
+        );
+
+        // Spells we can't afford right now are grayed out
+        let fg = if spell.mana_cost <= mana {
+            RGB::named(rltk::CYAN)
+        } else {
+            RGB::named(rltk::GRAY)
+        };
+        ctx.print_color(
+            21,
+            y,
+            fg,
+            RGB::named(rltk::BLACK),
+            &format!("{} ({} mana)", spell.name, spell.mana_cost),
+        );
+        y += 1;
+    }
+
+    match ctx.key {
+        None => (ItemMenuResult::NoResponse, None),
+        Some(key) => match key {
+            VirtualKeyCode::Escape => (ItemMenuResult::Cancel, None),
+            _ => {
+                let selection = rltk::letter_to_option(key);
+                if selection >= 0 && selection < count as i32 {
+                    (ItemMenuResult::Selected, Some(selection as usize))
+                } else {
+                    (ItemMenuResult::NoResponse, None)
+                }
+            }
+        },
+    }
+}
+
 pub fn show_inventory(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Option<Entity>) {
     let player_entity = gs.resources.get::<Entity>().unwrap();
 
@@ -590,117 +741,417 @@ pub fn remove_item_menu(gs: &mut State, ctx: &mut Rltk) -> (ItemMenuResult, Opti
     }
 }
 
-pub fn ranged_target(
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContainerMode {
+    Take,
+    Put,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ContainerMenuResult {
+    Cancel,
+    NoResponse,
+    SwitchMode,
+    TakeAll,
+    Selected,
+}
+
+pub fn show_container(
     gs: &mut State,
     ctx: &mut Rltk,
-    range: i32,
-) -> (ItemMenuResult, Option<Point>) {
-    let (min_x, max_x, min_y, max_y) = camera::get_screen_bounds(&gs.resources, ctx);
+    container: Entity,
+    mode: ContainerMode,
+) -> (ContainerMenuResult, Option<Entity>) {
     let player_entity = gs.resources.get::<Entity>().unwrap();
-    let player_pos = gs.resources.get::<Point>().unwrap();
 
+    let container_name = match gs.world.get_component::<Name>(container) {
+        Some(name) => name.name.clone(),
+        None => "-Unknown-".to_string(),
+    };
+
+    let items: Vec<(Entity, String)> = match mode {
+        ContainerMode::Take => <(Read<InContainer>, Read<Name>)>::query()
+            .iter_entities(&gs.world)
+            .filter(|(_entity, (stored, _name))| stored.owner == container)
+            .map(|(entity, (_stored, name))| (entity, name.name.clone()))
+            .collect(),
+        ContainerMode::Put => <(Read<InBackpack>, Read<Name>)>::query()
+            .iter_entities(&gs.world)
+            .filter(|(entity, (pack, _name))| pack.owner == *player_entity && *entity != container)
+            .filter(|(entity, _)| gs.world.get_component::<Container>(*entity).is_none())
+            .map(|(entity, (_pack, name))| (entity, name.name.clone()))
+            .collect(),
+    };
+
+    let count = items.len();
+    let title = match mode {
+        ContainerMode::Take => format!("Take from the {}", container_name),
+        ContainerMode::Put => format!("Put into the {}", container_name),
+    };
+
+    let mut y = (25 - (count / 2)) as i32;
+    ctx.draw_box(
+        10,
+        y - 2,
+        41,
+        (count + 4) as i32,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
     ctx.print_color(
-        5,
-        0,
+        13,
+        y - 2,
         RGB::named(rltk::YELLOW),
         RGB::named(rltk::BLACK),
-        "Select Target:",
+        &title,
+    );
+    ctx.print_color(
+        13,
+        y + count as i32 + 1,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        match mode {
+            ContainerMode::Take => "TAB to put, ENTER to take all",
+            ContainerMode::Put => "TAB to take",
+        },
+    );
+    ctx.print_color(
+        13,
+        y + count as i32 + 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "ESC to cancel",
     );
 
-    // Highlight available target cells
-    let mut available_cells = Vec::new();
-    let visible = gs.world.get_component::<Viewshed>(*player_entity);
-    if let Some(visible) = visible {
-        // We have a viewshed
-        for idx in visible.visible_tiles.iter() {
-            let distance = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, *idx);
-            if distance <= range as f32 {
-                let screen_x = idx.x - min_x;
-                let screen_y = idx.y - min_y;
-                if screen_x > 1
-                    && screen_x < (max_x - min_x) - 1
-                    && screen_y > 1
-                    && screen_y < (max_y - min_y) - 1
-                {
-                    ctx.set_bg(screen_x, screen_y, RGB::named(rltk::BLUE));
-                    available_cells.push(*idx);
-                }
-            }
-        }
-    } else {
-        return (ItemMenuResult::Cancel, None);
+    if items.is_empty() {
+        ctx.print_color(
+            17,
+            y,
+            RGB::named(rltk::GRAY),
+            RGB::named(rltk::BLACK),
+            "(nothing)",
+        );
     }
 
-    // Draw mouse cursor
-    let mouse_pos = ctx.mouse_pos();
-    let mouse_map_pos = (mouse_pos.0 + min_x - 1, mouse_pos.1 + min_y - 1);
-    let mut valid_target = false;
-    for idx in available_cells.iter() {
-        if idx.x == mouse_map_pos.0 && idx.y == mouse_map_pos.1 {
-            valid_target = true;
-        }
-    }
-    if valid_target {
-        ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::CYAN));
-        if ctx.left_click {
-            return (
-                ItemMenuResult::Selected,
-                Some(Point::new(mouse_map_pos.0, mouse_map_pos.1)),
-            );
-        }
-    } else {
-        ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::RED));
-        if ctx.left_click {
-            return (ItemMenuResult::Cancel, None);
-        }
+    for (j, (_entity, name)) in items.iter().enumerate() {
+        ctx.set(
+            12,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437('('),
+        );
+        ctx.set(
+            13,
+            y,
+            RGB::named(rltk::YELLOW),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437('a') + j as FontCharType,
+        );
+        ctx.set(
+            14,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437(')'),
+        );
+
+        ctx.print(16, y, &name);
+        y += 1;
     }
 
-    (ItemMenuResult::NoResponse, None)
+    match ctx.key {
+        None => (ContainerMenuResult::NoResponse, None),
+        Some(key) => match key {
+            VirtualKeyCode::Escape => (ContainerMenuResult::Cancel, None),
+            VirtualKeyCode::Tab => (ContainerMenuResult::SwitchMode, None),
+            VirtualKeyCode::Return if mode == ContainerMode::Take && count > 0 => {
+                (ContainerMenuResult::TakeAll, None)
+            }
+            _ => {
+                let selection = rltk::letter_to_option(key);
+                if selection >= 0 && selection < count as i32 {
+                    (
+                        ContainerMenuResult::Selected,
+                        Some(items[selection as usize].0),
+                    )
+                } else {
+                    (ContainerMenuResult::NoResponse, None)
+                }
+            }
+        },
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
-pub enum MainMenuSelection {
-    NewGame,
-    LoadGame,
-    Quit,
+pub enum VendorMode {
+    Buy,
+    Sell,
 }
 
-#[derive(Clone, Copy, Debug, PartialEq)]
-pub enum MainMenuResult {
-    NoSelection { selected: MainMenuSelection },
-    Selected { selected: MainMenuSelection },
+#[derive(Clone, Debug, PartialEq)]
+pub enum VendorResult {
+    Cancel,
+    NoResponse,
+    SwitchMode,
+    Buy { item: String, price: i32 },
+    Sell { item: Entity },
 }
 
-pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
-    let save_exists = super::saveload_system::does_save_exist();
-    let runstate = gs.resources.get::<RunState>().unwrap();
-    let assets = gs.resources.get::<RexAssets>().unwrap();
+pub fn show_vendor(
+    gs: &mut State,
+    ctx: &mut Rltk,
+    vendor: Entity,
+    mode: VendorMode,
+) -> VendorResult {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+
+    let vendor_name = match gs.world.get_component::<Name>(vendor) {
+        Some(name) => name.name.clone(),
+        None => "-Unknown-".to_string(),
+    };
+    let gold = gs
+        .world
+        .get_component::<Pools>(player_entity)
+        .map_or(0, |stats| stats.gold);
+
+    let stock: Vec<(String, i32)> = get_vendor_items(&PREFABS.lock().unwrap(), &vendor_name);
+    let sellable = vendor::sellable_items(&gs.world, player_entity);
+    let items: Vec<(String, i32)> = match mode {
+        VendorMode::Buy => stock.clone(),
+        VendorMode::Sell => sellable
+            .iter()
+            .map(|(_entity, name, price)| (name.clone(), *price))
+            .collect(),
+    };
 
-    ctx.render_xp_sprite(&assets.menu, 0, 0);
+    let count = items.len();
+    let title = match mode {
+        VendorMode::Buy => format!("Buy from the {} ({} gold)", vendor_name, gold),
+        VendorMode::Sell => format!("Sell to the {} ({} gold)", vendor_name, gold),
+    };
 
-    ctx.draw_box_double(
-        24,
-        18,
-        31,
+    let mut y = (25 - (count / 2)) as i32;
+    ctx.draw_box(
         10,
-        RGB::named(rltk::WHEAT),
+        y - 2,
+        51,
+        (count + 4) as i32,
+        RGB::named(rltk::WHITE),
         RGB::named(rltk::BLACK),
     );
-    ctx.print_color_centered(
-        20,
+    ctx.print_color(
+        13,
+        y - 2,
         RGB::named(rltk::YELLOW),
         RGB::named(rltk::BLACK),
-        "Rust Roguelike Tutorial",
+        &title,
     );
-    ctx.print_color_centered(
-        21,
-        RGB::named(rltk::CYAN),
+    ctx.print_color(
+        13,
+        y + count as i32 + 1,
+        RGB::named(rltk::YELLOW),
         RGB::named(rltk::BLACK),
-        "by Herbert Wolverson",
+        match mode {
+            VendorMode::Buy => "TAB to sell",
+            VendorMode::Sell => "TAB to buy",
+        },
     );
-    ctx.print_color_centered(
-        22,
-        RGB::named(rltk::GRAY),
+    ctx.print_color(
+        13,
+        y + count as i32 + 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "ESC to cancel",
+    );
+
+    if items.is_empty() {
+        ctx.print_color(
+            17,
+            y,
+            RGB::named(rltk::GRAY),
+            RGB::named(rltk::BLACK),
+            "(nothing)",
+        );
+    }
+
+    for (j, (name, price)) in items.iter().enumerate() {
+        ctx.set(
+            12,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437('('),
+        );
+        ctx.set(
+            13,
+            y,
+            RGB::named(rltk::YELLOW),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437('a') + j as FontCharType,
+        );
+        ctx.set(
+            14,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437(')'),
+        );
+
+        ctx.print(16, y, &name);
+        let affordable = mode == VendorMode::Sell || *price <= gold;
+        ctx.print_color(
+            48,
+            y,
+            if affordable {
+                RGB::named(rltk::GOLD)
+            } else {
+                RGB::named(rltk::GRAY)
+            },
+            RGB::named(rltk::BLACK),
+            &format!("{:>5} gold", price),
+        );
+        y += 1;
+    }
+
+    match ctx.key {
+        None => VendorResult::NoResponse,
+        Some(key) => match key {
+            VirtualKeyCode::Escape => VendorResult::Cancel,
+            VirtualKeyCode::Tab => VendorResult::SwitchMode,
+            _ => {
+                let selection = rltk::letter_to_option(key);
+                if selection >= 0 && selection < count as i32 {
+                    let selection = selection as usize;
+                    match mode {
+                        VendorMode::Buy => VendorResult::Buy {
+                            item: stock[selection].0.clone(),
+                            price: stock[selection].1,
+                        },
+                        VendorMode::Sell => VendorResult::Sell {
+                            item: sellable[selection].0,
+                        },
+                    }
+                } else {
+                    VendorResult::NoResponse
+                }
+            }
+        },
+    }
+}
+
+pub fn ranged_target(
+    gs: &mut State,
+    ctx: &mut Rltk,
+    range: i32,
+) -> (ItemMenuResult, Option<Point>) {
+    let (min_x, max_x, min_y, max_y) = camera::get_screen_bounds(&gs.resources, ctx);
+    let player_entity = gs.resources.get::<Entity>().unwrap();
+    let player_pos = gs.resources.get::<Point>().unwrap();
+
+    ctx.print_color(
+        5,
+        0,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Select Target:",
+    );
+
+    // Highlight available target cells
+    let mut available_cells = Vec::new();
+    let visible = gs.world.get_component::<Viewshed>(*player_entity);
+    if let Some(visible) = visible {
+        // We have a viewshed
+        for idx in visible.visible_tiles.iter() {
+            let distance = rltk::DistanceAlg::Pythagoras.distance2d(*player_pos, *idx);
+            if distance <= range as f32 {
+                let screen_x = idx.x - min_x;
+                let screen_y = idx.y - min_y;
+                if screen_x > 1
+                    && screen_x < (max_x - min_x) - 1
+                    && screen_y > 1
+                    && screen_y < (max_y - min_y) - 1
+                {
+                    ctx.set_bg(screen_x, screen_y, RGB::named(rltk::BLUE));
+                    available_cells.push(*idx);
+                }
+            }
+        }
+    } else {
+        return (ItemMenuResult::Cancel, None);
+    }
+
+    // Draw mouse cursor
+    let mouse_pos = ctx.mouse_pos();
+    let mouse_map_pos = (mouse_pos.0 + min_x - 1, mouse_pos.1 + min_y - 1);
+    let mut valid_target = false;
+    for idx in available_cells.iter() {
+        if idx.x == mouse_map_pos.0 && idx.y == mouse_map_pos.1 {
+            valid_target = true;
+        }
+    }
+    if valid_target {
+        ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::CYAN));
+        if ctx.left_click {
+            return (
+                ItemMenuResult::Selected,
+                Some(Point::new(mouse_map_pos.0, mouse_map_pos.1)),
+            );
+        }
+    } else {
+        ctx.set_bg(mouse_pos.0, mouse_pos.1, RGB::named(rltk::RED));
+        if ctx.left_click {
+            return (ItemMenuResult::Cancel, None);
+        }
+    }
+
+    (ItemMenuResult::NoResponse, None)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MainMenuSelection {
+    NewGame,
+    LoadGame,
+    HallOfFame,
+    Quit,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum MainMenuResult {
+    NoSelection { selected: MainMenuSelection },
+    Selected { selected: MainMenuSelection },
+}
+
+pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
+    let save_exists = super::saveload_system::does_save_exist();
+    let runstate = gs.resources.get::<RunState>().unwrap();
+    let assets = gs.resources.get::<RexAssets>().unwrap();
+
+    ctx.render_xp_sprite(&assets.menu, 0, 0);
+
+    ctx.draw_box_double(
+        24,
+        18,
+        31,
+        10,
+        RGB::named(rltk::WHEAT),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color_centered(
+        20,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Rust Roguelike Tutorial",
+    );
+    ctx.print_color_centered(
+        21,
+        RGB::named(rltk::CYAN),
+        RGB::named(rltk::BLACK),
+        "by Herbert Wolverson",
+    );
+    ctx.print_color_centered(
+        22,
+        RGB::named(rltk::GRAY),
         RGB::named(rltk::BLACK),
         "Use Up/Down Arrows and Enter",
     );
@@ -736,6 +1187,18 @@ pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
             y += 1;
         }
 
+        ctx.print_color_centered(
+            y,
+            if selected == MainMenuSelection::HallOfFame {
+                RGB::named(rltk::MAGENTA)
+            } else {
+                RGB::named(rltk::WHITE)
+            },
+            RGB::named(rltk::BLACK),
+            "Hall of Fame",
+        );
+        y += 1;
+
         ctx.print_color_centered(
             y,
             if selected == MainMenuSelection::Quit {
@@ -760,23 +1223,29 @@ pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
                     MainMenuSelection::LoadGame => MainMenuResult::NoSelection {
                         selected: MainMenuSelection::NewGame,
                     },
-                    MainMenuSelection::Quit => MainMenuResult::NoSelection {
+                    MainMenuSelection::HallOfFame => MainMenuResult::NoSelection {
                         selected: if save_exists {
                             MainMenuSelection::LoadGame
                         } else {
                             MainMenuSelection::NewGame
                         },
                     },
+                    MainMenuSelection::Quit => MainMenuResult::NoSelection {
+                        selected: MainMenuSelection::HallOfFame,
+                    },
                 },
                 VirtualKeyCode::Down => match selected {
                     MainMenuSelection::NewGame => MainMenuResult::NoSelection {
                         selected: if save_exists {
                             MainMenuSelection::LoadGame
                         } else {
-                            MainMenuSelection::Quit
+                            MainMenuSelection::HallOfFame
                         },
                     },
                     MainMenuSelection::LoadGame => MainMenuResult::NoSelection {
+                        selected: MainMenuSelection::HallOfFame,
+                    },
+                    MainMenuSelection::HallOfFame => MainMenuResult::NoSelection {
                         selected: MainMenuSelection::Quit,
                     },
                     MainMenuSelection::Quit => MainMenuResult::NoSelection {
@@ -794,31 +1263,122 @@ pub fn main_menu(gs: &mut State, ctx: &mut Rltk) -> MainMenuResult {
     }
 }
 
+pub fn hall_of_fame(gs: &State, ctx: &mut Rltk) -> ItemMenuResult {
+    let table = gs.resources.get::<hall_of_fame::HallOfFame>().unwrap();
+    let scores = &table.scores;
+    let shown = usize::min(scores.len(), 30);
+
+    ctx.draw_box_double(
+        2,
+        4,
+        75,
+        shown as i32 + 5,
+        RGB::named(rltk::WHEAT),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color_centered(
+        4,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Hall of Fame",
+    );
+    ctx.print_color(
+        4,
+        6,
+        RGB::named(rltk::CYAN),
+        RGB::named(rltk::BLACK),
+        "Score  Name            Lvl Depth  Fate                  Date",
+    );
+    if scores.is_empty() {
+        ctx.print_color(
+            4,
+            7,
+            RGB::named(rltk::GRAY),
+            RGB::named(rltk::BLACK),
+            "No one has fallen yet.",
+        );
+    }
+    for (i, entry) in scores.iter().take(shown).enumerate() {
+        let name: String = entry.name.chars().take(15).collect();
+        let cause: String = entry.cause.chars().take(21).collect();
+        ctx.print_color(
+            4,
+            7 + i as i32,
+            if entry.victory {
+                RGB::named(rltk::GOLD)
+            } else {
+                RGB::named(rltk::WHITE)
+            },
+            RGB::named(rltk::BLACK),
+            &format!(
+                "{:>5}  {:<15} {:>3} {:>5}  {:<21} {}",
+                entry.score, name, entry.level, entry.depth, cause, entry.date
+            ),
+        );
+    }
+    ctx.print_color_centered(
+        shown as i32 + 8,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "ESC to return",
+    );
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => ItemMenuResult::Cancel,
+        _ => ItemMenuResult::NoResponse,
+    }
+}
+
 #[derive(PartialEq, Copy, Clone)]
 pub enum GameOverResult {
     NoSelection,
     QuitToMenu,
 }
 
-pub fn game_over(ctx: &mut Rltk) -> GameOverResult {
+pub fn victory(gs: &mut State, ctx: &mut Rltk) -> GameOverResult {
     ctx.print_color_centered(
-        15,
-        RGB::named(rltk::YELLOW),
+        13,
+        RGB::named(rltk::GOLD),
         RGB::named(rltk::BLACK),
-        "Your journey has ended!",
+        "Victory! The Crown of the Deep is yours.",
     );
+    for (i, line) in morgue::summary(&gs.world, &gs.resources).iter().enumerate() {
+        ctx.print_color_centered(
+            15 + i as i32,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            line,
+        );
+    }
+
     ctx.print_color_centered(
-        17,
-        RGB::named(rltk::WHITE),
+        20,
+        RGB::named(rltk::MAGENTA),
         RGB::named(rltk::BLACK),
-        "One day, we'll tell you all about how you did.",
+        "Press any key to return to the menu.",
     );
+
+    match ctx.key {
+        None => GameOverResult::NoSelection,
+        Some(_) => GameOverResult::QuitToMenu,
+    }
+}
+
+pub fn game_over(gs: &mut State, ctx: &mut Rltk) -> GameOverResult {
     ctx.print_color_centered(
-        18,
-        RGB::named(rltk::WHITE),
+        13,
+        RGB::named(rltk::YELLOW),
         RGB::named(rltk::BLACK),
-        "That day, sadly, is not in this chapter..",
+        "Your journey has ended!",
     );
+    for (i, line) in morgue::summary(&gs.world, &gs.resources).iter().enumerate() {
+        ctx.print_color_centered(
+            15 + i as i32,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            line,
+        );
+    }
 
     ctx.print_color_centered(
         20,
@@ -832,3 +1392,916 @@ pub fn game_over(ctx: &mut Rltk) -> GameOverResult {
         Some(_) => GameOverResult::QuitToMenu,
     }
 }
+
+/// Breaks text into lines no wider than `width`, keeping words whole
+fn wrap_text(text: &str, width: usize) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut line = String::new();
+    for word in text.split_whitespace() {
+        if !line.is_empty() && line.len() + 1 + word.len() > width {
+            lines.push(line);
+            line = String::new();
+        }
+        if !line.is_empty() {
+            line.push(' ');
+        }
+        line.push_str(word);
+    }
+    if !line.is_empty() {
+        lines.push(line);
+    }
+    lines
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum QuestOfferResult {
+    NoResponse,
+    Accept,
+    Decline,
+}
+
+pub fn quest_offer(
+    gs: &mut State,
+    ctx: &mut Rltk,
+    giver: Entity,
+) -> (QuestOfferResult, Option<String>) {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+    let giver_name = match gs.world.get_component::<Name>(giver) {
+        Some(name) => name.name.clone(),
+        None => "-Unknown-".to_string(),
+    };
+    let quest_name = match quest_system::quest_on_offer(&gs.world, player_entity, &giver_name) {
+        Some(quest_name) => quest_name,
+        None => return (QuestOfferResult::Decline, None),
+    };
+    let pm = PREFABS.lock().unwrap();
+    let quest = get_quest(&pm, &quest_name).unwrap();
+
+    let description = wrap_text(&quest.description, 40);
+    let height = description.len() as i32 + 8;
+    let y = 20 - height / 2;
+    ctx.draw_box(
+        3,
+        y,
+        43,
+        height,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        5,
+        y,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        &quest.name,
+    );
+    ctx.print_color(
+        5,
+        y + 1,
+        RGB::named(rltk::CYAN),
+        RGB::named(rltk::BLACK),
+        &format!("The {} says:", giver_name),
+    );
+    for (i, line) in description.iter().enumerate() {
+        ctx.print(5, y + 2 + i as i32, line);
+    }
+    let y = y + 3 + description.len() as i32;
+    ctx.print(
+        5,
+        y,
+        &format!("Goal:   {}", quest_system::describe_objective(quest)),
+    );
+    ctx.print(
+        5,
+        y + 1,
+        &format!("Reward: {}", quest_system::describe_reward(quest)),
+    );
+    ctx.print_color(
+        5,
+        y + 3,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "(Y) Accept   (N) Decline",
+    );
+
+    match ctx.key {
+        None => (QuestOfferResult::NoResponse, None),
+        Some(key) => match key {
+            VirtualKeyCode::Y => (QuestOfferResult::Accept, Some(quest.name.clone())),
+            VirtualKeyCode::N | VirtualKeyCode::Escape => (QuestOfferResult::Decline, None),
+            _ => (QuestOfferResult::NoResponse, None),
+        },
+    }
+}
+
+pub fn show_quest_journal(gs: &mut State, ctx: &mut Rltk) -> ItemMenuResult {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+    let pm = PREFABS.lock().unwrap();
+    let quests = match gs.world.get_component::<QuestLog>(player_entity) {
+        Some(quest_log) => quest_log.quests.clone(),
+        None => Vec::new(),
+    };
+
+    let mut lines: Vec<(RGB, String)> = Vec::new();
+    lines.push((RGB::named(rltk::CYAN), "Active".to_string()));
+    for progress in quests.iter().filter(|q| q.status != QuestStatus::TurnedIn) {
+        if let Some(quest) = get_quest(&pm, &progress.name) {
+            let state = match (progress.status, &quest.objective) {
+                (QuestStatus::Complete, _) => format!(" - see the {}", quest.giver),
+                (_, QuestObjective::Kill { count, .. }) => {
+                    format!(" ({}/{})", progress.progress, count)
+                }
+                _ => String::new(),
+            };
+            lines.push((RGB::named(rltk::WHITE), quest.name.clone()));
+            lines.push((
+                RGB::named(rltk::GRAY),
+                format!("  {}{}", quest_system::describe_objective(quest), state),
+            ));
+        }
+    }
+    lines.push((RGB::named(rltk::CYAN), "Completed".to_string()));
+    for progress in quests.iter().filter(|q| q.status == QuestStatus::TurnedIn) {
+        lines.push((RGB::named(rltk::GREEN), progress.name.clone()));
+    }
+
+    let count = lines.len() as i32;
+    let y = 22 - count / 2;
+    ctx.draw_box(
+        3,
+        y - 2,
+        43,
+        count + 3,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        5,
+        y - 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Quest Journal",
+    );
+    ctx.print_color(
+        5,
+        y + count + 1,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "ESC to close",
+    );
+    for (i, (color, line)) in lines.iter().enumerate() {
+        ctx.print_color(5, y + i as i32, *color, RGB::named(rltk::BLACK), line);
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => ItemMenuResult::Cancel,
+        _ => ItemMenuResult::NoResponse,
+    }
+}
+
+pub fn show_skills(gs: &mut State, ctx: &mut Rltk) -> ItemMenuResult {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+    let level = gs
+        .world
+        .get_component::<Pools>(player_entity)
+        .map_or(1, |stats| stats.level);
+    let skills = match gs.world.get_component::<Skills>(player_entity) {
+        Some(skills) => skills.clone(),
+        None => return ItemMenuResult::Cancel,
+    };
+    let progress = match gs.world.get_component::<SkillProgress>(player_entity) {
+        Some(progress) => progress.practice.clone(),
+        None => HashMap::new(),
+    };
+    let cap = skill_system::skill_cap(level);
+
+    let count = level_up::SKILLS.len() as i32;
+    let y = 22 - count / 2;
+    ctx.draw_box(
+        15,
+        y - 2,
+        45,
+        count + 3,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        18,
+        y - 2,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Skills",
+    );
+    ctx.print_color(
+        18,
+        y + count + 1,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        &format!("Practice cap: {} - ESC to close", cap),
+    );
+
+    for (i, (skill, name)) in level_up::SKILLS.iter().enumerate() {
+        let row = y + i as i32;
+        let rank = *skills.skills.get(skill).unwrap_or(&0);
+        ctx.print_color(
+            17,
+            row,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            name,
+        );
+        ctx.print_color(
+            30,
+            row,
+            RGB::named(rltk::GOLD),
+            RGB::named(rltk::BLACK),
+            &format!("{:+}", skill_bonus(*skill, &skills)),
+        );
+        if rank >= cap {
+            ctx.print_color(
+                35,
+                row,
+                RGB::named(rltk::GRAY),
+                RGB::named(rltk::BLACK),
+                "(capped)",
+            );
+        } else {
+            ctx.draw_bar_horizontal(
+                35,
+                row,
+                20,
+                *progress.get(skill).unwrap_or(&0),
+                skill_system::practice_needed(rank),
+                RGB::named(rltk::GREEN),
+                RGB::named(rltk::BLACK),
+            );
+        }
+    }
+
+    match ctx.key {
+        Some(VirtualKeyCode::Escape) => ItemMenuResult::Cancel,
+        _ => ItemMenuResult::NoResponse,
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DialogueResult {
+    NoResponse,
+    Leave,
+    Selected { choice: usize },
+}
+
+pub fn show_dialogue(
+    gs: &mut State,
+    ctx: &mut Rltk,
+    speaker: Entity,
+    node: usize,
+) -> DialogueResult {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+    let speaker_name = match gs.world.get_component::<Name>(speaker) {
+        Some(name) => name.name.clone(),
+        None => return DialogueResult::Leave,
+    };
+    let pm = PREFABS.lock().unwrap();
+    let node = match get_dialogue(&pm, &speaker_name).and_then(|d| d.nodes.get(node)) {
+        Some(node) => node,
+        None => return DialogueResult::Leave,
+    };
+
+    // Only offer the choices the player qualifies for, remembering where each sits in the node
+    let choices: Vec<(usize, &DialogueChoice)> = node
+        .choices
+        .iter()
+        .enumerate()
+        .filter(|(_i, choice)| dialogue::choice_available(&gs.world, player_entity, choice, &pm))
+        .collect();
+
+    let text = wrap_text(&node.text, 40);
+    let mut lines: Vec<String> = Vec::new();
+    for (_i, choice) in choices.iter() {
+        lines.extend(wrap_text(&choice.text, 36));
+    }
+    let height = text.len() as i32 + lines.len() as i32 + 5;
+    let y = 20 - height / 2;
+    ctx.draw_box(
+        3,
+        y,
+        43,
+        height,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        5,
+        y,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        &speaker_name,
+    );
+    ctx.print_color(
+        5,
+        y + height,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "ESC to leave",
+    );
+    for (i, line) in text.iter().enumerate() {
+        ctx.print_color(
+            5,
+            y + 2 + i as i32,
+            RGB::named(rltk::CYAN),
+            RGB::named(rltk::BLACK),
+            line,
+        );
+    }
+
+    let mut y = y + 3 + text.len() as i32;
+    for (j, (_i, choice)) in choices.iter().enumerate() {
+        ctx.set(
+            5,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437('('),
+        );
+        ctx.set(
+            6,
+            y,
+            RGB::named(rltk::YELLOW),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437('a') + j as FontCharType,
+        );
+        ctx.set(
+            7,
+            y,
+            RGB::named(rltk::WHITE),
+            RGB::named(rltk::BLACK),
+            rltk::to_cp437(')'),
+        );
+        for line in wrap_text(&choice.text, 36) {
+            ctx.print(9, y, &line);
+            y += 1;
+        }
+    }
+
+    match ctx.key {
+        None => DialogueResult::NoResponse,
+        Some(key) => match key {
+            VirtualKeyCode::Escape => DialogueResult::Leave,
+            _ => {
+                let selection = rltk::letter_to_option(key);
+                if selection >= 0 && selection < choices.len() as i32 {
+                    DialogueResult::Selected {
+                        choice: choices[selection as usize].0,
+                    }
+                } else {
+                    DialogueResult::NoResponse
+                }
+            }
+        },
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum CharacterCreationResult {
+    NoSelection { selected: usize },
+    Cancel,
+    Begin,
+}
+
+// Rows on the character creation screen: name, the four attributes, background, begin
+const CREATION_ROWS: usize = 7;
+const NAME_ROW: usize = 0;
+const BACKGROUND_ROW: usize = 5;
+const BEGIN_ROW: usize = 6;
+const MAX_NAME_LENGTH: usize = 16;
+
+pub fn character_creation(
+    gs: &mut State,
+    ctx: &mut Rltk,
+    selected: usize,
+) -> CharacterCreationResult {
+    let pm = PREFABS.lock().unwrap();
+    let backgrounds = backgrounds(&pm);
+    let mut sheet = gs.resources.get_mut::<CharacterSheet>().unwrap();
+    let row_color = |row: usize| {
+        if row == selected {
+            RGB::named(rltk::MAGENTA)
+        } else {
+            RGB::named(rltk::WHITE)
+        }
+    };
+
+    ctx.draw_box_double(
+        10,
+        5,
+        59,
+        40,
+        RGB::named(rltk::WHEAT),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color_centered(
+        7,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Create Your Character",
+    );
+
+    let name = if selected == NAME_ROW {
+        format!("{}_", sheet.name)
+    } else {
+        sheet.player_name()
+    };
+    ctx.print_color(13, 10, row_color(NAME_ROW), RGB::named(rltk::BLACK), "Name");
+    ctx.print_color(27, 10, row_color(NAME_ROW), RGB::named(rltk::BLACK), &name);
+
+    for (i, attribute) in ATTRIBUTE_NAMES.iter().enumerate() {
+        let y = 12 + i as i32;
+        let score = sheet.scores[i];
+        ctx.print_color(13, y, row_color(i + 1), RGB::named(rltk::BLACK), attribute);
+        ctx.print_color(
+            27,
+            y,
+            row_color(i + 1),
+            RGB::named(rltk::BLACK),
+            &format!("{:>2} ({:+})", score, attr_bonus(score)),
+        );
+    }
+    let points = if sheet.rolled {
+        "Rolled - P to go back to point-buy".to_string()
+    } else {
+        format!("Points left: {}", sheet.points_left())
+    };
+    ctx.print_color(
+        13,
+        16,
+        RGB::named(rltk::GRAY),
+        RGB::named(rltk::BLACK),
+        &points,
+    );
+
+    let fitness = sheet.scores[1];
+    let intelligence = sheet.scores[3];
+    ctx.print_color(
+        13,
+        18,
+        RGB::named(rltk::CYAN),
+        RGB::named(rltk::BLACK),
+        &format!(
+            "Health: {}   Mana: {}",
+            player_hp_at_level(fitness, 1),
+            mana_at_level(intelligence, 1)
+        ),
+    );
+
+    ctx.print_color(
+        13,
+        20,
+        row_color(BACKGROUND_ROW),
+        RGB::named(rltk::BLACK),
+        "Background",
+    );
+    if let Some(background) = backgrounds.get(sheet.background) {
+        ctx.print_color(
+            27,
+            20,
+            row_color(BACKGROUND_ROW),
+            RGB::named(rltk::BLACK),
+            &format!("< {} >", background.name),
+        );
+        let mut y = 22;
+        for line in wrap_text(&background.description, 52) {
+            ctx.print(13, y, &line);
+            y += 1;
+        }
+        y += 1;
+        if let Some(skills) = &background.skills {
+            let mut trained: Vec<String> = skills
+                .iter()
+                .map(|(skill, level)| format!("{} {}", skill, level))
+                .collect();
+            trained.sort();
+            ctx.print(13, y, &format!("Skills: {}", trained.join(", ")));
+            y += 1;
+        }
+        let gear: Vec<String> = background
+            .equipped
+            .iter()
+            .flatten()
+            .chain(background.carried.iter().flatten())
+            .cloned()
+            .collect();
+        for line in wrap_text(&format!("Gear: {}", gear.join(", ")), 52) {
+            ctx.print(13, y, &line);
+            y += 1;
+        }
+        ctx.print(13, y, &format!("Gold: {}", background.gold.unwrap_or(0)));
+    }
+
+    ctx.print_color_centered(
+        38,
+        row_color(BEGIN_ROW),
+        RGB::named(rltk::BLACK),
+        "Begin Adventure",
+    );
+    ctx.print_color_centered(
+        41,
+        RGB::named(rltk::GRAY),
+        RGB::named(rltk::BLACK),
+        "Up/Down to select, Left/Right to change",
+    );
+    ctx.print_color_centered(
+        42,
+        RGB::named(rltk::GRAY),
+        RGB::named(rltk::BLACK),
+        "R to roll attributes, Enter to begin, ESC to go back",
+    );
+
+    match ctx.key {
+        None => CharacterCreationResult::NoSelection { selected },
+        Some(key) => match key {
+            VirtualKeyCode::Escape => CharacterCreationResult::Cancel,
+            VirtualKeyCode::Return => CharacterCreationResult::Begin,
+            VirtualKeyCode::Up => CharacterCreationResult::NoSelection {
+                selected: (selected + CREATION_ROWS - 1) % CREATION_ROWS,
+            },
+            VirtualKeyCode::Down => CharacterCreationResult::NoSelection {
+                selected: (selected + 1) % CREATION_ROWS,
+            },
+            VirtualKeyCode::Back if selected == NAME_ROW => {
+                sheet.name.pop();
+                CharacterCreationResult::NoSelection { selected }
+            }
+            _ if selected == NAME_ROW => {
+                // Typing on the name row spells out a name
+                let letter = rltk::letter_to_option(key);
+                if sheet.name.len() < MAX_NAME_LENGTH {
+                    if letter >= 0 {
+                        let letter = (b'a' + letter as u8) as char;
+                        if ctx.shift || sheet.name.is_empty() {
+                            sheet.name.push(letter.to_ascii_uppercase());
+                        } else {
+                            sheet.name.push(letter);
+                        }
+                    } else if key == VirtualKeyCode::Space && !sheet.name.is_empty() {
+                        sheet.name.push(' ');
+                    }
+                }
+                CharacterCreationResult::NoSelection { selected }
+            }
+            VirtualKeyCode::Left | VirtualKeyCode::Right => {
+                let raise = key == VirtualKeyCode::Right;
+                match selected {
+                    1..=4 if raise => sheet.raise(selected - 1),
+                    1..=4 => sheet.lower(selected - 1),
+                    BACKGROUND_ROW if !backgrounds.is_empty() => {
+                        let count = backgrounds.len();
+                        sheet.background = if raise {
+                            (sheet.background + 1) % count
+                        } else {
+                            (sheet.background + count - 1) % count
+                        };
+                    }
+                    _ => {}
+                }
+                CharacterCreationResult::NoSelection { selected }
+            }
+            VirtualKeyCode::R => {
+                let mut rng = gs.resources.get_mut::<RandomNumberGenerator>().unwrap();
+                sheet.roll(&mut rng);
+                CharacterCreationResult::NoSelection { selected }
+            }
+            VirtualKeyCode::P => {
+                sheet.reset();
+                CharacterCreationResult::NoSelection { selected }
+            }
+            _ => CharacterCreationResult::NoSelection { selected },
+        },
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum LevelUpResult {
+    NoSelection { selected: usize },
+    Confirm,
+}
+
+pub fn level_up(gs: &mut State, ctx: &mut Rltk, selected: usize) -> LevelUpResult {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+    let mut choices = gs.resources.get_mut::<LevelUpChoices>().unwrap();
+    let points = *gs
+        .world
+        .get_component::<UnspentPoints>(player_entity)
+        .unwrap();
+    let attributes = *gs.world.get_component::<Attributes>(player_entity).unwrap();
+    let stats = *gs.world.get_component::<Pools>(player_entity).unwrap();
+    let skills = gs.world.get_component::<Skills>(player_entity).unwrap();
+    let preview = level_up::preview_attributes(&attributes, &choices);
+    let preview_skills = level_up::preview_skills(&skills, &choices);
+    let rows = ATTRIBUTE_NAMES.len() + level_up::SKILLS.len();
+    let row_color = |row: usize| {
+        if row == selected {
+            RGB::named(rltk::MAGENTA)
+        } else {
+            RGB::named(rltk::WHITE)
+        }
+    };
+
+    ctx.draw_box(
+        15,
+        10,
+        49,
+        26,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        18,
+        10,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        &format!("Level {}", stats.level),
+    );
+
+    ctx.print_color(
+        18,
+        12,
+        RGB::named(rltk::CYAN),
+        RGB::named(rltk::BLACK),
+        &format!(
+            "Attributes - {} point(s) left",
+            choices.attributes_left(&points)
+        ),
+    );
+    let scores = [
+        preview.might,
+        preview.fitness,
+        preview.quickness,
+        preview.intelligence,
+    ];
+    for (i, (name, attribute)) in ATTRIBUTE_NAMES.iter().zip(scores.iter()).enumerate() {
+        let y = 13 + i as i32;
+        ctx.print_color(18, y, row_color(i), RGB::named(rltk::BLACK), name);
+        ctx.print_color(
+            34,
+            y,
+            row_color(i),
+            RGB::named(rltk::BLACK),
+            &format!("{:>2} ({:+})", attribute.base, attribute.bonus),
+        );
+        if choices.attributes[i] > 0 {
+            ctx.print_color(
+                44,
+                y,
+                RGB::named(rltk::GREEN),
+                RGB::named(rltk::BLACK),
+                &format!("+{}", choices.attributes[i]),
+            );
+        }
+    }
+
+    ctx.print_color(
+        18,
+        18,
+        RGB::named(rltk::CYAN),
+        RGB::named(rltk::BLACK),
+        &format!("Skills - {} point(s) left", choices.skills_left(&points)),
+    );
+    for (i, (skill, name)) in level_up::SKILLS.iter().enumerate() {
+        let y = 19 + i as i32;
+        let row = ATTRIBUTE_NAMES.len() + i;
+        let level = preview_skills.skills.get(skill).copied().unwrap_or(0);
+        ctx.print_color(18, y, row_color(row), RGB::named(rltk::BLACK), name);
+        ctx.print_color(
+            34,
+            y,
+            row_color(row),
+            RGB::named(rltk::BLACK),
+            &format!("{:>2}", level),
+        );
+        if choices.skills[i] > 0 {
+            ctx.print_color(
+                44,
+                y,
+                RGB::named(rltk::GREEN),
+                RGB::named(rltk::BLACK),
+                &format!("+{}", choices.skills[i]),
+            );
+        }
+    }
+
+    let (hit_points, mana) = level_up::preview_pools(&preview, stats.level);
+
+    // Worked out the same way the combat systems do, with what the player has on
+    let weapon_attribute = <(Read<MeleeWeapon>, Read<Equipped>)>::query()
+        .iter(&gs.world)
+        .find(|(_weapon, equipped)| {
+            equipped.owner == player_entity && equipped.slot == EquipmentSlot::Melee
+        })
+        .map_or(WeaponAttribute::Might, |(weapon, _equipped)| {
+            weapon.attribute
+        });
+    let worn_armor: f32 = <(Read<Wearable>, Read<Equipped>)>::query()
+        .iter(&gs.world)
+        .filter(|(_armor, equipped)| equipped.owner == player_entity)
+        .map(|(armor, _equipped)| armor.armor_class)
+        .sum();
+    let natural_armor = gs
+        .world
+        .get_component::<NaturalAttackDefense>(player_entity)
+        .and_then(|nat| nat.armor_class);
+    ctx.print_color(
+        18,
+        25,
+        RGB::named(rltk::CYAN),
+        RGB::named(rltk::BLACK),
+        &format!("Health: {}   Mana: {}", hit_points, mana),
+    );
+    ctx.print_color(
+        18,
+        26,
+        RGB::named(rltk::CYAN),
+        RGB::named(rltk::BLACK),
+        &format!(
+            "To hit: {:+}   Armor: {}   Perception: {:+}",
+            attack_bonus(&preview, weapon_attribute, Skill::Melee, &preview_skills),
+            armor_class(natural_armor, &preview, &preview_skills, worn_armor),
+            perception_bonus(&preview, &preview_skills),
+        ),
+    );
+
+    let done = choices.attributes_left(&points) == 0 && choices.skills_left(&points) == 0;
+    ctx.print_color(
+        18,
+        29,
+        RGB::named(rltk::GRAY),
+        RGB::named(rltk::BLACK),
+        "Up/Down to select, Left/Right to spend",
+    );
+    ctx.print_color(
+        18,
+        30,
+        if done {
+            RGB::named(rltk::YELLOW)
+        } else {
+            RGB::named(rltk::GRAY)
+        },
+        RGB::named(rltk::BLACK),
+        "Enter to confirm once every point is spent",
+    );
+
+    match ctx.key {
+        None => LevelUpResult::NoSelection { selected },
+        Some(key) => match key {
+            VirtualKeyCode::Up => LevelUpResult::NoSelection {
+                selected: (selected + rows - 1) % rows,
+            },
+            VirtualKeyCode::Down => LevelUpResult::NoSelection {
+                selected: (selected + 1) % rows,
+            },
+            VirtualKeyCode::Right => {
+                if selected < ATTRIBUTE_NAMES.len() {
+                    if choices.attributes_left(&points) > 0 {
+                        choices.attributes[selected] += 1;
+                    }
+                } else if choices.skills_left(&points) > 0 {
+                    choices.skills[selected - ATTRIBUTE_NAMES.len()] += 1;
+                }
+                LevelUpResult::NoSelection { selected }
+            }
+            VirtualKeyCode::Left => {
+                if selected < ATTRIBUTE_NAMES.len() {
+                    choices.attributes[selected] = i32::max(0, choices.attributes[selected] - 1);
+                } else {
+                    let skill = selected - ATTRIBUTE_NAMES.len();
+                    choices.skills[skill] = i32::max(0, choices.skills[skill] - 1);
+                }
+                LevelUpResult::NoSelection { selected }
+            }
+            VirtualKeyCode::Return if done => LevelUpResult::Confirm,
+            _ => LevelUpResult::NoSelection { selected },
+        },
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum PerkResult {
+    Cancel,
+    NoSelection { selected: usize },
+    Selected { perk: String },
+}
+
+pub fn show_perks(gs: &mut State, ctx: &mut Rltk, selected: usize) -> PerkResult {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+    let pm = PREFABS.lock().unwrap();
+    let perks = perks(&pm);
+    let points = *gs
+        .world
+        .get_component::<UnspentPoints>(player_entity)
+        .unwrap();
+    let taken = gs
+        .world
+        .get_component::<Perks>(player_entity)
+        .map(|perks| perks.taken.clone())
+        .unwrap_or_default();
+    let rows = perks.len();
+
+    ctx.draw_box(
+        15,
+        10,
+        49,
+        30,
+        RGB::named(rltk::WHITE),
+        RGB::named(rltk::BLACK),
+    );
+    ctx.print_color(
+        18,
+        10,
+        RGB::named(rltk::YELLOW),
+        RGB::named(rltk::BLACK),
+        "Perks",
+    );
+    ctx.print_color(
+        18,
+        12,
+        RGB::named(rltk::CYAN),
+        RGB::named(rltk::BLACK),
+        &format!("{} perk(s) to choose", points.perks),
+    );
+
+    let mut y = 14;
+    for (i, perk) in perks.iter().enumerate() {
+        // Taken perks are green, ones we can't have yet are gray
+        let fg = if i == selected {
+            RGB::named(rltk::MAGENTA)
+        } else if taken.contains(&perk.name) {
+            RGB::named(rltk::GREEN)
+        } else if level_up::perk_available(&gs.world, player_entity, perk) {
+            RGB::named(rltk::WHITE)
+        } else {
+            RGB::named(rltk::GRAY)
+        };
+        ctx.print_color(18, y, fg, RGB::named(rltk::BLACK), &perk.name);
+        if taken.contains(&perk.name) {
+            ctx.print_color(
+                50,
+                y,
+                RGB::named(rltk::GREEN),
+                RGB::named(rltk::BLACK),
+                "Taken",
+            );
+        }
+        y += 1;
+    }
+
+    if let Some(perk) = perks.get(selected) {
+        y += 1;
+        for line in wrap_text(&perk.description, 44) {
+            ctx.print_color(
+                18,
+                y,
+                RGB::named(rltk::WHITE),
+                RGB::named(rltk::BLACK),
+                &line,
+            );
+            y += 1;
+        }
+        ctx.print_color(
+            18,
+            y,
+            RGB::named(rltk::CYAN),
+            RGB::named(rltk::BLACK),
+            &format!("Requires: {}", level_up::describe_requirements(perk)),
+        );
+    }
+
+    ctx.print_color(
+        18,
+        39,
+        RGB::named(rltk::GRAY),
+        RGB::named(rltk::BLACK),
+        "Up/Down to select, Enter to learn, ESC to close",
+    );
+
+    match ctx.key {
+        None => PerkResult::NoSelection { selected },
+        Some(key) => match key {
+            VirtualKeyCode::Up if rows > 0 => PerkResult::NoSelection {
+                selected: (selected + rows - 1) % rows,
+            },
+            VirtualKeyCode::Down if rows > 0 => PerkResult::NoSelection {
+                selected: (selected + 1) % rows,
+            },
+            VirtualKeyCode::Escape => PerkResult::Cancel,
+            VirtualKeyCode::Return => match perks.get(selected) {
+                Some(perk) if level_up::perk_available(&gs.world, player_entity, perk) => {
+                    PerkResult::Selected {
+                        perk: perk.name.clone(),
+                    }
+                }
+                _ => PerkResult::NoSelection { selected },
+            },
+            _ => PerkResult::NoSelection { selected },
+        },
+    }
+}
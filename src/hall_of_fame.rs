@@ -0,0 +1,119 @@
+use super::{clock_system::GameClock, storage, Map, Name, Pools, RunStats};
+use legion::prelude::*;
+use serde::{Deserialize, Serialize};
+
+// Where the table lives in storage
+const HALL_OF_FAME_KEY: &str = "halloffame.json";
+// Only the best are remembered
+const MAX_ENTRIES: usize = 100;
+
+const SCORE_PER_DEPTH: i32 = 500;
+const SCORE_PER_KILL: i32 = 25;
+const SCORE_FOR_VICTORY: i32 = 10000;
+// A point for every so many turns survived
+const TURNS_PER_POINT: i32 = 20;
+
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct HighScore {
+    pub name: String,
+    pub score: i32,
+    pub level: i32,
+    pub depth: i32,
+    pub cause: String,
+    pub seed: u64,
+    pub date: String,
+    #[serde(default)]
+    pub victory: bool,
+}
+
+/// The table as it was when the screen opened, so it isn't read from storage every frame
+pub struct HallOfFame {
+    pub scores: Vec<HighScore>,
+}
+
+/// Deeper, more experienced, bloodier and longer runs all score higher, and winning most of all
+pub fn score(stats: &RunStats, experience: i32, turns: i32) -> i32 {
+    let victory = if stats.victory { SCORE_FOR_VICTORY } else { 0 };
+    stats.max_depth * SCORE_PER_DEPTH
+        + experience
+        + stats.kills.values().sum::<i32>() * SCORE_PER_KILL
+        + turns / TURNS_PER_POINT
+        + victory
+}
+
+/// The player's score for the run so far
+pub fn current_score(world: &World, resources: &Resources) -> i32 {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let experience = world
+        .get_component::<Pools>(player_entity)
+        .map_or(0, |stats| stats.experience);
+    let turns = resources
+        .get::<GameClock>()
+        .map_or(0, |clock| clock.elapsed());
+    match world.get_component::<RunStats>(player_entity) {
+        Some(stats) => score(&stats, experience, turns),
+        None => 0,
+    }
+}
+
+/// Every recorded run, best first
+pub fn load() -> Vec<HighScore> {
+    storage::read(HALL_OF_FAME_KEY)
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default()
+}
+
+/// Adds the run that just ended to the table
+pub fn record(world: &World, resources: &Resources) {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let stats = match world.get_component::<RunStats>(player_entity) {
+        Some(stats) => (*stats).clone(),
+        None => return,
+    };
+    let entry = HighScore {
+        name: world
+            .get_component::<Name>(player_entity)
+            .map_or("The adventurer".to_string(), |name| name.name.clone()),
+        score: current_score(world, resources),
+        level: world
+            .get_component::<Pools>(player_entity)
+            .map_or(1, |pools| pools.level),
+        depth: resources.get::<Map>().map_or(1, |map| map.depth),
+        cause: if stats.victory {
+            "Victorious".to_string()
+        } else {
+            stats
+                .last_hurt_by
+                .clone()
+                .unwrap_or_else(|| "misadventure".to_string())
+        },
+        seed: stats.seed,
+        date: date_string(storage::now()),
+        victory: stats.victory,
+    };
+
+    let mut scores = load();
+    scores.push(entry);
+    scores.sort_by(|a, b| b.score.cmp(&a.score));
+    scores.truncate(MAX_ENTRIES);
+    match serde_json::to_string_pretty(&scores) {
+        Ok(data) => storage::write(HALL_OF_FAME_KEY, &data),
+        Err(err) => rltk::console::log(format!("Unable to save the hall of fame: {}", err)),
+    }
+}
+
+/// A Unix timestamp as a YYYY-MM-DD date
+fn date_string(timestamp: u64) -> String {
+    // See: http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = (timestamp / 86400) as i64 + 719_468;
+    let era = days / 146_097;
+    let day_of_era = days - era * 146_097;
+    let year_of_era =
+        (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let mp = (5 * day_of_year + 2) / 153;
+    let day = day_of_year - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
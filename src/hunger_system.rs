@@ -1,16 +1,26 @@
-use super::{gamelog::GameLog, HungerClock, HungerState, RunState, SufferDamage};
+use super::{gamelog::GameLog, HungerClock, HungerState, Modifiers, RunState, SufferDamage};
 use legion::prelude::*;
 
+const HUNGER_DURATION: i32 = 200;
+
 pub fn build() -> Box<(dyn Schedulable + 'static)> {
     SystemBuilder::new("hunger")
         .read_resource::<Entity>() // The Player
         .read_resource::<RunState>()
         .write_resource::<GameLog>()
         .with_query(Write::<HungerClock>::query())
+        .read_component::<Modifiers>()
         .build(
-            |command_buffer, world, (player_entity, runstate, log), query| {
-                for (entity, mut clock) in query.iter_entities_mut(world) {
+            |command_buffer, world, (player_entity, runstate, log), query| unsafe {
+                for (entity, mut clock) in query.iter_entities_unchecked(world) {
                     let is_player = entity == **player_entity;
+                    // Some constitutions take longer to go from one state to the next
+                    let duration = match world.get_component::<Modifiers>(entity) {
+                        Some(modifiers) => {
+                            HUNGER_DURATION * (100 + modifiers.hunger_duration) / 100
+                        }
+                        None => HUNGER_DURATION,
+                    };
 
                     let proceed = match **runstate {
                         RunState::PlayerTurn => is_player,
@@ -24,21 +34,21 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                             match clock.state {
                                 HungerState::WellFed => {
                                     clock.state = HungerState::Normal;
-                                    clock.duration = 200;
+                                    clock.duration = duration;
                                     if is_player {
                                         log.entries.push("You are no longer well fed.".to_string());
                                     }
                                 }
                                 HungerState::Normal => {
                                     clock.state = HungerState::Hungry;
-                                    clock.duration = 200;
+                                    clock.duration = duration;
                                     if is_player {
                                         log.entries.push("You are hungry.".to_string());
                                     }
                                 }
                                 HungerState::Hungry => {
                                     clock.state = HungerState::Starving;
-                                    clock.duration = 200;
+                                    clock.duration = duration;
                                     if is_player {
                                         log.entries.push("You are starving.".to_string());
                                     }
@@ -50,6 +60,11 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                                         "Your hunger is getting painful!. You suffer 1 hp damage."
                                             .to_string(),
                                     );
+                                        RunStats::hurt_by(
+                                            command_buffer,
+                                            entity,
+                                            "starvation".to_string(),
+                                        );
                                     }
                                     SufferDamage::new_damage(command_buffer, entity, 1, false);
                                 }
@@ -1,35 +1,50 @@
 use super::{
-    components::*, field_of_view, gamelog::GameLog, particle_system::ParticleBuilder, Map, RunState,
+    components::*,
+    effects::{EffectQueue, EffectType, Targets},
+    field_of_view,
+    gamelog::GameLog,
+    gui,
+    noise_system::{NoiseBuilder, EXPLOSION_NOISE},
+    particle_system::ParticleBuilder,
+    quest_system::{QuestEvent, QuestEvents},
+    Map, RunState,
 };
+use crate::prefabs::{get_item_drop, spawn_named_item, SpawnType, PREFABS};
 use legion::prelude::*;
+use rltk::RandomNumberGenerator;
 
 pub fn build() -> Box<(dyn Schedulable + 'static)> {
     SystemBuilder::new("item_collection")
         .with_query(Read::<WantsToPickupItem>::query())
         .read_resource::<Entity>()
         .write_resource::<GameLog>()
+        .write_resource::<QuestEvents>()
         .read_component::<Name>()
-        .build(|command_buffer, world, (player, gamelog), query| {
-            // NOTE: In case of multiple requests to pick item up, the last one wins.
-            // (As the InBackpack component gets overwritten)
-            for (entity, pickup) in query.iter_entities(world) {
-                command_buffer.remove_component::<WantsToPickupItem>(entity);
-                command_buffer.remove_component::<Position>(pickup.item);
-                command_buffer.add_component(
-                    pickup.item,
-                    InBackpack {
-                        owner: pickup.collected_by,
-                    },
-                );
+        .build(
+            |command_buffer, world, (player, gamelog, quest_events), query| {
+                // NOTE: In case of multiple requests to pick item up, the last one wins.
+                // (As the InBackpack component gets overwritten)
+                for (entity, pickup) in query.iter_entities(world) {
+                    command_buffer.remove_component::<WantsToPickupItem>(entity);
+                    command_buffer.remove_component::<Position>(pickup.item);
+                    command_buffer.remove_component::<InContainer>(pickup.item);
+                    command_buffer.add_component(
+                        pickup.item,
+                        InBackpack {
+                            owner: pickup.collected_by,
+                        },
+                    );
 
-                if pickup.collected_by == **player {
-                    let name = world.get_component::<Name>(pickup.item).unwrap();
-                    gamelog
-                        .entries
-                        .push(format!("You pick up the {}.", name.name))
+                    if pickup.collected_by == **player {
+                        let name = world.get_component::<Name>(pickup.item).unwrap();
+                        gamelog
+                            .entries
+                            .push(format!("You pick up the {}.", name.name));
+                        quest_events.record(QuestEvent::Pickup(name.name.clone()));
+                    }
                 }
-            }
-        })
+            },
+        )
 }
 
 pub fn item_use() -> Box<(dyn Schedulable + 'static)> {
@@ -39,64 +54,43 @@ pub fn item_use() -> Box<(dyn Schedulable + 'static)> {
         .write_resource::<GameLog>()
         .read_resource::<Map>()
         .write_resource::<ParticleBuilder>()
+        .write_resource::<NoiseBuilder>()
+        .write_resource::<EffectQueue>()
         .read_component::<Name>()
         .read_component::<AreaOfEffect>()
         .read_component::<InflictsDamage>()
-        .read_component::<ProvidesHealing>()
-        .read_component::<Confusion>()
         .read_component::<Equippable>()
-        .read_component::<Position>()
         .with_query(<(Read<Equipped>, Read<Name>)>::query())
-        .write_resource::<RunState>()
         .build(
-            #[allow(clippy::cognitive_complexity)]
             |command_buffer,
              world,
-             (player, gamelog, map, particle_builder, runstate),
+             (player, gamelog, map, particle_builder, noise, effects),
              (query, query_equipped)| {
                 for (entity, use_item) in query.iter_entities(world) {
                     let player_entity = **player;
                     let item_entity = use_item.item;
-                    let mut used_item = false;
-
-                    let item_name =
-                        if let Some(item_name) = world.get_component::<Name>(item_entity) {
-                            item_name.name.clone()
-                        } else {
-                            "-Unknown-".to_string()
-                        };
 
                     // Targeting
-                    let mut targets = Vec::new();
-                    match use_item.target {
-                        None => targets.push((player_entity, "Player".to_string())),
+                    let targets = match use_item.target {
+                        None => Targets::Caster,
                         Some(target) => {
                             let area_effect = world.get_component::<AreaOfEffect>(item_entity);
-                            let mut target_tiles = Vec::new();
-                            match area_effect {
+                            let target_tiles = match &area_effect {
                                 None => {
                                     // Single tile target
-                                    target_tiles.push(target);
+                                    vec![target]
                                 }
                                 Some(area_effect) => {
                                     // AoE
-                                    target_tiles =
-                                        field_of_view(target, area_effect.radius as usize, &**map);
-                                }
-                            }
-                            for tile in target_tiles.iter() {
-                                let idx = map.xy_idx(tile.x, tile.y);
-                                for mob in map.tile_content[idx].iter() {
-                                    let mob_entity = *mob;
-                                    let mob_name = if let Some(mob_name) =
-                                        world.get_component::<Name>(mob_entity)
+                                    // Explosions can be heard all over the level
+                                    if world.get_component::<InflictsDamage>(item_entity).is_some()
                                     {
-                                        mob_name.name.clone()
-                                    } else {
-                                        "-Unnamed-".to_string()
-                                    };
-                                    targets.push((mob_entity, mob_name));
+                                        noise.request(target.x, target.y, EXPLOSION_NOISE);
+                                    }
+                                    field_of_view(target, area_effect.radius as usize, &**map)
                                 }
+                            };
+                            for tile in target_tiles.iter() {
                                 particle_builder.request(
                                     tile.x,
                                     tile.y,
@@ -106,13 +100,19 @@ pub fn item_use() -> Box<(dyn Schedulable + 'static)> {
                                     200.0,
                                 );
                             }
+                            match area_effect {
+                                None => Targets::Tile { tile: target },
+                                Some(_) => Targets::Area {
+                                    tiles: target_tiles,
+                                },
+                            }
                         }
-                    }
+                    };
 
                     // If it is equippable, then we want to equip it - and unequip whatever else was in that slot
                     if let Some(can_equip) = world.get_component::<Equippable>(item_entity) {
                         let target_slot = can_equip.slot;
-                        let target = targets[0].0;
+                        let target = entity;
 
                         // Remove any items the target has in the item's slot
                         let mut to_unequip = Vec::new();
@@ -150,126 +150,12 @@ pub fn item_use() -> Box<(dyn Schedulable + 'static)> {
                         }
                     }
 
-                    // It it is edible, eat it!
-                    if let Some(_food) = world.get_tag::<ProvidesFood>(item_entity) {
-                        let target = targets[0].0;
-                        command_buffer.exec_mut(move |world| {
-                            if let Some(mut hc) = world.get_component_mut::<HungerClock>(target) {
-                                hc.state = HungerState::WellFed;
-                                hc.duration = 20;
-                            }
-                        });
-                        if target == player_entity {
-                            let name = world.get_component::<Name>(item_entity).unwrap();
-                            gamelog.entries.push(format!("You eat the {}.", name.name));
-                        }
-                        used_item = true;
-                    }
-
-                    // It it's a magic mapper...
-                    if let Some(_mm) = world.get_tag::<MagicMapper>(item_entity) {
-                        gamelog
-                            .entries
-                            .push("The map is revealed to you!".to_string());
-                        **runstate = RunState::MagicMapReveal { row: 0 };
-                        used_item = true;
-                    }
-
-                    // If it heals, apply the healing
-                    if let Some(healer) = world.get_component::<ProvidesHealing>(item_entity) {
-                        let heal_amount = healer.heal_amount;
-                        for (target_entity, _target_name) in targets.iter() {
-                            let target_entity = *target_entity;
-                            command_buffer.exec_mut(move |world| {
-                                if let Some(mut stats) =
-                                    world.get_component_mut::<Pools>(target_entity)
-                                {
-                                    stats.hit_points.current = i32::min(
-                                        stats.hit_points.max,
-                                        stats.hit_points.current + heal_amount,
-                                    );
-                                }
-                            });
-                            if let Some(pos) = world.get_component::<Position>(target_entity) {
-                                particle_builder.request(
-                                    pos.x,
-                                    pos.y,
-                                    rltk::RGB::named(rltk::GREEN),
-                                    rltk::RGB::named(rltk::BLACK),
-                                    rltk::to_cp437('♥'),
-                                    200.0,
-                                );
-                            }
-
-                            if entity == player_entity {
-                                gamelog.entries.push(format!(
-                                    "You use the {}, healing {} hp.",
-                                    item_name, heal_amount
-                                ));
-                            }
-                        }
-                        used_item = true;
-                    }
-
-                    // If it inflicts damage, apply damage to all target cell entities
-                    if let Some(damages) = world.get_component::<InflictsDamage>(item_entity) {
-                        for (target_entity, target_name) in targets.iter() {
-                            let target_entity = *target_entity;
-                            let damage = damages.damage;
-                            SufferDamage::new_damage(command_buffer, target_entity, damage, true);
-                            if let Some(pos) = world.get_component::<Position>(target_entity) {
-                                particle_builder.request(
-                                    pos.x,
-                                    pos.y,
-                                    rltk::RGB::named(rltk::RED),
-                                    rltk::RGB::named(rltk::BLACK),
-                                    rltk::to_cp437('‼'),
-                                    200.0,
-                                );
-                            }
-
-                            if entity == player_entity {
-                                gamelog.entries.push(format!(
-                                    "You use {} on {}, inflicting {} hp.",
-                                    item_name, target_name, damage
-                                ));
-                            }
-                        }
-                        used_item = true;
-                    }
-
-                    // Can it pass along confusion?
-                    if let Some(confusion) = world.get_component::<Confusion>(item_entity) {
-                        for (target_entity, target_name) in targets.iter() {
-                            let target_entity = *target_entity;
-                            command_buffer.add_component(target_entity, *confusion);
-                            if let Some(pos) = world.get_component::<Position>(target_entity) {
-                                particle_builder.request(
-                                    pos.x,
-                                    pos.y,
-                                    rltk::RGB::named(rltk::MAGENTA),
-                                    rltk::RGB::named(rltk::BLACK),
-                                    rltk::to_cp437('?'),
-                                    200.0,
-                                );
-                            }
-
-                            if entity == player_entity {
-                                gamelog.entries.push(format!(
-                                    "You use {} on {}, confusing them.",
-                                    item_name, target_name
-                                ));
-                            }
-                        }
-                        used_item = true;
-                    }
-
-                    // If it's a consumable, we delete it on use
-                    if used_item {
-                        if let Some(_consumable) = world.get_tag::<Consumable>(item_entity) {
-                            command_buffer.delete(item_entity);
-                        }
-                    }
+                    // Everything else the item does is handled by the effects queue
+                    effects.add_effect(
+                        Some(entity),
+                        EffectType::ItemUse { item: item_entity },
+                        targets,
+                    );
                     command_buffer.remove_component::<WantsToUseItem>(entity);
                 }
             },
@@ -328,7 +214,201 @@ pub fn item_remove() -> Box<(dyn Schedulable + 'static)> {
         })
 }
 
+pub fn item_take() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("item_take")
+        .with_query(Read::<WantsToTakeItem>::query())
+        .with_query(Read::<InContainer>::query())
+        .read_resource::<Entity>()
+        .write_resource::<GameLog>()
+        .write_resource::<QuestEvents>()
+        .read_component::<Name>()
+        .build(
+            |command_buffer, world, (player, gamelog, quest_events), (query, query_contents)| {
+                for (entity, to_take) in query.iter_entities(world) {
+                    let items: Vec<Entity> = match to_take.item {
+                        Some(item) => vec![item],
+                        None => query_contents
+                            .iter_entities(world)
+                            .filter(|(_item, stored)| stored.owner == to_take.container)
+                            .map(|(item, _stored)| item)
+                            .collect(),
+                    };
+
+                    for item_entity in items.iter() {
+                        command_buffer.remove_component::<InContainer>(*item_entity);
+                        command_buffer.add_component(*item_entity, InBackpack { owner: entity });
+
+                        if entity == **player {
+                            let item_name = if let Some(item_name) =
+                                world.get_component::<Name>(*item_entity)
+                            {
+                                item_name.name.clone()
+                            } else {
+                                "-Unknown-".to_string()
+                            };
+                            gamelog.entries.push(format!("You take the {}.", item_name));
+                            quest_events.record(QuestEvent::Pickup(item_name));
+                        }
+                    }
+
+                    // Once picked clean, a corpse is left to rot away
+                    if world.get_tag::<Corpse>(to_take.container).is_some()
+                        && !query_contents.iter_entities(world).any(|(item, stored)| {
+                            stored.owner == to_take.container && !items.contains(&item)
+                        })
+                    {
+                        command_buffer.delete(to_take.container);
+                    }
+                    command_buffer.remove_component::<WantsToTakeItem>(entity);
+                }
+            },
+        )
+}
+
+pub fn item_put() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("item_put")
+        .with_query(Read::<WantsToPutItem>::query())
+        .read_resource::<Entity>()
+        .write_resource::<GameLog>()
+        .read_component::<Name>()
+        .read_component::<Container>()
+        .build(|command_buffer, world, (player, gamelog), query| {
+            for (entity, to_put) in query.iter_entities(world) {
+                let item_entity = to_put.item;
+                command_buffer.remove_component::<WantsToPutItem>(entity);
+
+                // Bags don't nest
+                if world.get_component::<Container>(item_entity).is_some() {
+                    if entity == **player {
+                        gamelog
+                            .entries
+                            .push("You can't put a container inside another.".to_string());
+                    }
+                    continue;
+                }
+
+                command_buffer.remove_component::<InBackpack>(item_entity);
+                command_buffer.add_component(
+                    item_entity,
+                    InContainer {
+                        owner: to_put.container,
+                    },
+                );
+
+                if entity == **player {
+                    let item_name =
+                        if let Some(item_name) = world.get_component::<Name>(item_entity) {
+                            item_name.name.clone()
+                        } else {
+                            "-Unknown-".to_string()
+                        };
+                    let container_name =
+                        if let Some(name) = world.get_component::<Name>(to_put.container) {
+                            name.name.clone()
+                        } else {
+                            "-Unknown-".to_string()
+                        };
+                    gamelog.entries.push(format!(
+                        "You put the {} into the {}.",
+                        item_name, container_name
+                    ));
+                }
+            }
+        })
+}
+
+/// Returns true if the target isn't locked, or if the holder carries a key for it
+pub fn unlock_with_key(
+    world: &mut World,
+    resources: &Resources,
+    holder: Entity,
+    target: Entity,
+) -> bool {
+    let key_id = match world.get_component::<Lock>(target) {
+        Some(lock) => lock.key_id,
+        None => return true,
+    };
+
+    let mut key_name = None;
+    let query = <(Read<Key>, Read<InBackpack>, Read<Name>)>::query();
+    for (key, backpack, name) in query.iter(world) {
+        if backpack.owner == holder && key.key_id == key_id {
+            key_name = Some(name.name.clone());
+        }
+    }
+
+    let target_name = if let Some(name) = world.get_component::<Name>(target) {
+        name.name.clone()
+    } else {
+        "-Unknown-".to_string()
+    };
+    let mut gamelog = resources.get_mut::<GameLog>().unwrap();
+    match key_name {
+        None => {
+            gamelog
+                .entries
+                .push(format!("The {} is locked.", target_name));
+            false
+        }
+        Some(key_name) => {
+            world
+                .remove_component::<Lock>(target)
+                .expect("Cannot remove component");
+            gamelog.entries.push(format!(
+                "You unlock the {} with the {}.",
+                target_name, key_name
+            ));
+            true
+        }
+    }
+}
+
+pub fn open_container(world: &mut World, resources: &Resources, container: Entity) -> RunState {
+    let loot_rolls = match world.get_component::<Container>(container) {
+        Some(state) => state.loot_rolls,
+        None => return RunState::AwaitingInput,
+    };
+
+    let player = *resources.get::<Entity>().unwrap();
+    if !unlock_with_key(world, resources, player, container) {
+        return RunState::AwaitingInput;
+    }
+
+    // Loot is rolled the first time the container is opened
+    if loot_rolls > 0 {
+        let table = world
+            .get_component::<LootTable>(container)
+            .map(|loot| loot.table.clone());
+        if let Some(table) = table {
+            let prefabs = PREFABS.lock().unwrap();
+            let mut rng = resources.get_mut::<RandomNumberGenerator>().unwrap();
+            for _i in 0..loot_rolls {
+                if let Some(drop) = get_item_drop(&prefabs, &mut rng, &table) {
+                    spawn_named_item(
+                        &prefabs,
+                        world,
+                        &drop,
+                        SpawnType::Contained { by: container },
+                    );
+                }
+            }
+        }
+        if let Some(mut state) = world.get_component_mut::<Container>(container) {
+            state.loot_rolls = 0;
+        }
+    }
+
+    RunState::ShowContainer {
+        container,
+        mode: gui::ContainerMode::Take,
+    }
+}
+
 pub fn activate_item(world: &mut World, resources: &Resources, item: Entity) -> RunState {
+    if world.get_component::<Container>(item).is_some() {
+        return open_container(world, resources, item);
+    }
+
     if let Some(ranged) = world.get_component::<Ranged>(item) {
         return RunState::ShowTargeting {
             range: ranged.range,
@@ -0,0 +1,267 @@
+use super::{
+    attr_bonus, attribute_score, gamelog::GameLog, mana_at_level, player_hp_at_level, Attributes,
+    Modifiers, Perks, Pools, RunState, Skill, Skills, UnspentPoints,
+};
+use crate::prefabs::{
+    get_perk, parse_reaction, perks, string_to_weapon_category, Perk, PerkEffect, PrefabMaster,
+    PREFABS,
+};
+use legion::prelude::*;
+
+pub const ATTRIBUTE_POINTS_PER_LEVEL: i32 = 1;
+pub const SKILL_POINTS_PER_LEVEL: i32 = 2;
+// A perk is earned at every second level
+pub const PERK_LEVEL_INTERVAL: i32 = 2;
+
+pub const SKILLS: [(Skill, &str); 5] = [
+    (Skill::Melee, "Melee"),
+    (Skill::Defense, "Defense"),
+    (Skill::Magic, "Magic"),
+    (Skill::Lockpicking, "Lockpicking"),
+    (Skill::Perception, "Perception"),
+];
+
+/// Points handed out on the level-up screen, held back until the player confirms them
+pub struct LevelUpChoices {
+    pub attributes: [i32; 4],
+    pub skills: [i32; 5],
+}
+
+impl LevelUpChoices {
+    pub fn new() -> Self {
+        LevelUpChoices {
+            attributes: [0; 4],
+            skills: [0; 5],
+        }
+    }
+
+    pub fn attributes_left(&self, points: &UnspentPoints) -> i32 {
+        points.attributes - self.attributes.iter().sum::<i32>()
+    }
+
+    pub fn skills_left(&self, points: &UnspentPoints) -> i32 {
+        points.skills - self.skills.iter().sum::<i32>()
+    }
+}
+
+/// The player's attributes as they would be with the pending choices applied
+pub fn preview_attributes(attributes: &Attributes, choices: &LevelUpChoices) -> Attributes {
+    let mut preview = *attributes;
+    let mut targets = [
+        &mut preview.might,
+        &mut preview.fitness,
+        &mut preview.quickness,
+        &mut preview.intelligence,
+    ];
+    for (attribute, added) in targets.iter_mut().zip(choices.attributes.iter()) {
+        attribute.base += added;
+        attribute.bonus = attr_bonus(attribute.base + attribute.modifiers);
+    }
+    preview
+}
+
+/// The player's skills as they would be with the pending choices applied
+pub fn preview_skills(skills: &Skills, choices: &LevelUpChoices) -> Skills {
+    let mut preview = skills.clone();
+    for ((skill, _name), added) in SKILLS.iter().zip(choices.skills.iter()) {
+        if *added > 0 {
+            *preview.skills.entry(*skill).or_insert(0) += added;
+        }
+    }
+    preview
+}
+
+/// Health and mana at the player's level for the given attributes
+pub fn preview_pools(attributes: &Attributes, level: i32) -> (i32, i32) {
+    (
+        player_hp_at_level(
+            attributes.fitness.base + attributes.fitness.modifiers,
+            level,
+        ),
+        mana_at_level(
+            attributes.intelligence.base + attributes.intelligence.modifiers,
+            level,
+        ),
+    )
+}
+
+/// Spends the chosen points on the player for good
+pub fn apply(world: &mut World, resources: &Resources) {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let mut choices = resources.get_mut::<LevelUpChoices>().unwrap();
+
+    let attributes = match world.get_component::<Attributes>(player_entity) {
+        Some(attributes) => preview_attributes(&attributes, &choices),
+        None => return,
+    };
+    if let Some(mut current) = world.get_component_mut::<Attributes>(player_entity) {
+        *current = attributes;
+    }
+
+    if let Some(mut skills) = world.get_component_mut::<Skills>(player_entity) {
+        let trained = preview_skills(&skills, &choices);
+        *skills = trained;
+    }
+
+    // Tougher or wiser characters get the difference straight away
+    if let Some(mut stats) = world.get_component_mut::<Pools>(player_entity) {
+        let (hit_points, mana) = preview_pools(&attributes, stats.level);
+        stats.hit_points.current += hit_points - stats.hit_points.max;
+        stats.hit_points.max = hit_points;
+        stats.mana.current += mana - stats.mana.max;
+        stats.mana.max = mana;
+    }
+
+    if let Some(mut points) = world.get_component_mut::<UnspentPoints>(player_entity) {
+        points.attributes -= choices.attributes.iter().sum::<i32>();
+        points.skills -= choices.skills.iter().sum::<i32>();
+    }
+    *choices = LevelUpChoices::new();
+
+    resources
+        .get_mut::<GameLog>()
+        .unwrap()
+        .entries
+        .push("You feel more capable than before.".to_string());
+}
+
+/// Whether the player has yet to take a perk and meets everything it asks for
+pub fn perk_available(world: &World, player: Entity, perk: &Perk) -> bool {
+    let taken = match world.get_component::<Perks>(player) {
+        Some(perks) => perks.taken.clone(),
+        None => return false,
+    };
+    if taken.contains(&perk.name) {
+        return false;
+    }
+
+    let requires = match &perk.requires {
+        Some(requires) => requires,
+        None => return true,
+    };
+    if let Some(level) = requires.level {
+        match world.get_component::<Pools>(player) {
+            Some(stats) if stats.level >= level => {}
+            _ => return false,
+        }
+    }
+    if let Some(attributes) = &requires.attributes {
+        let current = match world.get_component::<Attributes>(player) {
+            Some(attributes) => *attributes,
+            None => return false,
+        };
+        let meets =
+            attributes
+                .iter()
+                .all(|(name, at_least)| match attribute_score(&current, name) {
+                    Some(score) => score >= *at_least,
+                    None => false,
+                });
+        if !meets {
+            return false;
+        }
+    }
+    requires
+        .perks
+        .iter()
+        .flatten()
+        .all(|required| taken.contains(required))
+}
+
+/// A short summary of what a perk asks for, e.g. "Level 4, Might 13"
+pub fn describe_requirements(perk: &Perk) -> String {
+    let requires = match &perk.requires {
+        Some(requires) => requires,
+        None => return "None".to_string(),
+    };
+    let mut needs = Vec::new();
+    if let Some(level) = requires.level {
+        needs.push(format!("Level {}", level));
+    }
+    for (name, at_least) in requires.attributes.iter().flatten() {
+        needs.push(format!("{} {}", name, at_least));
+    }
+    needs.extend(requires.perks.iter().flatten().cloned());
+    needs.join(", ")
+}
+
+/// The state the player must visit before play carries on, if they have points to spend
+pub fn pending(world: &World, player: Entity) -> Option<RunState> {
+    let points = *world.get_component::<UnspentPoints>(player)?;
+    if points.attributes > 0 || points.skills > 0 {
+        return Some(RunState::LevelUp { selection: 0 });
+    }
+
+    // Perk points are banked until there's something to spend them on
+    if points.perks > 0 {
+        let pm = PREFABS.lock().unwrap();
+        if perks(&pm)
+            .iter()
+            .any(|perk| perk_available(world, player, perk))
+        {
+            return Some(RunState::ShowPerks { selection: 0 });
+        }
+    }
+    None
+}
+
+/// Totals the bonuses from every perk taken
+fn perk_modifiers(taken: &[String], pm: &PrefabMaster) -> Modifiers {
+    let mut modifiers = Modifiers::new();
+    for effect in taken
+        .iter()
+        .filter_map(|name| get_perk(pm, name))
+        .flat_map(|perk| perk.effects.iter())
+    {
+        match effect {
+            PerkEffect::MeleeHit { category, bonus } => {
+                if let Some(category) = string_to_weapon_category(category) {
+                    *modifiers.melee_hit.entry(category).or_insert(0) += bonus;
+                }
+            }
+            PerkEffect::KeepScroll(chance) => modifiers.keep_scroll_chance += chance,
+            PerkEffect::SlowerHunger(percent) => modifiers.hunger_duration += percent,
+            PerkEffect::LightRadius(radius) => modifiers.light_radius += radius,
+            PerkEffect::Reaction { faction, reaction } => {
+                modifiers
+                    .reactions
+                    .insert(faction.clone(), parse_reaction(reaction));
+            }
+        }
+    }
+    modifiers
+}
+
+/// Spends a perk point on the named perk
+pub fn take_perk(world: &mut World, resources: &Resources, name: &str) {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let pm = PREFABS.lock().unwrap();
+    let perk = match get_perk(&pm, name) {
+        Some(perk) => perk,
+        None => return,
+    };
+    if !perk_available(world, player_entity, perk) {
+        return;
+    }
+
+    let taken = match world.get_component_mut::<Perks>(player_entity) {
+        Some(mut perks) => {
+            perks.taken.push(perk.name.clone());
+            perks.taken.clone()
+        }
+        None => return,
+    };
+    if let Some(mut modifiers) = world.get_component_mut::<Modifiers>(player_entity) {
+        *modifiers = perk_modifiers(&taken, &pm);
+    }
+
+    if let Some(mut points) = world.get_component_mut::<UnspentPoints>(player_entity) {
+        points.perks -= 1;
+    }
+
+    resources
+        .get_mut::<GameLog>()
+        .unwrap()
+        .entries
+        .push(format!("You have learned {}.", perk.name));
+}
@@ -0,0 +1,94 @@
+use super::{
+    clock_system::GameClock, field_of_view, Equipped, InBackpack, LightSource, Map, Modifiers,
+    Position, Viewshed,
+};
+use legion::prelude::*;
+use rltk::{DistanceAlg, Point, RGB};
+
+// Tiles dimmer than this can only be seen up close or with darkvision
+const LIGHT_THRESHOLD: f32 = 0.2;
+
+pub fn ambient_light(map: &Map, clock: &GameClock) -> f32 {
+    if map.outdoors {
+        clock.daylight()
+    } else {
+        // The deeper you go, the darker it gets
+        f32::max(0.0, 0.4 - 0.05 * map.depth as f32)
+    }
+}
+
+pub fn is_lit(map: &Map, idx: usize) -> bool {
+    bright_enough(map.light[idx])
+}
+
+fn bright_enough(light: RGB) -> bool {
+    (light.r + light.g + light.b) / 3.0 >= LIGHT_THRESHOLD
+}
+
+pub fn build() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("lighting")
+        .write_resource::<Map>()
+        .read_resource::<GameClock>()
+        .with_query(Read::<LightSource>::query())
+        .with_query(<(Write<Viewshed>, Read<Position>)>::query())
+        .read_component::<Position>()
+        .read_component::<Equipped>()
+        .read_component::<InBackpack>()
+        .read_component::<Modifiers>()
+        .build(|_, world, (map, clock), (light_query, viewshed_query)| {
+            let ambient = ambient_light(map, clock);
+            let mut light = vec![RGB::from_f32(ambient, ambient, ambient); map.tiles.len()];
+
+            for (entity, source) in light_query.iter_entities(world) {
+                // Carried lights shine from whoever carries them
+                let holder = if let Some(equipped) = world.get_component::<Equipped>(entity) {
+                    equipped.owner
+                } else if let Some(carried) = world.get_component::<InBackpack>(entity) {
+                    carried.owner
+                } else {
+                    entity
+                };
+
+                let reach = match world.get_component::<Modifiers>(holder) {
+                    Some(modifiers) => source.range + modifiers.light_radius,
+                    None => source.range,
+                };
+
+                if let Some(pos) = world.get_component::<Position>(holder) {
+                    let origin = Point::new(pos.x, pos.y);
+                    let range = reach as f32;
+                    for tile in field_of_view(origin, reach as usize, &**map).iter() {
+                        let idx = map.xy_idx(tile.x, tile.y);
+                        let distance = DistanceAlg::Pythagoras.distance2d(origin, *tile);
+                        let intensity = f32::max(0.0, 1.0 - distance / range);
+                        light[idx] = RGB::from_f32(
+                            f32::min(1.0, light[idx].r + source.color.r * intensity),
+                            f32::min(1.0, light[idx].g + source.color.g * intensity),
+                            f32::min(1.0, light[idx].b + source.color.b * intensity),
+                        );
+                    }
+                }
+            }
+
+            // Only tiles that went light or dark change what can be seen
+            let changed: Vec<Point> = (0..light.len())
+                .filter(|idx| is_lit(map, *idx) != bright_enough(light[*idx]))
+                .map(|idx| Point::new(idx as i32 % map.width, idx as i32 / map.width))
+                .collect();
+            map.light = light;
+
+            // Anyone who could see one of those tiles has to look again
+            if !changed.is_empty() {
+                for (mut viewshed, pos) in viewshed_query.iter_mut(world) {
+                    let origin = Point::new(pos.x, pos.y);
+                    let range = viewshed.range as f32;
+                    if changed
+                        .iter()
+                        .any(|tile| DistanceAlg::Pythagoras.distance2d(origin, *tile) <= range)
+                    {
+                        viewshed.dirty = true;
+                    }
+                }
+            }
+        })
+}
@@ -1,30 +1,47 @@
 use legion::prelude::*;
 use rltk::{GameState, Point, RandomNumberGenerator, Rltk, RltkBuilder};
+use std::collections::HashSet;
 
 mod animal_ai_system;
 mod bystander_ai_system;
 mod camera;
+mod clock_system;
 mod components;
 mod damage_system;
+mod dialogue;
+mod effects;
+mod flow_field_system;
 mod game_system;
 mod gamelog;
+mod gas_system;
 mod gui;
+mod hall_of_fame;
 mod hunger_system;
 mod inventory_system;
+mod level_up;
+mod lighting_system;
 mod map;
 mod map_builders;
 mod map_indexing_system;
 mod melee_combat_system;
 mod monster_ai_system;
+mod morgue;
+mod noise_system;
 mod particle_system;
 mod player;
 mod prefabs;
+mod quest_system;
 mod random_table;
+mod ranged_combat_system;
 mod rect;
 mod rex_assets;
 mod saveload_system;
+mod skill_system;
 mod spawner;
+mod spell_system;
+mod storage;
 mod trigger_system;
+mod vendor;
 mod visibility_system;
 pub use components::*;
 pub use game_system::*;
@@ -49,16 +66,50 @@ pub enum RunState {
         range: i32,
         item: Entity,
     },
+    ShowSpells,
+    ShowSpellTargeting {
+        range: i32,
+        spell: usize,
+    },
+    ShowContainer {
+        container: Entity,
+        mode: gui::ContainerMode,
+    },
+    ShowVendor {
+        vendor: Entity,
+        mode: gui::VendorMode,
+    },
     MainMenu {
         menu_selection: gui::MainMenuSelection,
     },
+    ShowHallOfFame,
+    CharacterCreation {
+        selection: usize,
+    },
+    LevelUp {
+        selection: usize,
+    },
+    ShowPerks {
+        selection: usize,
+    },
     SaveGame,
     NextLevel,
+    PreviousLevel,
     GameOver,
+    Victory,
     MagicMapReveal {
         row: i32,
     },
     MapGeneration,
+    ShowQuestOffer {
+        giver: Entity,
+    },
+    ShowQuestJournal,
+    ShowSkills,
+    ShowDialogue {
+        speaker: Entity,
+        node: usize,
+    },
 }
 
 pub struct State {
@@ -89,7 +140,13 @@ impl GameState for State {
         particle_system::cull_dead_particles(&mut self.world, ctx);
 
         match runstate {
-            RunState::MainMenu { .. } | RunState::GameOver => {}
+            RunState::MainMenu { .. }
+            | RunState::CharacterCreation { .. }
+            | RunState::ShowHallOfFame
+            | RunState::GameOver
+            | RunState::Victory => {
+                // Full screen menus, with no map behind them
+            }
             _ => {
                 camera::render_camera(&self.world, &self.resources, ctx);
                 gui::draw_ui(&self.world, &self.resources, ctx);
@@ -142,6 +199,15 @@ impl GameState for State {
             RunState::WorldTurn => {
                 self.run_systems();
                 runstate = RunState::AwaitingInput;
+
+                // Points from going up a level are spent before play carries on, while a perk
+                // put off from the perk screen can wait until the player asks for it again
+                let player_entity = *self.resources.get::<Entity>().unwrap();
+                if let Some(pending @ RunState::LevelUp { .. }) =
+                    level_up::pending(&self.world, player_entity)
+                {
+                    runstate = pending;
+                }
             }
 
             RunState::ShowInventory => {
@@ -214,6 +280,187 @@ impl GameState for State {
                     }
                 }
             }
+            RunState::ShowSpells => {
+                let (result, spell) = gui::show_spells(self, ctx);
+                match result {
+                    gui::ItemMenuResult::Cancel => {
+                        runstate = RunState::AwaitingInput;
+                    }
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        runstate = spell_system::begin_casting(
+                            &mut self.world,
+                            &self.resources,
+                            spell.unwrap(),
+                        );
+                    }
+                }
+            }
+            RunState::ShowSpellTargeting { range, spell } => {
+                let (result, target) = gui::ranged_target(self, ctx, range);
+                match result {
+                    gui::ItemMenuResult::Cancel => {
+                        runstate = RunState::AwaitingInput;
+                    }
+                    gui::ItemMenuResult::NoResponse => {}
+                    gui::ItemMenuResult::Selected => {
+                        runstate = spell_system::cast_spell(
+                            &mut self.world,
+                            &self.resources,
+                            spell,
+                            target,
+                        );
+                    }
+                }
+            }
+
+            RunState::ShowContainer { container, mode } => {
+                let (result, item) = gui::show_container(self, ctx, container, mode);
+                match result {
+                    gui::ContainerMenuResult::Cancel => {
+                        runstate = RunState::AwaitingInput;
+                    }
+                    gui::ContainerMenuResult::NoResponse => {}
+                    gui::ContainerMenuResult::SwitchMode => {
+                        runstate = RunState::ShowContainer {
+                            container,
+                            mode: match mode {
+                                gui::ContainerMode::Take => gui::ContainerMode::Put,
+                                gui::ContainerMode::Put => gui::ContainerMode::Take,
+                            },
+                        };
+                    }
+                    gui::ContainerMenuResult::TakeAll => {
+                        self.world
+                            .add_component(
+                                *self.resources.get::<Entity>().unwrap(),
+                                WantsToTakeItem {
+                                    container,
+                                    item: None,
+                                },
+                            )
+                            .expect("Unable to insert intent");
+                        runstate = RunState::PlayerTurn;
+                    }
+                    gui::ContainerMenuResult::Selected => {
+                        let item = item.unwrap();
+                        let player_entity = *self.resources.get::<Entity>().unwrap();
+                        match mode {
+                            gui::ContainerMode::Take => self.world.add_component(
+                                player_entity,
+                                WantsToTakeItem {
+                                    container,
+                                    item: Some(item),
+                                },
+                            ),
+                            gui::ContainerMode::Put => self
+                                .world
+                                .add_component(player_entity, WantsToPutItem { container, item }),
+                        }
+                        .expect("Unable to insert intent");
+                        runstate = RunState::PlayerTurn;
+                    }
+                }
+            }
+
+            RunState::ShowVendor { vendor, mode } => {
+                match gui::show_vendor(self, ctx, vendor, mode) {
+                    gui::VendorResult::Cancel => {
+                        runstate = RunState::AwaitingInput;
+                    }
+                    gui::VendorResult::NoResponse => {}
+                    gui::VendorResult::SwitchMode => {
+                        runstate = RunState::ShowVendor {
+                            vendor,
+                            mode: match mode {
+                                gui::VendorMode::Buy => gui::VendorMode::Sell,
+                                gui::VendorMode::Sell => gui::VendorMode::Buy,
+                            },
+                        };
+                    }
+                    gui::VendorResult::Buy { item, price } => {
+                        vendor::buy(&mut self.world, &self.resources, &item, price);
+                    }
+                    gui::VendorResult::Sell { item } => {
+                        vendor::sell(&mut self.world, &self.resources, item);
+                    }
+                }
+            }
+
+            RunState::ShowQuestOffer { giver } => {
+                let (result, quest) = gui::quest_offer(self, ctx, giver);
+                match result {
+                    gui::QuestOfferResult::NoResponse => {}
+                    gui::QuestOfferResult::Decline => {
+                        runstate = RunState::AwaitingInput;
+                    }
+                    gui::QuestOfferResult::Accept => {
+                        quest_system::accept_quest(
+                            &mut self.world,
+                            &self.resources,
+                            &quest.unwrap(),
+                        );
+                        runstate = RunState::PlayerTurn;
+                    }
+                }
+            }
+            RunState::ShowQuestJournal => {
+                if gui::show_quest_journal(self, ctx) == gui::ItemMenuResult::Cancel {
+                    runstate = RunState::AwaitingInput;
+                }
+            }
+            RunState::ShowSkills => {
+                if gui::show_skills(self, ctx) == gui::ItemMenuResult::Cancel {
+                    runstate = RunState::AwaitingInput;
+                }
+            }
+            RunState::LevelUp { selection } => {
+                let result = gui::level_up(self, ctx, selection);
+                match result {
+                    gui::LevelUpResult::NoSelection { selected } => {
+                        runstate = RunState::LevelUp {
+                            selection: selected,
+                        }
+                    }
+                    gui::LevelUpResult::Confirm => {
+                        level_up::apply(&mut self.world, &self.resources);
+                        let player_entity = *self.resources.get::<Entity>().unwrap();
+                        runstate = level_up::pending(&self.world, player_entity)
+                            .unwrap_or(RunState::AwaitingInput);
+                    }
+                }
+            }
+            RunState::ShowPerks { selection } => match gui::show_perks(self, ctx, selection) {
+                gui::PerkResult::Cancel => runstate = RunState::AwaitingInput,
+                gui::PerkResult::NoSelection { selected } => {
+                    runstate = RunState::ShowPerks {
+                        selection: selected,
+                    }
+                }
+                gui::PerkResult::Selected { perk } => {
+                    level_up::take_perk(&mut self.world, &self.resources, &perk);
+                    let player_entity = *self.resources.get::<Entity>().unwrap();
+                    runstate = level_up::pending(&self.world, player_entity)
+                        .unwrap_or(RunState::AwaitingInput);
+                }
+            },
+            RunState::ShowDialogue { speaker, node } => {
+                match gui::show_dialogue(self, ctx, speaker, node) {
+                    gui::DialogueResult::NoResponse => {}
+                    gui::DialogueResult::Leave => {
+                        runstate = RunState::AwaitingInput;
+                    }
+                    gui::DialogueResult::Selected { choice } => {
+                        runstate = dialogue::choose(
+                            &mut self.world,
+                            &self.resources,
+                            speaker,
+                            node,
+                            choice,
+                        );
+                    }
+                }
+            }
 
             RunState::MainMenu { .. } => {
                 let result = gui::main_menu(self, ctx);
@@ -224,12 +471,21 @@ impl GameState for State {
                         }
                     }
                     gui::MainMenuResult::Selected { selected } => match selected {
-                        gui::MainMenuSelection::NewGame => runstate = RunState::PreRun,
+                        gui::MainMenuSelection::NewGame => {
+                            self.resources.insert(spawner::CharacterSheet::new());
+                            runstate = RunState::CharacterCreation { selection: 0 };
+                        }
                         gui::MainMenuSelection::LoadGame => {
-                            saveload_system::load_game(&mut self.world);
+                            saveload_system::load_game(&mut self.world, &mut self.resources);
                             runstate = RunState::PreRun;
                             saveload_system::delete_save();
                         }
+                        gui::MainMenuSelection::HallOfFame => {
+                            self.resources.insert(hall_of_fame::HallOfFame {
+                                scores: hall_of_fame::load(),
+                            });
+                            runstate = RunState::ShowHallOfFame;
+                        }
                         gui::MainMenuSelection::Quit => {
                             std::process::exit(0);
                         }
@@ -237,12 +493,53 @@ impl GameState for State {
                 }
             }
 
+            RunState::ShowHallOfFame => {
+                if gui::hall_of_fame(self, ctx) == gui::ItemMenuResult::Cancel {
+                    runstate = RunState::MainMenu {
+                        menu_selection: gui::MainMenuSelection::HallOfFame,
+                    };
+                }
+            }
+
+            RunState::CharacterCreation { selection } => {
+                let result = gui::character_creation(self, ctx, selection);
+                match result {
+                    gui::CharacterCreationResult::NoSelection { selected } => {
+                        runstate = RunState::CharacterCreation {
+                            selection: selected,
+                        }
+                    }
+                    gui::CharacterCreationResult::Cancel => {
+                        runstate = RunState::MainMenu {
+                            menu_selection: gui::MainMenuSelection::NewGame,
+                        }
+                    }
+                    gui::CharacterCreationResult::Begin => {
+                        self.new_game();
+                        runstate = RunState::PreRun;
+                    }
+                }
+            }
+
+            RunState::Victory => {
+                let result = gui::victory(self, ctx);
+                match result {
+                    gui::GameOverResult::NoSelection => {}
+                    gui::GameOverResult::QuitToMenu => {
+                        self.quit_to_menu();
+                        runstate = RunState::MainMenu {
+                            menu_selection: gui::MainMenuSelection::NewGame,
+                        };
+                    }
+                }
+            }
+
             RunState::GameOver => {
-                let result = gui::game_over(ctx);
+                let result = gui::game_over(self, ctx);
                 match result {
                     gui::GameOverResult::NoSelection => {}
                     gui::GameOverResult::QuitToMenu => {
-                        self.game_over_cleanup();
+                        self.quit_to_menu();
                         runstate = RunState::MainMenu {
                             menu_selection: gui::MainMenuSelection::NewGame,
                         };
@@ -251,7 +548,11 @@ impl GameState for State {
             }
 
             RunState::SaveGame => {
-                saveload_system::save_game(&mut self.world, &*self.resources.get::<Map>().unwrap());
+                saveload_system::save_game(
+                    &mut self.world,
+                    &*self.resources.get::<Map>().unwrap(),
+                    &*self.resources.get::<clock_system::GameClock>().unwrap(),
+                );
                 runstate = RunState::MainMenu {
                     menu_selection: gui::MainMenuSelection::LoadGame,
                 };
@@ -262,6 +563,22 @@ impl GameState for State {
                 runstate = RunState::PreRun;
             }
 
+            RunState::PreviousLevel => {
+                self.goto_previous_level();
+                runstate = RunState::PreRun;
+                if self.player_has_won() {
+                    let player_entity = *self.resources.get::<Entity>().unwrap();
+                    if let Some(mut run_stats) =
+                        self.world.get_component_mut::<RunStats>(player_entity)
+                    {
+                        run_stats.victory = true;
+                    }
+                    morgue::write_morgue_file(&self.world, &self.resources);
+                    hall_of_fame::record(&self.world, &self.resources);
+                    runstate = RunState::Victory;
+                }
+            }
+
             RunState::MagicMapReveal { row } => {
                 let mut map = self.resources.get_mut::<Map>().unwrap();
                 for x in 0..map.width {
@@ -300,6 +617,13 @@ impl State {
                     return false;
                 }
 
+                // Don't delete the contents of carried bags
+                if let Some(stored) = self.world.get_component::<InContainer>(*entity) {
+                    if self.world.has_component::<InBackpack>(stored.owner) {
+                        return false;
+                    }
+                }
+
                 // To Hades with it!
                 true
             })
@@ -315,9 +639,16 @@ impl State {
         // Build a new map and Place the player and update resources
         let current_map = self.resources.remove::<Map>().unwrap();
         self.generate_world_map(current_map.depth + 1);
+        self.resources
+            .get_mut::<quest_system::QuestEvents>()
+            .unwrap()
+            .record(quest_system::QuestEvent::Depth(current_map.depth + 1));
 
         // Notify the player and give one some health
         let player_entity = self.resources.get::<Entity>().unwrap();
+        if let Some(mut run_stats) = self.world.get_component_mut::<RunStats>(*player_entity) {
+            run_stats.max_depth = i32::max(run_stats.max_depth, current_map.depth + 1);
+        }
         let mut gamelog = self.resources.get_mut::<gamelog::GameLog>().unwrap();
         gamelog
             .entries
@@ -327,7 +658,61 @@ impl State {
         }
     }
 
-    fn game_over_cleanup(&mut self) {
+    fn goto_previous_level(&mut self) {
+        // Delete entities that aren't the player or his/her equipment
+        for target in self.entities_to_remove_on_level_change() {
+            self.world.delete(target);
+        }
+
+        // Levels aren't kept, so the one above is built afresh
+        let current_map = self.resources.remove::<Map>().unwrap();
+        self.generate_world_map(current_map.depth - 1);
+
+        // Arrive at the foot of the stairs we came up, rather than the usual start
+        let player_entity = *self.resources.get::<Entity>().unwrap();
+        let stairs = {
+            let map = self.resources.get::<Map>().unwrap();
+            map.tiles
+                .iter()
+                .position(|tile| *tile == TileType::DownStairs)
+                .map(|idx| Point::new(idx as i32 % map.width, idx as i32 / map.width))
+        };
+        if let Some(stairs) = stairs {
+            self.resources.insert(stairs);
+            if let Some(mut player_pos) = self.world.get_component_mut::<Position>(player_entity) {
+                player_pos.x = stairs.x;
+                player_pos.y = stairs.y;
+            }
+        }
+
+        self.resources
+            .get_mut::<gamelog::GameLog>()
+            .unwrap()
+            .entries
+            .push("You climb back up to the previous level.".to_string());
+    }
+
+    /// Whether the player is back in town with the prize from the bottom of the dungeon
+    fn player_has_won(&self) -> bool {
+        let player_entity = *self.resources.get::<Entity>().unwrap();
+        let in_town = self
+            .resources
+            .get::<Map>()
+            .map_or(false, |map| map.depth == 1);
+        in_town
+            && Read::<InBackpack>::query()
+                .filter(tag::<MacGuffin>())
+                .iter(&self.world)
+                .any(|backpack| backpack.owner == player_entity)
+    }
+
+    /// Leaves a finished run behind, so nothing from it carries into the next character
+    fn quit_to_menu(&mut self) {
+        self.resources.insert(spawner::CharacterSheet::new());
+        self.new_game();
+    }
+
+    fn new_game(&mut self) {
         // Delete everything
         self.world.delete_all();
 
@@ -335,11 +720,27 @@ impl State {
         {
             let mut log = self.resources.get_mut::<gamelog::GameLog>().unwrap();
             log.entries.clear();
+            log.entries.push("Welcome to Rusty Roguelike".to_string());
         }
 
-        // Spawn a new player
-        self.resources
-            .insert(spawner::player(&mut self.world, 0, 0));
+        // Start again on the first morning
+        self.resources.insert(clock_system::GameClock::new());
+
+        // Each run gets its own seed, kept so it can be shared afterwards
+        let seed = RandomNumberGenerator::new().rand::<u64>();
+        self.resources.insert(RandomNumberGenerator::seeded(seed));
+
+        // Spawn a new player from the character sheet
+        let player = spawner::player(
+            &mut self.world,
+            0,
+            0,
+            &self.resources.get::<spawner::CharacterSheet>().unwrap(),
+        );
+        if let Some(mut run_stats) = self.world.get_component_mut::<RunStats>(player) {
+            run_stats.seed = seed;
+        }
+        self.resources.insert(player);
 
         // Build a new map and place the player
         self.generate_world_map(1);
@@ -354,7 +755,57 @@ impl State {
         let mut rng = self.resources.get_mut::<RandomNumberGenerator>().unwrap();
         let mut builder = map_builders::level_builder(depth, 80, 50, "New Map", &mut rng);
         builder.build_map(&mut rng);
+
+        // Hide anything the player has been sent to find on this level
+        let player_entity = *self.resources.get::<Entity>().unwrap();
+        let start_idx = builder
+            .build_data
+            .starting_position
+            .map(|start| builder.build_data.map.xy_idx(start.x, start.y));
+        for item in quest_system::quest_items_for_depth(&self.world, player_entity, depth) {
+            let map = &builder.build_data.map;
+            let free_tiles: Vec<usize> = (0..map.tiles.len())
+                .filter(|idx| tile_walkable(map.tiles[*idx]) && Some(*idx) != start_idx)
+                .filter(|idx| {
+                    !builder
+                        .build_data
+                        .spawn_list
+                        .iter()
+                        .any(|(at, _)| at == idx)
+                })
+                .collect();
+            if !free_tiles.is_empty() {
+                let idx = free_tiles[(rng.roll_dice(1, free_tiles.len() as i32) - 1) as usize];
+                builder.build_data.spawn_list.push((idx, item));
+            }
+        }
         std::mem::drop(rng); // do not borrow self anymore
+
+        // Levels are rebuilt on every visit, so a boss only returns if it's still alive and a
+        // prize only if it isn't already out in the world
+        {
+            let pm = prefabs::PREFABS.lock().unwrap();
+            let kills = self
+                .world
+                .get_component::<RunStats>(player_entity)
+                .map(|stats| stats.kills.clone())
+                .unwrap_or_default();
+            let existing: HashSet<String> = Read::<Name>::query()
+                .iter(&self.world)
+                .map(|name| name.name.clone())
+                .collect();
+            builder.build_data.spawn_list.retain(|(_idx, name)| {
+                !prefabs::is_unique(&pm, name)
+                    || !(kills.contains_key(name) || existing.contains(name))
+            });
+        }
+
+        // Every level below the town has a way back up where the player arrives
+        if depth > 1 {
+            if let Some(idx) = start_idx {
+                builder.build_data.map.tiles[idx] = TileType::UpStairs;
+            }
+        }
         self.mapgen_history = builder.build_data.history.clone();
         let map = builder.build_data.map.clone();
         self.resources.insert(map);
@@ -392,7 +843,15 @@ fn main() -> rltk::BError {
 
     resources.insert(RandomNumberGenerator::new());
     resources.insert(particle_system::ParticleBuilder::new());
+    resources.insert(noise_system::NoiseBuilder::new());
+    resources.insert(FlowField::new());
+    resources.insert(clock_system::GameClock::new());
+    resources.insert(quest_system::QuestEvents::new());
+    resources.insert(skill_system::SkillUses::new());
+    resources.insert(effects::EffectQueue::new());
+    resources.insert(level_up::LevelUpChoices::new());
     resources.insert(rex_assets::RexAssets::new());
+    resources.insert(hall_of_fame::HallOfFame { scores: Vec::new() });
 
     resources.insert(RunState::MapGeneration {});
     resources.insert(gamelog::GameLog {
@@ -401,28 +860,44 @@ fn main() -> rltk::BError {
 
     // Insert placeholder values for "Start Game" map generator
     resources.insert(Point::new(0, 0));
-    resources.insert(spawner::player(&mut world, 0, 0));
+    let sheet = spawner::CharacterSheet::new();
+    resources.insert(spawner::player(&mut world, 0, 0, &sheet));
+    resources.insert(sheet);
     resources.insert(Map::new(1, 64, 64, "New Map"));
 
     let schedules = vec![
         Schedule::builder()
+            .add_system(lighting_system::build()) // Computes Map light levels from LightSource
             .add_system(visibility_system::build())
             .build(),
         Schedule::builder()
+            .add_system(flow_field_system::build()) // Maps the way to the player for hostile mobs
             .add_system(animal_ai_system::build())
             .add_system(bystander_ai_system::build())
             .add_system(monster_ai_system::build())
             .add_system(melee_combat_system::build()) // Creates SufferDamage out of WantsToMelee
+            .add_system(ranged_combat_system::build()) // Applies WantsToUseAbility to its target
             .add_system(damage_system::build()) // Turns SufferDamage to HP reduction
             .add_system(inventory_system::build()) // Turns WantsToPickupItem into InBackpack
             .add_system(inventory_system::item_drop()) // Turns WantsToDropItem into Position
             .add_system(inventory_system::item_remove()) // Turns WantsToRemoveItem into InBackpack
+            .add_system(inventory_system::item_take()) // Turns WantsToTakeItem into InBackpack
+            .add_system(inventory_system::item_put()) // Turns WantsToPutItem into InContainer
             .add_system(inventory_system::item_use()) // Process WantsToUseItem
+            .add_thread_local_fn(effects::run_effects_queue()) // Applies queued effects in order
             .build(),
         Schedule::builder()
             .add_system(trigger_system::build())
+            .add_thread_local_fn(effects::run_effects_queue()) // Applies queued effects in order
+            .add_system(gas_system::build()) // Spreads and fades gas clouds
+            .add_system(gas_system::poison()) // Process Poisoned
+            .add_system(noise_system::build()) // Spreads NoiseRequests to listening monsters
             .add_system(map_indexing_system::build())
             .add_system(hunger_system::build()) // Process HungerClock
+            .add_system(clock_system::build()) // Advances the time of day
+            .add_system(spell_system::build()) // Regenerates mana
+            .add_system(quest_system::build()) // Advances quests from QuestEvents
+            .add_system(skill_system::build()) // Improves skills from SkillUses
             .add_thread_local_fn(particle_system::particle_spawn()) // Turns ParticleRequests into particle Entities
             .build(),
     ];
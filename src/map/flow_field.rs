@@ -0,0 +1,82 @@
+use super::Map;
+use rltk::{DijkstraMap, Point};
+
+// Deep enough to cover the largest map
+const MAX_DEPTH: f32 = 1000.0;
+
+/// Distances to the player shared by every hostile mob, so they don't each search for a path
+pub struct FlowField {
+    origin: Option<Point>,
+    obstacles: Vec<bool>,
+    distances: Vec<f32>,
+}
+
+impl FlowField {
+    pub fn new() -> Self {
+        FlowField {
+            origin: None,
+            obstacles: Vec::new(),
+            distances: Vec::new(),
+        }
+    }
+
+    /// Where the field flows to
+    pub fn origin(&self) -> Option<Point> {
+        self.origin
+    }
+
+    /// Forces a rebuild on the next refresh, such as when a door opens or closes
+    pub fn invalidate(&mut self) {
+        self.origin = None;
+    }
+
+    /// Rebuilds the field, unless neither the player nor the blocked layer has changed
+    pub fn refresh(&mut self, map: &mut Map, origin: Point, obstacles: Vec<bool>) {
+        if self.origin == Some(origin) && self.obstacles == obstacles {
+            return;
+        }
+
+        // Creatures move every turn, so they are stepped around rather than baked in
+        let blocked = std::mem::replace(&mut map.blocked, obstacles);
+        let idx = map.xy_idx(origin.x, origin.y);
+        let dijkstra = DijkstraMap::new(
+            map.width as usize,
+            map.height as usize,
+            &[idx],
+            &*map,
+            MAX_DEPTH,
+        );
+        self.obstacles = std::mem::replace(&mut map.blocked, blocked);
+        self.distances = dijkstra.map;
+        self.origin = Some(origin);
+    }
+
+    /// Picks the free neighbouring tile that is closest to the origin, if any gets us closer
+    pub fn next_step(&self, map: &Map, from: Point) -> Option<Point> {
+        if self.distances.len() != map.tiles.len() {
+            return None;
+        }
+
+        let mut best = None;
+        let mut current = self.distances[map.xy_idx(from.x, from.y)];
+        for dy in -1..=1 {
+            for dx in -1..=1 {
+                let step = Point::new(from.x + dx, from.y + dy);
+                if (dx == 0 && dy == 0)
+                    || step.x < 0
+                    || step.x >= map.width
+                    || step.y < 0
+                    || step.y >= map.height
+                {
+                    continue;
+                }
+                let idx = map.xy_idx(step.x, step.y);
+                if !map.blocked[idx] && self.distances[idx] < current {
+                    current = self.distances[idx];
+                    best = Some(step);
+                }
+            }
+        }
+        best
+    }
+}
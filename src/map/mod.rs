@@ -1,5 +1,5 @@
 use legion::prelude::*;
-use rltk::{Algorithm2D, BaseMap, Point, SmallVec};
+use rltk::{Algorithm2D, BaseMap, Point, SmallVec, RGB};
 use serde::{Deserialize, Serialize};
 use std::collections::HashSet;
 use type_uuid::TypeUuid;
@@ -10,6 +10,8 @@ mod fov;
 pub use fov::field_of_view;
 mod astar;
 pub use astar::a_star_search;
+mod flow_field;
+pub use flow_field::FlowField;
 mod themes;
 pub use themes::tile_glyph;
 pub mod dungeon;
@@ -26,6 +28,8 @@ pub struct Map {
     pub depth: i32,
     pub bloodstains: HashSet<usize>,
     pub view_blocked: HashSet<usize>,
+    pub light: Vec<RGB>,
+    pub outdoors: bool,
     pub name: String,
 
     #[serde(skip)]
@@ -72,6 +76,8 @@ impl Map {
             depth,
             bloodstains: HashSet::new(),
             view_blocked: HashSet::new(),
+            light: vec![RGB::from_f32(0.0, 0.0, 0.0); map_tile_count],
+            outdoors: false,
             name: name.to_string(),
         }
     }
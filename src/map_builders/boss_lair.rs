@@ -0,0 +1,16 @@
+use super::{
+    prefab_builder::{prefab_levels, PrefabBuilder},
+    BuilderChain,
+};
+use rltk::RandomNumberGenerator;
+
+pub fn boss_lair_builder(
+    depth: i32,
+    width: i32,
+    height: i32,
+    _rng: &mut RandomNumberGenerator,
+) -> BuilderChain {
+    let mut chain = BuilderChain::new(depth, width, height, "The Hollow King's Lair");
+    chain.start_with(PrefabBuilder::constant(prefab_levels::BOSS_LAIR));
+    chain
+}
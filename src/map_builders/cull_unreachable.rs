@@ -1,5 +1,5 @@
 use super::{BuilderMap, MetaMapBuilder, TileType};
-use rltk::RandomNumberGenerator;
+use rltk::{DijkstraMap, RandomNumberGenerator};
 
 pub struct CullUnreachable {}
 
@@ -17,17 +17,7 @@ impl CullUnreachable {
 
     fn build(&mut self, _rng: &mut RandomNumberGenerator, build_data: &mut BuilderMap) {
         // Find all the tiles we can reach from the starting point
-        let starting_pos = build_data.starting_position.as_ref().unwrap();
-        let start_idx = build_data.map.xy_idx(starting_pos.x, starting_pos.y);
-        build_data.map.populate_blocked();
-        let map_starts: Vec<usize> = vec![start_idx];
-        let dijkstra_map = rltk::DijkstraMap::new(
-            build_data.map.width,
-            build_data.map.height,
-            &map_starts,
-            &build_data.map,
-            1000.0,
-        );
+        let dijkstra_map = reachable_from_start(build_data, &[]);
         for (i, tile) in build_data.map.tiles.iter_mut().enumerate() {
             if *tile == TileType::Floor {
                 let distance_to_start = dijkstra_map.map[i];
@@ -39,3 +29,24 @@ impl CullUnreachable {
         }
     }
 }
+
+/// Flood fills the map from the starting position, treating `extra_blocked` as walls.
+/// Unreachable tiles have a distance of `std::f32::MAX`.
+pub fn reachable_from_start(build_data: &mut BuilderMap, extra_blocked: &[usize]) -> DijkstraMap {
+    let starting_pos = build_data.starting_position.as_ref().unwrap();
+    let start_idx = build_data.map.xy_idx(starting_pos.x, starting_pos.y);
+    build_data.map.populate_blocked();
+    for idx in extra_blocked.iter() {
+        build_data.map.blocked[*idx] = true;
+    }
+    let map_starts: Vec<usize> = vec![start_idx];
+    let dijkstra_map = DijkstraMap::new(
+        build_data.map.width,
+        build_data.map.height,
+        &map_starts,
+        &build_data.map,
+        1000.0,
+    );
+    build_data.map.populate_blocked();
+    dijkstra_map
+}
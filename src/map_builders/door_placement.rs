@@ -1,5 +1,6 @@
-use super::{BuilderMap, MetaMapBuilder, TileType};
-use rltk::RandomNumberGenerator;
+use super::{reachable_from_start, BuilderMap, MetaMapBuilder, TileType};
+use rltk::{DijkstraMap, RandomNumberGenerator};
+use std::collections::HashSet;
 
 pub struct DoorPlacement {}
 
@@ -7,6 +8,7 @@ impl MetaMapBuilder for DoorPlacement {
     #[allow(dead_code)]
     fn build_map(&mut self, rng: &mut rltk::RandomNumberGenerator, build_data: &mut BuilderMap) {
         self.doors(rng, build_data);
+        place_key(rng, build_data);
     }
 }
 
@@ -17,13 +19,14 @@ impl DoorPlacement {
     }
 
     fn doors(&mut self, rng: &mut RandomNumberGenerator, build_data: &mut BuilderMap) {
+        let exit_route = exit_route(build_data);
         if let Some(halls_original) = &build_data.corridors {
             let halls = halls_original.clone(); // To avoid nested borrowing
             for hall in halls.iter() {
                 // We aren't interested in tiny corridors
                 if hall.len() > 2 {
                     if self.door_possible(build_data, hall[0]) {
-                        build_data.spawn_list.push((hall[0], "Door".to_string()));
+                        self.place_door(rng, build_data, &exit_route, hall[0]);
                     }
                 }
             }
@@ -35,12 +38,35 @@ impl DoorPlacement {
                     && self.door_possible(build_data, i)
                     && rng.roll_dice(1, 3) == 1
                 {
-                    build_data.spawn_list.push((i, "Door".to_string()));
+                    self.place_door(rng, build_data, &exit_route, i);
                 }
             }
         }
     }
 
+    fn place_door(
+        &mut self,
+        rng: &mut RandomNumberGenerator,
+        build_data: &mut BuilderMap,
+        exit_route: &Option<HashSet<usize>>,
+        idx: usize,
+    ) {
+        // Hiding the door must not cut the player off from the exit
+        let can_hide = exit_route
+            .as_ref()
+            .map_or(false, |route| !route.contains(&idx));
+        let door = match rng.roll_dice(1, 10) {
+            1 if can_hide => {
+                // Secret doors look like a wall until they are found
+                build_data.map.tiles[idx] = TileType::Wall;
+                "Secret Door"
+            }
+            2 => "Locked Door",
+            _ => "Door",
+        };
+        build_data.spawn_list.push((idx, door.to_string()));
+    }
+
     fn door_possible(&mut self, build_data: &mut BuilderMap, idx: usize) -> bool {
         for (spawn_idx, _name) in build_data.spawn_list.iter() {
             if *spawn_idx == idx {
@@ -82,3 +108,76 @@ impl DoorPlacement {
         false
     }
 }
+
+/// One walk from the start to the way down, found with a single flood of the level. Walling
+/// off any tile not on it leaves the exit reachable. None if the exit can't be reached at all.
+fn exit_route(build_data: &mut BuilderMap) -> Option<HashSet<usize>> {
+    let dijkstra_map = reachable_from_start(build_data, &[]);
+    let mut idx = build_data
+        .map
+        .tiles
+        .iter()
+        .position(|tile| *tile == TileType::DownStairs)?;
+    if dijkstra_map.map[idx] == std::f32::MAX {
+        return None;
+    }
+
+    // Walk back downhill to the start
+    let mut route = HashSet::new();
+    route.insert(idx);
+    while dijkstra_map.map[idx] > 0.0 {
+        idx = DijkstraMap::find_lowest_exit(&dijkstra_map, idx, &build_data.map)?;
+        route.insert(idx);
+    }
+    Some(route)
+}
+
+/// Places the key for this level's locked doors and chests somewhere the player can reach
+/// without it
+pub fn place_key(rng: &mut RandomNumberGenerator, build_data: &mut BuilderMap) {
+    // There is only ever one key per level
+    build_data.spawn_list.retain(|(_idx, name)| name != "Key");
+
+    let has_locks = build_data
+        .spawn_list
+        .iter()
+        .any(|(_idx, name)| name == "Locked Door" || name == "Locked Chest");
+    if !has_locks {
+        return;
+    }
+
+    // Only doors stand in the way; a locked chest just needs the key
+    let locked_doors: Vec<usize> = build_data
+        .spawn_list
+        .iter()
+        .filter(|(_idx, name)| name == "Locked Door")
+        .map(|(idx, _name)| *idx)
+        .collect();
+
+    let dijkstra_map = reachable_from_start(build_data, &locked_doors);
+    let starting_pos = build_data.starting_position.as_ref().unwrap();
+    let start_idx = build_data.map.xy_idx(starting_pos.x, starting_pos.y);
+    let possible_targets: Vec<usize> = build_data
+        .map
+        .tiles
+        .iter()
+        .enumerate()
+        .filter(|(idx, tile)| {
+            **tile == TileType::Floor
+                && *idx != start_idx
+                && dijkstra_map.map[*idx] < std::f32::MAX
+                && !build_data
+                    .spawn_list
+                    .iter()
+                    .any(|(spawn_idx, _name)| spawn_idx == idx)
+        })
+        .map(|(idx, _tile)| idx)
+        .collect();
+    if possible_targets.is_empty() {
+        // The doors will have to be picked
+        return;
+    }
+
+    let target = possible_targets[(rng.roll_dice(1, possible_targets.len() as i32) - 1) as usize];
+    build_data.spawn_list.push((target, "Key".to_string()));
+}
@@ -12,6 +12,7 @@ pub fn forest_builder(
     _rng: &mut RandomNumberGenerator,
 ) -> BuilderChain {
     let mut chain = BuilderChain::new(depth, width, height, "Into the Woods");
+    chain.build_data.map.outdoors = true;
     chain.start_with(CellularAutomataBuilder::new());
     chain.with(AreaStartingPosition::new(XStart::CENTER, YStart::CENTER));
     chain.with(CullUnreachable::new());
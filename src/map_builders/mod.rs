@@ -1,6 +1,10 @@
-use super::{spawner, tile_walkable, Map, Position, Rect, TileType, SHOW_MAPGEN_VISUALIZER};
+use super::{
+    spawner, tile_walkable, DailyRoutine, Map, MonsterMemory, Pack, Position, Rect, TileType,
+    SHOW_MAPGEN_VISUALIZER,
+};
 use legion::prelude::*;
 use rltk::RandomNumberGenerator;
+use std::collections::HashMap;
 
 mod common;
 use common::*;
@@ -33,7 +37,7 @@ use room_based_stairs::RoomBasedStairs;
 mod area_starting_points;
 use area_starting_points::*;
 mod cull_unreachable;
-use cull_unreachable::CullUnreachable;
+use cull_unreachable::{reachable_from_start, CullUnreachable};
 mod voronoi_spawning;
 use voronoi_spawning::VoronoiSpawning;
 mod distant_exit;
@@ -57,11 +61,16 @@ use rooms_corridors_lines::StraightLineCorridors;
 mod room_corridor_spawner;
 use room_corridor_spawner::CorridorSpawner;
 mod door_placement;
-use door_placement::DoorPlacement;
+use door_placement::{place_key, DoorPlacement};
 mod town;
 use town::town_builder;
 mod forest;
 use forest::forest_builder;
+mod boss_lair;
+use boss_lair::boss_lair_builder;
+
+// The deepest level, where the Hollow King waits
+pub const FINAL_DEPTH: i32 = 10;
 
 pub struct BuilderMap {
     pub map: Map,
@@ -70,6 +79,8 @@ pub struct BuilderMap {
     pub corridors: Option<Vec<Vec<usize>>>,
     pub history: Vec<Map>,
     pub spawn_list: Vec<(usize, String)>,
+    pub spawn_groups: Vec<Vec<usize>>,
+    pub routines: Vec<(usize, DailyRoutine)>,
 }
 
 impl BuilderMap {
@@ -102,6 +113,8 @@ impl BuilderChain {
                 corridors: None,
                 history: Vec::new(),
                 spawn_list: Vec::new(),
+                spawn_groups: Vec::new(),
+                routines: Vec::new(),
             },
         }
     }
@@ -133,8 +146,36 @@ impl BuilderChain {
     }
 
     pub fn spawn_entities(&mut self, world: &mut World) {
+        let mut spawned = HashMap::new();
         for (idx, name) in self.build_data.spawn_list.iter() {
-            spawner::spawn_entity(world, &self.build_data.map, idx, name);
+            if let Some(entity) = spawner::spawn_entity(world, &self.build_data.map, idx, name) {
+                spawned.insert(*idx, entity);
+            }
+        }
+
+        // Bind each group into a pack, the first member leading it
+        for (i, group) in self.build_data.spawn_groups.iter().enumerate() {
+            let id = self.build_data.map.depth * 1000 + i as i32;
+            let members: Vec<Entity> = group
+                .iter()
+                .filter_map(|idx| spawned.get(idx))
+                .filter(|entity| world.get_component::<MonsterMemory>(**entity).is_some())
+                .copied()
+                .collect();
+            for (n, member) in members.iter().enumerate() {
+                world
+                    .add_component(*member, Pack { id, leader: n == 0 })
+                    .expect("Cannot add component");
+            }
+        }
+
+        // Townsfolk keep to their daily routines
+        for (idx, routine) in self.build_data.routines.iter() {
+            if let Some(entity) = spawned.get(idx) {
+                world
+                    .add_component(*entity, *routine)
+                    .expect("Cannot add component");
+            }
         }
     }
 }
@@ -312,6 +353,7 @@ pub fn level_builder<S: ToString>(
     match depth {
         1 => town_builder(depth, width, height, rng),
         2 => forest_builder(depth, width, height, rng),
+        FINAL_DEPTH => boss_lair_builder(depth, width, height, rng),
         _ => random_builder(depth, width, height, name, rng),
     }
 }
@@ -1,4 +1,4 @@
-use super::{BuilderMap, InitialMapBuilder, MetaMapBuilder, Position, TileType};
+use super::{place_key, BuilderMap, InitialMapBuilder, MetaMapBuilder, Position, TileType};
 use rltk::RandomNumberGenerator;
 use std::collections::HashSet;
 
@@ -116,6 +116,30 @@ impl PrefabBuilder {
                     .spawn_list
                     .push((idx, "Health Potion".to_string()));
             }
+            'C' => {
+                build_data.map.tiles[idx] = TileType::Floor;
+                build_data.spawn_list.push((idx, "Chest".to_string()));
+            }
+            'L' => {
+                build_data.map.tiles[idx] = TileType::Floor;
+                build_data
+                    .spawn_list
+                    .push((idx, "Locked Chest".to_string()));
+            }
+            '=' => {
+                build_data.map.tiles[idx] = TileType::Floor;
+                build_data.spawn_list.push((idx, "Locked Door".to_string()));
+            }
+            'B' => {
+                build_data.map.tiles[idx] = TileType::Floor;
+                build_data.spawn_list.push((idx, "Hollow King".to_string()));
+            }
+            'M' => {
+                build_data.map.tiles[idx] = TileType::Floor;
+                build_data
+                    .spawn_list
+                    .push((idx, "Crown of the Deep".to_string()));
+            }
 
             c => {
                 rltk::console::log(format!("Unknown glyph loading map: {}", c));
@@ -252,7 +276,13 @@ impl PrefabBuilder {
         }
 
         // Note that this is a place-holder and will be moved out of this function
-        let master_vault_list = vec![TOTALLY_NOT_A_TRAP, CHECKERBOARD, SILLY_SMILE];
+        let master_vault_list = vec![
+            TOTALLY_NOT_A_TRAP,
+            CHECKERBOARD,
+            SILLY_SMILE,
+            TREASURE_CACHE,
+            LOCKED_CLOSET,
+        ];
 
         // Filter the vault list down to ones that are applicable to the current depth
         let mut possible_vaults: Vec<&PrefabRoom> = master_vault_list
@@ -361,5 +391,8 @@ impl PrefabBuilder {
                 possible_vaults.remove(vault_index);
             }
         }
+
+        // Vaults may have locked doors, or covered up an existing key
+        place_key(rng, build_data);
     }
 }
@@ -56,3 +56,55 @@ const LEVEL_MAP: &str = "\
 # %^##  ^   ###     ############### ########      #####       ##################
 ################################################################################
 ";
+
+pub const BOSS_LAIR: PrefabLevel = PrefabLevel {
+    template: LAIR_MAP,
+    width: 80,
+    height: 43,
+};
+
+const LAIR_MAP: &str = "\
+################################################################################
+################################################################################
+######             #############################################################
+######      @      #############################################################
+######             #############################################################
+######             #############################################################
+#########  ###########################################        ##################
+#########  ###########################################   !    ##################
+#########  ###########################################        ##################
+#########  #################         #################### ##########  ##########
+#########  #################    o    #################### ##########  ##########
+#########          ^                            ^                     ##########
+####################### ####         ########################### #### ##########
+####################### ######## #################               #### ##########
+####################### ######## ################    o      o    #### ##########
+###########     ####### ######## ################                #### ##########
+###########  %  ####### ######## ################       !        #### ##########
+###########     ####### ######## #################               #### ##########
+############ ########## ######## ######################## ###########  #########
+############ ########## ######## ######################## ###########  #########
+############                               ^              ###########  #########
+######################## #######################################       #########
+######################## #######################################  ##############
+####################        ####################################  ##############
+####################   !    ####################################  ##############
+####################         ########                             ##############
+############################   o  ### ################################### ######
+############################       ## ##      #   #   #   #       ####### ######
+############################  ^    ##         #   #   #   #        ###### ######
+############################       ##    o                    o    ###### ######
+#############################     ###                                   o ######
+################################ ####         #   #   #   #       ##############
+################################ ####         #   #   #   #       ##############
+################################             ^                    ##############
+#####################################       ######   ######       ##############
+#####################################       ###         ###       ##############
+#####################################       ##     B     ##       ##############
+#####################################       ###    M    ###       ##############
+#####################################       ####       ####       ##############
+#####################################                             ##############
+################################################################################
+################################################################################
+################################################################################
+";
@@ -53,3 +53,33 @@ const CHECKERBOARD_MAP: &str = "
  ^# #
 
 ";
+
+pub const TREASURE_CACHE: PrefabRoom = PrefabRoom {
+    template: TREASURE_CACHE_MAP,
+    width: 6,
+    height: 5,
+    first_depth: 3,
+    last_depth: 100,
+};
+
+const TREASURE_CACHE_MAP: &str = "
+ ####
+ #L^
+ ####
+
+";
+
+pub const LOCKED_CLOSET: PrefabRoom = PrefabRoom {
+    template: LOCKED_CLOSET_MAP,
+    width: 5,
+    height: 5,
+    first_depth: 4,
+    last_depth: 100,
+};
+
+const LOCKED_CLOSET_MAP: &str = "
+ ###
+ #C#
+ #=#
+
+";
@@ -25,6 +25,7 @@ impl RoomBasedSpawner {
                     room,
                     build_data.map.depth,
                     &mut build_data.spawn_list,
+                    &mut build_data.spawn_groups,
                 );
             }
         } else {
@@ -1,4 +1,4 @@
-use super::{BuilderChain, BuilderMap, InitialMapBuilder, Position, TileType};
+use super::{BuilderChain, BuilderMap, DailyRoutine, InitialMapBuilder, Position, Rect, TileType};
 use crate::a_star_search;
 use rltk::RandomNumberGenerator;
 use std::collections::HashSet;
@@ -10,6 +10,7 @@ pub fn town_builder(
     _rng: &mut RandomNumberGenerator,
 ) -> BuilderChain {
     let mut chain = BuilderChain::new(depth, width, height, "The Town of Bracketon");
+    chain.build_data.map.outdoors = true;
     chain.start_with(TownBuilder::new());
     chain
 }
@@ -63,6 +64,7 @@ impl TownBuilder {
 
         self.spawn_dockers(rng, build_data);
         self.spawn_townsfolk(rng, build_data, &mut available_town_tiles);
+        self.assign_routines(rng, build_data, &buildings, &building_size);
 
         // Make visible for screenshot
         for t in build_data.map.visible_tiles.iter_mut() {
@@ -523,6 +525,59 @@ impl TownBuilder {
         }
     }
 
+    fn assign_routines(
+        &mut self,
+        rng: &mut RandomNumberGenerator,
+        build_data: &mut BuilderMap,
+        buildings: &[(i32, i32, i32, i32)],
+        building_index: &[(usize, i32, BuildingTag)],
+    ) {
+        // The floor inside each building's walls
+        let interior = |i: usize| {
+            let (bx, by, bw, bh) = buildings[i];
+            Rect::new(bx + 1, by + 1, bw - 3, bh - 3)
+        };
+        let pub_rect = building_index
+            .iter()
+            .find(|(_, _, tag)| matches!(tag, BuildingTag::Pub))
+            .map(|(i, _, _)| interior(*i));
+        let homes: Vec<Rect> = building_index
+            .iter()
+            .filter(|(_, _, tag)| matches!(tag, BuildingTag::Hovel))
+            .map(|(i, _, _)| interior(*i))
+            .collect();
+
+        for (idx, name) in build_data.spawn_list.iter() {
+            let x = *idx as i32 % build_data.map.width;
+            let y = *idx as i32 / build_data.map.width;
+            let building = buildings
+                .iter()
+                .enumerate()
+                .map(|(i, _)| interior(i))
+                .find(|rect| rect.contains(x, y));
+            let random_home = if homes.is_empty() {
+                None
+            } else {
+                Some(homes[(rng.roll_dice(1, homes.len() as i32) - 1) as usize])
+            };
+
+            let (day, night) = match name.as_ref() {
+                // Living above the shop
+                "Barkeep" | "Shady Salesman" | "Priest" | "Mom" => (building, building),
+                // Shut up shop and head for a drink
+                "Blacksmith" | "Clothier" | "Alchemist" => (building, pub_rect),
+                // Out and about by day, at the bar by night
+                "Patron" | "Drunk" => (None, pub_rect),
+                "Parishioner" => (building, random_home),
+                "Peasant" => (None, building.or(random_home)),
+                _ => continue,
+            };
+            build_data
+                .routines
+                .push((*idx, DailyRoutine { day, night }));
+        }
+    }
+
     fn spawn_townsfolk(
         &mut self,
         rng: &mut RandomNumberGenerator,
@@ -531,13 +586,14 @@ impl TownBuilder {
     ) {
         for idx in available_town_tiles.iter() {
             if rng.roll_dice(1, 10) == 1 {
-                let roll = rng.roll_dice(1, 4);
+                let roll = rng.roll_dice(1, 5);
                 match roll {
                     1 => build_data.spawn_list.push((*idx, "Peasant".to_string())),
                     2 => build_data.spawn_list.push((*idx, "Drunk".to_string())),
                     3 => build_data
                         .spawn_list
                         .push((*idx, "Dock Worker".to_string())),
+                    4 => build_data.spawn_list.push((*idx, "Town Guard".to_string())),
                     _ => build_data.spawn_list.push((*idx, "Fisher".to_string())),
                 }
             }
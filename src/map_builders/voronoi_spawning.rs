@@ -46,6 +46,7 @@ impl VoronoiSpawning {
                 area,
                 build_data.map.depth,
                 &mut build_data.spawn_list,
+                &mut build_data.spawn_groups,
             );
         }
     }
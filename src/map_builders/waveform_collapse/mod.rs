@@ -54,6 +54,8 @@ impl WaveformCollapseBuilder {
 
         // We've rewritten whole map, so previous spawn points are invalid
         build_data.spawn_list.clear();
+        build_data.spawn_groups.clear();
+        build_data.routines.clear();
     }
 
     fn render_tile_gallery(
@@ -1,7 +1,17 @@
-use super::{components::*, gamelog::GameLog, particle_system::ParticleBuilder, skill_bonus};
+use super::{
+    armor_class, attack_bonus,
+    components::*,
+    gamelog::GameLog,
+    noise_system::{NoiseBuilder, MELEE_NOISE},
+    particle_system::ParticleBuilder,
+    skill_bonus,
+    skill_system::SkillUses,
+};
 use legion::prelude::*;
 use rltk::{console, RandomNumberGenerator};
 
+const SNEAK_ATTACK_HIT_BONUS: i32 = 4;
+
 pub fn build() -> Box<(dyn Schedulable + 'static)> {
     SystemBuilder::new("melee_combat")
         .with_query(<(
@@ -16,14 +26,18 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
         .with_query(<(Read<MeleeWeapon>, Read<Equipped>)>::query())
         .with_query(<(Read<Wearable>, Read<Equipped>)>::query())
         .read_component::<NaturalAttackDefense>()
+        .read_component::<MonsterMemory>()
+        .read_component::<Modifiers>()
         .write_resource::<GameLog>()
         .write_resource::<ParticleBuilder>()
+        .write_resource::<NoiseBuilder>()
         .write_resource::<RandomNumberGenerator>()
         .read_resource::<Entity>()
+        .write_resource::<SkillUses>()
         .build(
             |command_buffer,
              world,
-             (log, particle_builder, rng, player_entity),
+             (log, particle_builder, noise, rng, player_entity, skill_uses),
              (query, query_melee, query_defense)| {
                 for (entity, (wants_melee, attacker_attributes, attacker_skills, attacker_pools)) in
                     query.iter_entities(world)
@@ -56,6 +70,7 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                                     damage_n_dice: 1,
                                     damage_die_type: 4,
                                     damage_bonus: 0,
+                                    category: None,
                                 };
 
                                 if let Some(nat) =
@@ -84,24 +99,41 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                                 }
 
                                 let natural_roll = rng.roll_dice(1, 20);
-                                let attribute_hit_bonus = match weapon_info.attribute {
-                                    WeaponAttribute::Might => attacker_attributes.might.bonus,
-                                    WeaponAttribute::Quickness => {
-                                        attacker_attributes.quickness.bonus
+                                let attacker_hit_bonus = attack_bonus(
+                                    &*attacker_attributes,
+                                    weapon_info.attribute,
+                                    Skill::Melee,
+                                    &*attacker_skills,
+                                );
+                                let weapon_hit_bonus = weapon_info.hit_bonus;
+                                let perk_hit_bonus = match (
+                                    weapon_info.category,
+                                    world.get_component::<Modifiers>(entity),
+                                ) {
+                                    (Some(category), Some(modifiers)) => {
+                                        *modifiers.melee_hit.get(&category).unwrap_or(&0)
                                     }
+                                    _ => 0,
                                 };
-                                let skill_hit_bonus = skill_bonus(Skill::Melee, &*attacker_skills);
-                                let weapon_hit_bonus = weapon_info.hit_bonus;
                                 let mut status_hit_bonus = 0;
                                 if let Some(hc) = world.get_component::<HungerClock>(entity) {
                                     if hc.state == HungerState::WellFed {
                                         status_hit_bonus += 1;
                                     }
                                 }
+                                // Sleeping targets are easy pickings
+                                let sneak_attack =
+                                    match world.get_component::<MonsterMemory>(target) {
+                                        Some(memory) => memory.state == AiState::Asleep,
+                                        None => false,
+                                    };
+                                if sneak_attack {
+                                    status_hit_bonus += SNEAK_ATTACK_HIT_BONUS;
+                                }
                                 let modified_hit_roll = natural_roll
-                                    + attribute_hit_bonus
-                                    + skill_hit_bonus
+                                    + attacker_hit_bonus
                                     + weapon_hit_bonus
+                                    + perk_hit_bonus
                                     + status_hit_bonus;
 
                                 let mut armor_item_bonus_f = 0.0;
@@ -111,19 +143,22 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                                     }
                                 }
 
-                                let base_armor_class =
-                                    match world.get_component::<NaturalAttackDefense>(target) {
-                                        None => 10,
-                                        Some(nat) => nat.armor_class.unwrap_or(10),
-                                    };
-                                let armor_quickness_bonus = target_attributes.quickness.bonus;
-                                let armor_skill_bonus =
-                                    skill_bonus(Skill::Defense, &*target_skills);
-                                let armor_item_bonus = armor_item_bonus_f as i32;
-                                let armor_class = base_armor_class
-                                    + armor_quickness_bonus
-                                    + armor_skill_bonus
-                                    + armor_item_bonus;
+                                let natural_armor = world
+                                    .get_component::<NaturalAttackDefense>(target)
+                                    .and_then(|nat| nat.armor_class);
+                                let armor_class = armor_class(
+                                    natural_armor,
+                                    &*target_attributes,
+                                    &*target_skills,
+                                    armor_item_bonus_f,
+                                );
+
+                                // Clashing blades can be heard from afar
+                                if natural_roll != 1 {
+                                    if let Some(pos) = world.get_component::<Position>(target) {
+                                        noise.request(pos.x, pos.y, MELEE_NOISE);
+                                    }
+                                }
 
                                 if natural_roll == 1 {
                                     // Natural 1 miss
@@ -152,13 +187,20 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                                         skill_bonus(Skill::Melee, &*attacker_skills);
                                     let weapon_damage_bonus = weapon_info.damage_bonus;
 
-                                    let damage = i32::max(
+                                    let mut damage = i32::max(
                                         0,
                                         base_damage
                                             + attr_damage_bonus
                                             + skill_damage_bonus
                                             + weapon_damage_bonus,
                                     );
+                                    if sneak_attack {
+                                        damage *= 2;
+                                        log.entries.push(format!(
+                                            "{} catches {} sleeping!",
+                                            &attacker_name, &target_name
+                                        ));
+                                    }
                                     SufferDamage::new_damage(
                                         &command_buffer,
                                         target,
@@ -169,6 +211,14 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                                         "{} hits {}, for {} hp.",
                                         &attacker_name, &target_name, damage
                                     ));
+                                    skill_uses.record(entity, Skill::Melee);
+                                    if target == **player_entity {
+                                        RunStats::hurt_by(
+                                            &command_buffer,
+                                            target,
+                                            attacker_name.clone(),
+                                        );
+                                    }
                                     if let Some(pos) = world.get_component::<Position>(target) {
                                         particle_builder.request(
                                             pos.x,
@@ -185,6 +235,8 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                                         "{} attacks {}, but can't connect.",
                                         attacker_name, target_name
                                     ));
+                                    // Turning a blow aside is good practice for the defender
+                                    skill_uses.record(target, Skill::Defense);
                                     if let Some(pos) = world.get_component::<Position>(target) {
                                         particle_builder.request(
                                             pos.x,
@@ -1,26 +1,257 @@
 use super::{
-    a_star_search, particle_system::ParticleBuilder, Confusion, Map, Monster, Point, Position,
-    RunState, Viewshed, WantsToMelee,
+    a_star_search, field_of_view, gamelog::GameLog, particle_system::ParticleBuilder,
+    perception_bonus, AiState, Attributes, Confusion, Faction, FlowField, Guarding, Map, Modifiers,
+    Monster, MonsterMemory, Morale, Name, Pack, Point, Pools, Position, Reputation, RunState,
+    Skills, SpecialAbilities, Viewshed, Wandering, WantsToMelee, WantsToUseAbility,
 };
+use crate::prefabs::{reaction_towards, Reaction, PREFABS};
 use legion::prelude::*;
+use rltk::RandomNumberGenerator;
+use std::collections::{HashMap, HashSet};
+
+// How long a mob keeps chasing or searching before it gives up
+pub const HUNT_TURNS: i32 = 30;
+const SEARCH_TURNS: i32 = 10;
+const SEARCH_RADIUS: f32 = 4.0;
+
+// Sleeping mobs have to beat this to notice an enemy
+const WAKE_DIFFICULTY: i32 = 15;
+
+// Morale lost per level an enemy has over the mob
+const FEAR_MORALE: i32 = 2;
+
+// How far pack members stray from their leader
+const PACK_RANGE: f32 = 3.0;
+
+// How far a wandering mob strolls to its next spot
+const WANDER_RANGE: f32 = 20.0;
+
+/// Takes one step along the path to within `dist` of the target, returning false if there is no way forward
+fn move_towards(
+    map: &mut Map,
+    pos: &mut Position,
+    viewshed: &mut Viewshed,
+    target: Point,
+    dist: f32,
+) -> bool {
+    if let Some((path, _cost)) = a_star_search(Point::new(pos.x, pos.y), target, dist, map) {
+        if path.len() > 1 {
+            step_to(map, pos, viewshed, path[1]);
+            return true;
+        }
+    }
+    false
+}
+
+/// Moves onto a neighbouring tile, keeping the blocked layer up to date
+fn step_to(map: &mut Map, pos: &mut Position, viewshed: &mut Viewshed, step: Point) {
+    let mut idx = map.xy_idx(pos.x, pos.y);
+    map.blocked[idx] = false;
+    pos.x = step.x;
+    pos.y = step.y;
+    idx = map.xy_idx(pos.x, pos.y);
+    map.blocked[idx] = true;
+    viewshed.dirty = true;
+}
+
+/// Takes one step away from all of the given tiles, returning false if cornered
+fn flee_from(
+    map: &mut Map,
+    pos: &mut Position,
+    viewshed: &mut Viewshed,
+    sources: &[usize],
+) -> bool {
+    let my_idx = map.xy_idx(pos.x, pos.y);
+    let flee_map = rltk::DijkstraMap::new(
+        map.width as usize,
+        map.height as usize,
+        sources,
+        &*map,
+        10.0,
+    );
+    if let Some(flee_target) = rltk::DijkstraMap::find_highest_exit(&flee_map, my_idx, &*map) {
+        if !map.blocked[flee_target] {
+            map.blocked[my_idx] = false;
+            map.blocked[flee_target] = true;
+            viewshed.dirty = true;
+            pos.x = flee_target as i32 % map.width;
+            pos.y = flee_target as i32 / map.width;
+            return true;
+        }
+    }
+    false
+}
+
+/// True if the target is in range and nothing stands in the way of a shot at it
+fn line_of_fire(map: &Map, from: Point, to: Point, range: f32) -> bool {
+    if !field_of_view(from, range as usize, map).contains(&to) {
+        return false;
+    }
+    rltk::line2d(rltk::LineAlg::Bresenham, from, to)
+        .iter()
+        .all(|tile| *tile == from || *tile == to || !map.blocked[map.xy_idx(tile.x, tile.y)])
+}
+
+/// Finds the free tile next to the target closest to the mob, so a pack can surround it
+fn surround_slot(map: &Map, me: Point, target: Point, claimed: &HashSet<usize>) -> Option<Point> {
+    let mut best: Option<(Point, f32)> = None;
+    for dy in -1..=1 {
+        for dx in -1..=1 {
+            let slot = Point::new(target.x + dx, target.y + dy);
+            if (dx == 0 && dy == 0)
+                || slot.x < 0
+                || slot.x >= map.width
+                || slot.y < 0
+                || slot.y >= map.height
+            {
+                continue;
+            }
+            let idx = map.xy_idx(slot.x, slot.y);
+            if map.blocked[idx] || claimed.contains(&idx) {
+                continue;
+            }
+            let distance = rltk::DistanceAlg::Pythagoras.distance2d(me, slot);
+            let closer = match best {
+                Some((_, best_distance)) => distance < best_distance,
+                None => true,
+            };
+            if closer {
+                best = Some((slot, distance));
+            }
+        }
+    }
+    best.map(|(slot, _)| slot)
+}
+
+/// Steps towards a free side of the target that no packmate has taken yet, returning whether
+/// the mob moved, or None if every side is taken
+pub fn surround_target(
+    map: &mut Map,
+    pos: &mut Position,
+    viewshed: &mut Viewshed,
+    target: Point,
+    claimed: &mut HashSet<usize>,
+) -> Option<bool> {
+    let slot = surround_slot(map, Point::new(pos.x, pos.y), target, claimed)?;
+    claimed.insert(map.xy_idx(slot.x, slot.y));
+    Some(move_towards(map, pos, viewshed, slot, 0.0))
+}
+
+/// Where the leader of a mob's pack is, unless the mob is the leader itself
+pub fn pack_leader(pack: Option<Pack>, pack_leaders: &HashMap<i32, Point>) -> Option<Point> {
+    match pack {
+        Some(pack) if !pack.leader => pack_leaders.get(&pack.id).copied(),
+        _ => None,
+    }
+}
+
+/// What a mob does with nothing to chase: keep up with its pack leader, stroll about, or
+/// head back to its post
+pub fn idle_movement(
+    map: &mut Map,
+    pos: &mut Position,
+    viewshed: &mut Viewshed,
+    rng: &mut RandomNumberGenerator,
+    leader: Option<Point>,
+    wander: Option<&mut Wandering>,
+    post: Option<Point>,
+) {
+    if let Some(leader) = leader {
+        // Stay close to the leader of the pack
+        if rltk::DistanceAlg::Pythagoras.distance2d(Point::new(pos.x, pos.y), leader) > PACK_RANGE {
+            move_towards(map, pos, viewshed, leader, 2.0);
+        }
+    } else if let Some(wander) = wander {
+        // Stroll from one spot to the next
+        let arrived = match wander.destination {
+            Some(destination) => {
+                destination == Point::new(pos.x, pos.y)
+                    || !move_towards(map, pos, viewshed, destination, 2.0)
+            }
+            None => true,
+        };
+        if arrived {
+            wander.destination = random_destination(map, Point::new(pos.x, pos.y), rng);
+        }
+    } else if let Some(post) = post {
+        // Head back to the post
+        if post != Point::new(pos.x, pos.y) {
+            move_towards(map, pos, viewshed, post, 2.0);
+        }
+    }
+}
+
+/// Picks a random open tile within a stroll of a wandering mob that it can walk to
+fn random_destination(map: &Map, from: Point, rng: &mut RandomNumberGenerator) -> Option<Point> {
+    let start = map.xy_idx(from.x, from.y);
+    let reachable = rltk::DijkstraMap::new(
+        map.width as usize,
+        map.height as usize,
+        &[start],
+        map,
+        WANDER_RANGE,
+    );
+    let open_tiles: Vec<usize> = reachable
+        .map
+        .iter()
+        .enumerate()
+        .filter(|(idx, distance)| **distance < std::f32::MAX && *idx != start && !map.blocked[*idx])
+        .map(|(idx, _distance)| idx)
+        .collect();
+    if open_tiles.is_empty() {
+        return None;
+    }
+    let idx = open_tiles[(rng.roll_dice(1, open_tiles.len() as i32) - 1) as usize];
+    Some(Point::new(idx as i32 % map.width, idx as i32 / map.width))
+}
 
 pub fn build() -> Box<(dyn Schedulable + 'static)> {
     SystemBuilder::new("monster_ai")
         .write_resource::<Map>()
-        .read_resource::<Point>()
-        .read_resource::<Entity>()
         .read_resource::<RunState>()
         .with_query(<(Write<Viewshed>, Write<Position>)>::query().filter(tag::<Monster>()))
+        .read_component::<Faction>()
+        .read_component::<Reputation>()
+        .read_component::<Modifiers>()
+        .read_component::<Name>()
+        .write_component::<Pools>()
+        .write_component::<Morale>()
+        .write_component::<SpecialAbilities>()
         .write_component::<Confusion>()
+        .write_component::<MonsterMemory>()
+        .write_component::<Wandering>()
+        .read_component::<Guarding>()
+        .read_component::<Attributes>()
+        .read_component::<Skills>()
+        .read_component::<Pack>()
+        .with_query(<(Read<Pack>, Read<MonsterMemory>, Read<Position>)>::query())
         .write_resource::<ParticleBuilder>()
+        .write_resource::<RandomNumberGenerator>()
+        .write_resource::<GameLog>()
+        .read_resource::<FlowField>()
         .build(
             |command_buffer,
              world,
-             (map, player_pos, player_entity, runstate, particle_builder),
-             query| unsafe {
+             (map, runstate, particle_builder, rng, log, flow_field),
+             (query, pack_query)| unsafe {
                 if **runstate != RunState::MonsterTurn {
                     return;
                 }
+                let pm = PREFABS.lock().unwrap();
+
+                // What each pack saw last turn, and where its leaders are
+                let mut pack_sightings: HashMap<i32, Point> = HashMap::new();
+                let mut pack_leaders: HashMap<i32, Point> = HashMap::new();
+                for (pack, memory, pos) in pack_query.iter(world) {
+                    if memory.state == AiState::Hunting && memory.turns_since_seen == 0 {
+                        pack_sightings.insert(pack.id, memory.last_seen);
+                    }
+                    if pack.leader {
+                        pack_leaders.insert(pack.id, Point::new(pos.x, pos.y));
+                    }
+                }
+                let mut claimed_slots: HashSet<usize> = HashSet::new();
+
                 for (entity, (mut viewshed, mut pos)) in query.iter_entities_unchecked(world) {
                     let mut can_act = true;
 
@@ -43,28 +274,333 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                     }
 
                     if can_act {
-                        let distance = rltk::DistanceAlg::Pythagoras
-                            .distance2d(Point::new(pos.x, pos.y), **player_pos);
-                        if distance < 1.5 {
+                        let mut memory =
+                            match world.get_component_mut_unchecked::<MonsterMemory>(entity) {
+                                Some(memory) => memory,
+                                None => continue,
+                            };
+
+                        // Pick the closest thing in view that the mob wants to fight
+                        let my_faction = match world.get_component::<Faction>(entity) {
+                            Some(faction) => faction.name.clone(),
+                            None => continue,
+                        };
+                        let mut target: Option<(Entity, Point, f32)> = None;
+                        let mut enemies = Vec::new();
+                        for tile in viewshed.visible_tiles.iter() {
+                            let idx = map.xy_idx(tile.x, tile.y);
+                            for other in map.tile_content[idx].iter() {
+                                if *other == entity {
+                                    continue;
+                                }
+                                if let Some(faction) = world.get_component::<Faction>(*other) {
+                                    let reputation = world.get_component::<Reputation>(*other);
+                                    let modifiers = world.get_component::<Modifiers>(*other);
+                                    if reaction_towards(
+                                        &my_faction,
+                                        &faction.name,
+                                        reputation.as_deref(),
+                                        modifiers.as_deref(),
+                                        &pm,
+                                    ) == Reaction::Attack
+                                    {
+                                        enemies.push(idx);
+                                        let distance = rltk::DistanceAlg::Pythagoras
+                                            .distance2d(Point::new(pos.x, pos.y), *tile);
+                                        let closer = match target {
+                                            Some((_, _, best)) => distance < best,
+                                            None => true,
+                                        };
+                                        if closer {
+                                            target = Some((*other, *tile, distance));
+                                        }
+                                    }
+                                }
+                            }
+                        }
+
+                        // Sleepers only wake if they notice an enemy nearby
+                        if memory.state == AiState::Asleep {
+                            if target.is_none() {
+                                continue;
+                            }
+                            let perception = match (
+                                world.get_component::<Attributes>(entity),
+                                world.get_component::<Skills>(entity),
+                            ) {
+                                (Some(attributes), Some(skills)) => {
+                                    perception_bonus(&attributes, &skills)
+                                }
+                                _ => 0,
+                            };
+                            if rng.roll_dice(1, 20) + perception < WAKE_DIFFICULTY {
+                                continue;
+                            }
+                            particle_builder.request(
+                                pos.x,
+                                pos.y,
+                                rltk::RGB::named(rltk::YELLOW),
+                                rltk::RGB::named(rltk::BLACK),
+                                rltk::to_cp437('!'),
+                                200.0,
+                            );
+                        }
+
+                        let adjacent_target = match target {
+                            Some((target_entity, _, distance)) if distance < 1.5 => {
+                                Some(target_entity)
+                            }
+                            _ => None,
+                        };
+
+                        // Abilities come off cooldown, and mana returns while resting
+                        if let Some(mut abilities) =
+                            world.get_component_mut_unchecked::<SpecialAbilities>(entity)
+                        {
+                            for ability in abilities.abilities.iter_mut() {
+                                ability.cooldown_left = i32::max(0, ability.cooldown_left - 1);
+                            }
+                        }
+                        if target.is_none() {
+                            if let Some(mut pools) =
+                                world.get_component_mut_unchecked::<Pools>(entity)
+                            {
+                                pools.mana.current =
+                                    i32::min(pools.mana.max, pools.mana.current + 1);
+                            }
+                        }
+
+                        // Morale drops at the sight of a tougher enemy, and recovers in peace
+                        if let Some(mut morale) =
+                            world.get_component_mut_unchecked::<Morale>(entity)
+                        {
+                            match target {
+                                Some((target_entity, _, _)) => {
+                                    let newly_spotted = memory.state != AiState::Hunting
+                                        || memory.turns_since_seen > 0;
+                                    if newly_spotted && memory.state != AiState::Fleeing {
+                                        let my_level = world
+                                            .get_component::<Pools>(entity)
+                                            .map_or(1, |pools| pools.level);
+                                        let their_level = world
+                                            .get_component::<Pools>(target_entity)
+                                            .map_or(1, |pools| pools.level);
+                                        if their_level > my_level {
+                                            morale.current -=
+                                                (their_level - my_level) * FEAR_MORALE;
+                                        }
+                                    }
+                                }
+                                None => morale.current = i32::min(morale.max, morale.current + 1),
+                            }
+
+                            if memory.state == AiState::Fleeing {
+                                if morale.current >= morale.max / 2 {
+                                    memory.state = AiState::Idle;
+                                }
+                            } else if morale.current <= 0 {
+                                memory.state = AiState::Fleeing;
+                                let idx = map.xy_idx(pos.x, pos.y);
+                                if map.visible_tiles[idx] {
+                                    if let Some(name) = world.get_component::<Name>(entity) {
+                                        log.entries.push(format!("{} turns and flees!", name.name));
+                                    }
+                                }
+                            }
+                        }
+
+                        // Run from every enemy in sight, fighting back only when cornered
+                        if memory.state == AiState::Fleeing {
+                            if !enemies.is_empty()
+                                && !flee_from(&mut **map, &mut *pos, &mut *viewshed, &enemies)
+                            {
+                                if let Some(target_entity) = adjacent_target {
+                                    command_buffer.add_component(
+                                        entity,
+                                        WantsToMelee {
+                                            target: target_entity,
+                                        },
+                                    );
+                                }
+                            }
+                            continue;
+                        }
+
+                        let pack = world.get_component::<Pack>(entity).map(|pack| *pack);
+                        if let Some((_, target_pos, _)) = target {
+                            memory.hunt(target_pos);
+                        } else {
+                            memory.turns_since_seen += 1;
+
+                            // Packs share what they have seen
+                            if let Some(sighting) =
+                                pack.and_then(|pack| pack_sightings.get(&pack.id))
+                            {
+                                memory.share(*sighting);
+                            }
+                        }
+
+                        // Archers and casters keep their distance and use their abilities
+                        if let Some((target_entity, target_pos, distance)) = target {
+                            if let Some(mut abilities) =
+                                world.get_component_mut_unchecked::<SpecialAbilities>(entity)
+                            {
+                                let keep_away =
+                                    abilities.abilities.iter().fold(0.0, |furthest, ability| {
+                                        f32::max(furthest, ability.min_range)
+                                    });
+                                let target_idx = map.xy_idx(target_pos.x, target_pos.y);
+                                if distance < keep_away
+                                    && flee_from(
+                                        &mut **map,
+                                        &mut *pos,
+                                        &mut *viewshed,
+                                        &[target_idx],
+                                    )
+                                {
+                                    continue;
+                                }
+
+                                let my_pos = Point::new(pos.x, pos.y);
+                                let mana = world
+                                    .get_component::<Pools>(entity)
+                                    .map_or(0, |pools| pools.mana.current);
+                                let usable = abilities.abilities.iter().position(|ability| {
+                                    ability.cooldown_left == 0
+                                        && ability.mana_cost <= mana
+                                        && distance <= ability.range
+                                        && line_of_fire(&**map, my_pos, target_pos, ability.range)
+                                });
+                                if let Some(idx) = usable {
+                                    let ability = &mut abilities.abilities[idx];
+                                    ability.cooldown_left = ability.cooldown;
+                                    if let Some(mut pools) =
+                                        world.get_component_mut_unchecked::<Pools>(entity)
+                                    {
+                                        pools.mana.current -= ability.mana_cost;
+                                    }
+                                    command_buffer.add_component(
+                                        entity,
+                                        WantsToUseAbility {
+                                            ability: idx,
+                                            target: target_entity,
+                                        },
+                                    );
+                                    continue;
+                                }
+                            }
+                        }
+
+                        if let Some(target_entity) = adjacent_target {
                             command_buffer.add_component(
                                 entity,
                                 WantsToMelee {
-                                    target: **player_entity,
+                                    target: target_entity,
                                 },
                             );
-                        } else if viewshed.visible_tiles.contains(&**player_pos) {
-                            // Path to the player
-                            if let Some((path, _cost)) =
-                                a_star_search(Point::new(pos.x, pos.y), **player_pos, 2., &**map)
-                            {
-                                if path.len() > 1 {
-                                    let mut idx = map.xy_idx(pos.x, pos.y);
-                                    map.blocked[idx] = false;
-                                    pos.x = path[1].x;
-                                    pos.y = path[1].y;
-                                    idx = map.xy_idx(pos.x, pos.y);
-                                    map.blocked[idx] = true;
-                                    viewshed.dirty = true;
+                        } else {
+                            match memory.state {
+                                AiState::Asleep | AiState::Fleeing => {}
+                                AiState::Idle => {
+                                    let mut wander =
+                                        world.get_component_mut_unchecked::<Wandering>(entity);
+                                    let post = world
+                                        .get_component::<Guarding>(entity)
+                                        .map(|guard| guard.post);
+                                    idle_movement(
+                                        &mut **map,
+                                        &mut *pos,
+                                        &mut *viewshed,
+                                        &mut **rng,
+                                        pack_leader(pack, &pack_leaders),
+                                        wander.as_deref_mut(),
+                                        post,
+                                    );
+                                }
+                                AiState::Hunting => {
+                                    // Head for where the target was last seen, with each
+                                    // member of a pack taking its own side
+                                    let surrounding = if pack.is_some() && target.is_some() {
+                                        surround_target(
+                                            &mut **map,
+                                            &mut *pos,
+                                            &mut *viewshed,
+                                            memory.last_seen,
+                                            &mut claimed_slots,
+                                        )
+                                    } else {
+                                        None
+                                    };
+                                    let moved = match surrounding {
+                                        Some(moved) => moved,
+                                        None => {
+                                            // Chasing the player follows the shared flow field
+                                            let step = flow_field
+                                                .origin()
+                                                .filter(|origin| *origin == memory.last_seen)
+                                                .and_then(|_| {
+                                                    flow_field
+                                                        .next_step(&**map, Point::new(pos.x, pos.y))
+                                                });
+                                            match step {
+                                                Some(step) => {
+                                                    step_to(
+                                                        &mut **map,
+                                                        &mut *pos,
+                                                        &mut *viewshed,
+                                                        step,
+                                                    );
+                                                    true
+                                                }
+                                                None => move_towards(
+                                                    &mut **map,
+                                                    &mut *pos,
+                                                    &mut *viewshed,
+                                                    memory.last_seen,
+                                                    2.0,
+                                                ),
+                                            }
+                                        }
+                                    };
+                                    let arrived = rltk::DistanceAlg::Pythagoras
+                                        .distance2d(Point::new(pos.x, pos.y), memory.last_seen)
+                                        < 1.5;
+                                    if arrived || !moved {
+                                        memory.state = AiState::Searching;
+                                        memory.turns_since_seen = 0;
+                                    } else if memory.turns_since_seen > HUNT_TURNS {
+                                        memory.state = AiState::Idle;
+                                    }
+                                }
+                                AiState::Searching => {
+                                    // Poke around near the last known position
+                                    let dx = rng.roll_dice(1, 3) - 2;
+                                    let dy = rng.roll_dice(1, 3) - 2;
+                                    let target = Point::new(pos.x + dx, pos.y + dy);
+                                    let in_range = rltk::DistanceAlg::Pythagoras
+                                        .distance2d(target, memory.last_seen)
+                                        <= SEARCH_RADIUS;
+                                    if in_range
+                                        && target.x >= 0
+                                        && target.x < map.width
+                                        && target.y >= 0
+                                        && target.y < map.height
+                                    {
+                                        let idx = map.xy_idx(target.x, target.y);
+                                        if !map.blocked[idx] {
+                                            let old_idx = map.xy_idx(pos.x, pos.y);
+                                            map.blocked[old_idx] = false;
+                                            pos.x = target.x;
+                                            pos.y = target.y;
+                                            map.blocked[idx] = true;
+                                            viewshed.dirty = true;
+                                        }
+                                    }
+
+                                    // Give up after a while
+                                    if memory.turns_since_seen > SEARCH_TURNS {
+                                        memory.state = AiState::Idle;
+                                    }
                                 }
                             }
                         }
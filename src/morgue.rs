@@ -0,0 +1,234 @@
+use super::{
+    clock_system::GameClock, gamelog::GameLog, hall_of_fame, level_up::SKILLS, skill_bonus,
+    storage, Attributes, Equipped, InBackpack, Map, Name, Perks, Pools, Position, RunStats, Skills,
+    TileType,
+};
+use legion::prelude::*;
+use std::{collections::HashMap, fmt::Write};
+
+// How much of the log makes it into the morgue file
+const LAST_MESSAGES: usize = 15;
+// Older morgue files are thrown away, so they don't pile up in the browser's storage
+#[cfg(target_arch = "wasm32")]
+const MAX_MORGUE_FILES: usize = 20;
+// Where the names of the kept morgue files are listed
+#[cfg(target_arch = "wasm32")]
+const MORGUE_INDEX_KEY: &str = "morgues.json";
+
+/// A few lines on how the run ended, for the game over screen and the top of the morgue file
+pub fn summary(world: &World, resources: &Resources) -> Vec<String> {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let name = world
+        .get_component::<Name>(player_entity)
+        .map_or("The adventurer".to_string(), |name| name.name.clone());
+    let level = world
+        .get_component::<Pools>(player_entity)
+        .map_or(1, |stats| stats.level);
+    let depth = resources.get::<Map>().map_or(1, |map| map.depth);
+    let turns = resources
+        .get::<GameClock>()
+        .map_or(0, |clock| clock.elapsed());
+    let stats = match world.get_component::<RunStats>(player_entity) {
+        Some(stats) => (*stats).clone(),
+        None => RunStats::new(),
+    };
+
+    let fate = if stats.victory {
+        format!(
+            "{}, level {}, returned to town with the prize from the deep!",
+            name, level
+        )
+    } else {
+        format!(
+            "{}, level {}, was killed by {} on depth {}.",
+            name,
+            level,
+            stats.last_hurt_by.as_deref().unwrap_or("misadventure"),
+            depth
+        )
+    };
+
+    vec![
+        fate,
+        format!(
+            "Survived {} turns and reached depth {}.",
+            turns, stats.max_depth
+        ),
+        format!(
+            "Slew {} foes, dealing {} damage and taking {}.",
+            stats.kills.values().sum::<i32>(),
+            stats.damage_dealt,
+            stats.damage_taken
+        ),
+        format!("Used {} items.", stats.items_used.values().sum::<i32>()),
+        format!(
+            "Final score: {}",
+            hall_of_fame::current_score(world, resources)
+        ),
+    ]
+}
+
+/// Writes everything worth remembering about the run to a timestamped text file
+pub fn write_morgue_file(world: &World, resources: &Resources) {
+    let filename = format!("morgue-{}.txt", storage::now());
+    storage::write(&filename, &morgue_text(world, resources));
+    #[cfg(target_arch = "wasm32")]
+    prune_morgue_files(filename);
+}
+
+/// Only keeps the latest morgue files, since the browser's storage is small and shared.
+/// On the desktop they're ordinary files, and the player can tidy them up as they like.
+#[cfg(target_arch = "wasm32")]
+fn prune_morgue_files(filename: String) {
+    let mut kept: Vec<String> = storage::read(MORGUE_INDEX_KEY)
+        .and_then(|data| serde_json::from_str(&data).ok())
+        .unwrap_or_default();
+    kept.retain(|name| *name != filename);
+    kept.push(filename);
+    while kept.len() > MAX_MORGUE_FILES {
+        storage::remove(&kept.remove(0));
+    }
+    match serde_json::to_string(&kept) {
+        Ok(data) => storage::write(MORGUE_INDEX_KEY, &data),
+        Err(err) => rltk::console::log(format!("Unable to save the morgue index: {}", err)),
+    }
+}
+
+fn morgue_text(world: &World, resources: &Resources) -> String {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let mut text = String::new();
+
+    for line in summary(world, resources).iter() {
+        writeln!(text, "{}", line).unwrap();
+    }
+
+    writeln!(text, "\n== Character ==").unwrap();
+    if let Some(stats) = world.get_component::<Pools>(player_entity) {
+        writeln!(
+            text,
+            "Level {} ({} xp), Health {}/{}, Mana {}/{}, Gold {}",
+            stats.level,
+            stats.experience,
+            stats.hit_points.current,
+            stats.hit_points.max,
+            stats.mana.current,
+            stats.mana.max,
+            stats.gold
+        )
+        .unwrap();
+    }
+    if let Some(attributes) = world.get_component::<Attributes>(player_entity) {
+        for (name, attribute) in [
+            ("Might", attributes.might),
+            ("Fitness", attributes.fitness),
+            ("Quickness", attributes.quickness),
+            ("Intelligence", attributes.intelligence),
+        ]
+        .iter()
+        {
+            writeln!(
+                text,
+                "{:<14}{:>3} ({:+})",
+                name,
+                attribute.base + attribute.modifiers,
+                attribute.bonus
+            )
+            .unwrap();
+        }
+    }
+    if let Some(skills) = world.get_component::<Skills>(player_entity) {
+        let skills: Vec<String> = SKILLS
+            .iter()
+            .map(|(skill, name)| format!("{} {:+}", name, skill_bonus(*skill, &skills)))
+            .collect();
+        writeln!(text, "Skills: {}", skills.join(", ")).unwrap();
+    }
+    if let Some(perks) = world.get_component::<Perks>(player_entity) {
+        if !perks.taken.is_empty() {
+            writeln!(text, "Perks: {}", perks.taken.join(", ")).unwrap();
+        }
+    }
+
+    writeln!(text, "\n== Equipment ==").unwrap();
+    for (equipped, name) in <(Read<Equipped>, Read<Name>)>::query().iter(world) {
+        if equipped.owner == player_entity {
+            writeln!(text, "{:?}: {}", equipped.slot, name.name).unwrap();
+        }
+    }
+    writeln!(text, "\n== Backpack ==").unwrap();
+    for (backpack, name) in <(Read<InBackpack>, Read<Name>)>::query().iter(world) {
+        if backpack.owner == player_entity {
+            writeln!(text, "{}", name.name).unwrap();
+        }
+    }
+
+    if let Some(stats) = world.get_component::<RunStats>(player_entity) {
+        writeln!(text, "\n== Kills ==").unwrap();
+        for (name, count) in sorted_counts(&stats.kills).iter() {
+            writeln!(text, "{:>4} {}", count, name).unwrap();
+        }
+        writeln!(text, "\n== Items used ==").unwrap();
+        for (name, count) in sorted_counts(&stats.items_used).iter() {
+            writeln!(text, "{:>4} {}", count, name).unwrap();
+        }
+    }
+
+    writeln!(text, "\n== Last messages ==").unwrap();
+    let log = resources.get::<GameLog>().unwrap();
+    let skip = log.entries.len().saturating_sub(LAST_MESSAGES);
+    for entry in log.entries.iter().skip(skip) {
+        writeln!(text, "{}", entry).unwrap();
+    }
+
+    if let Some(map) = resources.get::<Map>() {
+        writeln!(text, "\n== {} (depth {}) ==", map.name, map.depth).unwrap();
+        let player_pos = world
+            .get_component::<Position>(player_entity)
+            .map(|pos| *pos);
+        text.push_str(&map_snapshot(&map, player_pos));
+    }
+
+    text
+}
+
+/// Counts from most to least, ties broken by name so the file reads the same every time
+fn sorted_counts(counts: &HashMap<String, i32>) -> Vec<(String, i32)> {
+    let mut sorted: Vec<(String, i32)> = counts
+        .iter()
+        .map(|(name, count)| (name.clone(), *count))
+        .collect();
+    sorted.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(&b.0)));
+    sorted
+}
+
+/// The parts of the map the player had seen, as plain text
+fn map_snapshot(map: &Map, player_pos: Option<Position>) -> String {
+    let mut snapshot = String::new();
+    for y in 0..map.height {
+        let mut row = String::new();
+        for x in 0..map.width {
+            let idx = map.xy_idx(x, y);
+            let glyph = if player_pos.map_or(false, |pos| pos.x == x && pos.y == y) {
+                '@'
+            } else if !map.revealed_tiles[idx] {
+                ' '
+            } else {
+                match map.tiles[idx] {
+                    TileType::Wall => '#',
+                    TileType::Floor | TileType::WoodFloor => '.',
+                    TileType::DownStairs => '>',
+                    TileType::UpStairs => '<',
+                    TileType::Road | TileType::Gravel => ':',
+                    TileType::Grass => '"',
+                    TileType::ShallowWater => '~',
+                    TileType::DeepWater => '≈',
+                    TileType::Bridge => '=',
+                }
+            };
+            row.push(glyph);
+        }
+        snapshot.push_str(row.trim_end());
+        snapshot.push('\n');
+    }
+    snapshot
+}
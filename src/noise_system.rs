@@ -0,0 +1,68 @@
+use super::{Map, Monster, MonsterMemory, Position};
+use legion::prelude::*;
+use rltk::{DijkstraMap, Point};
+
+// How far (in steps) some common sounds carry
+pub const DOOR_NOISE: i32 = 6;
+pub const MELEE_NOISE: i32 = 8;
+pub const TRAP_NOISE: i32 = 10;
+pub const EXPLOSION_NOISE: i32 = 16;
+
+struct NoiseRequest {
+    x: i32,
+    y: i32,
+    loudness: i32,
+}
+
+pub struct NoiseBuilder {
+    requests: Vec<NoiseRequest>,
+}
+
+impl NoiseBuilder {
+    pub fn new() -> Self {
+        NoiseBuilder {
+            requests: Vec::new(),
+        }
+    }
+
+    pub fn request(&mut self, x: i32, y: i32, loudness: i32) {
+        self.requests.push(NoiseRequest { x, y, loudness })
+    }
+}
+
+pub fn build() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("noise")
+        .write_resource::<NoiseBuilder>()
+        .write_resource::<Map>()
+        .with_query(<(Read<Position>, Write<MonsterMemory>)>::query().filter(tag::<Monster>()))
+        .build(|_, world, (noise, map), query| {
+            if noise.requests.is_empty() {
+                return;
+            }
+
+            // Sound travels around creatures, but not through walls
+            let blocked = map.blocked.clone();
+            map.populate_blocked();
+
+            for request in noise.requests.drain(..) {
+                let source = Point::new(request.x, request.y);
+                let idx = map.xy_idx(source.x, source.y);
+                let sound = DijkstraMap::new(
+                    map.width as usize,
+                    map.height as usize,
+                    &[idx],
+                    &**map,
+                    request.loudness as f32,
+                );
+
+                for (pos, mut memory) in query.iter_mut(world) {
+                    let heard_at = sound.map[map.xy_idx(pos.x, pos.y)];
+                    if heard_at <= request.loudness as f32 {
+                        memory.investigate(source);
+                    }
+                }
+            }
+
+            map.blocked = blocked;
+        })
+}
@@ -1,11 +1,53 @@
 use super::{
-    activate_item, components::*, gamelog::GameLog, Map, RunState, State, TileType, Viewshed,
+    activate_item,
+    clock_system::GameClock,
+    components::*,
+    gamelog::GameLog,
+    gui,
+    inventory_system::{open_container, unlock_with_key},
+    noise_system::{NoiseBuilder, DOOR_NOISE},
+    perception_bonus,
+    quest_system::{quest_from, quest_on_offer, turn_in_quest},
+    skill_bonus, spell_system, FlowField, Map, RunState, State, TileType, Viewshed,
 };
+use crate::prefabs::{get_dialogue, reaction_towards, Reaction, PREFABS};
 use legion::prelude::*;
-use rltk::{Point, Rltk, VirtualKeyCode};
+use rltk::{Point, RandomNumberGenerator, Rltk, VirtualKeyCode};
 use std::cmp::{max, min};
 
-pub fn try_move_player(delta_x: i32, delta_y: i32, gs: &mut State) {
+pub fn try_move_player(mut delta_x: i32, mut delta_y: i32, gs: &mut State) {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+
+    // Confused players stumble in a random direction
+    let mut recovered = false;
+    if let Some(mut confusion) = gs.world.get_component_mut::<Confusion>(player_entity) {
+        let mut rng = gs.resources.get_mut::<RandomNumberGenerator>().unwrap();
+        let directions = [
+            (-1, -1),
+            (0, -1),
+            (1, -1),
+            (-1, 0),
+            (1, 0),
+            (-1, 1),
+            (0, 1),
+            (1, 1),
+        ];
+        let (dx, dy) = directions[(rng.roll_dice(1, 8) - 1) as usize];
+        delta_x = dx;
+        delta_y = dy;
+        confusion.turns -= 1;
+        recovered = confusion.turns < 1;
+    }
+    if recovered {
+        gs.world
+            .remove_component::<Confusion>(player_entity)
+            .expect("Cannot remove component");
+        let mut gamelog = gs.resources.get_mut::<GameLog>().unwrap();
+        gamelog
+            .entries
+            .push("You are no longer confused.".to_string());
+    }
+
     let map = gs.resources.get::<Map>().unwrap();
 
     let mut wants_to_melee = Vec::new();
@@ -27,6 +69,10 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, gs: &mut State) {
                 let bystander = gs.world.get_tag::<Bystander>(*potential_target);
                 let vendor = gs.world.get_tag::<Vendor>(*potential_target);
                 if bystander.is_some() || vendor.is_some() {
+                    // Shopkeepers won't make way, or do business, out of hours
+                    if vendor.is_some() && vendor_closed(gs, *potential_target) {
+                        continue;
+                    }
                     if let Some(mut target_position) = gs
                         .world
                         .get_component_mut_unchecked::<Position>(*potential_target)
@@ -40,9 +86,12 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, gs: &mut State) {
                     wants_to_melee.push((player_entity, *potential_target));
                 }
 
-                if let Some(_door) = gs.world.get_component::<Door>(*potential_target) {
-                    open_doors.push(*potential_target);
-                    viewshed.dirty = true;
+                if let Some(door) = gs.world.get_component::<Door>(*potential_target) {
+                    // Secret doors have to be found before they can be opened
+                    if !door.open && gs.world.get_tag::<Hidden>(*potential_target).is_none() {
+                        open_doors.push(*potential_target);
+                        viewshed.dirty = true;
+                    }
                 }
             }
 
@@ -70,6 +119,9 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, gs: &mut State) {
 
     // open doors
     for entity in open_doors.iter() {
+        if !unlock_with_key(&mut gs.world, &gs.resources, player_entity, *entity) {
+            continue;
+        }
         if let Some(mut door) = gs.world.get_component_mut::<Door>(*entity) {
             door.open = true;
         }
@@ -79,9 +131,15 @@ pub fn try_move_player(delta_x: i32, delta_y: i32, gs: &mut State) {
         gs.world
             .remove_tag::<BlocksTile>(*entity)
             .expect("Cannot remove BlocksTile tag");
+        gs.resources.get_mut::<FlowField>().unwrap().invalidate();
         if let Some(mut glyph) = gs.world.get_component_mut::<Renderable>(*entity) {
             glyph.glyph = rltk::to_cp437('/');
         }
+        // Creaking hinges carry through the halls
+        if let Some(pos) = gs.world.get_component::<Position>(*entity) {
+            let mut noise = gs.resources.get_mut::<NoiseBuilder>().unwrap();
+            noise.request(pos.x, pos.y, DOOR_NOISE);
+        }
     }
 }
 
@@ -100,10 +158,24 @@ pub fn try_next_level(resources: &mut Resources) -> bool {
     }
 }
 
-fn get_item(gs: &mut State) {
-    let player_pos = gs.resources.get::<Point>().unwrap();
-    let player_entity = gs.resources.get::<Entity>().unwrap();
-    let mut gamelog = gs.resources.get_mut::<GameLog>().unwrap();
+pub fn try_previous_level(resources: &mut Resources) -> bool {
+    let player_pos = resources.get::<Point>().unwrap();
+    let map = resources.get::<Map>().unwrap();
+    let player_idx = map.xy_idx(player_pos.x, player_pos.y);
+    if map.tiles[player_idx] == TileType::UpStairs {
+        true
+    } else {
+        let mut gamelog = resources.get_mut::<GameLog>().unwrap();
+        gamelog
+            .entries
+            .push("There is no way up from here.".to_string());
+        false
+    }
+}
+
+fn get_item(gs: &mut State) -> RunState {
+    let player_pos = *gs.resources.get::<Point>().unwrap();
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
 
     let mut target_item = None;
     let query = Read::<Position>::query().filter(tag::<Item>());
@@ -113,35 +185,343 @@ fn get_item(gs: &mut State) {
         }
     }
 
-    match target_item {
-        None => gamelog
-            .entries
-            .push("There is nothing here to pick up.".to_string()),
-        Some(item) => gs
-            .world
+    if let Some(item) = target_item {
+        gs.world
             .add_component(
-                *player_entity,
+                player_entity,
                 WantsToPickupItem {
-                    collected_by: *player_entity,
+                    collected_by: player_entity,
                     item,
                 },
             )
-            .expect("Unable to insert want to pickup"),
+            .expect("Unable to insert want to pickup");
+        return RunState::PlayerTurn;
+    }
+
+    // Nothing to pick up - maybe there is something to open
+    let mut target_container = None;
+    let query = <(Read<Position>, Read<Container>)>::query();
+    for (container_entity, (position, _container)) in query.iter_entities(&gs.world) {
+        if position.x == player_pos.x && position.y == player_pos.y {
+            target_container = Some(container_entity);
+        }
+    }
+
+    match target_container {
+        None => {
+            let mut gamelog = gs.resources.get_mut::<GameLog>().unwrap();
+            gamelog
+                .entries
+                .push("There is nothing here to pick up.".to_string());
+            RunState::PlayerTurn
+        }
+        Some(container) => open_container(&mut gs.world, &gs.resources, container),
     }
 }
 
+fn close_door(gs: &mut State) -> RunState {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+    let player_pos = *gs.resources.get::<Point>().unwrap();
+    let map = gs.resources.get::<Map>().unwrap();
+
+    // Find an open door next to the player with nothing standing in it
+    let mut target_door = None;
+    for y in player_pos.y - 1..=player_pos.y + 1 {
+        for x in player_pos.x - 1..=player_pos.x + 1 {
+            if (x == player_pos.x && y == player_pos.y)
+                || x < 0
+                || x > map.width - 1
+                || y < 0
+                || y > map.height - 1
+            {
+                continue;
+            }
+            let idx = map.xy_idx(x, y);
+            let mut door = None;
+            for entity in map.tile_content[idx].iter() {
+                if let Some(door_state) = gs.world.get_component::<Door>(*entity) {
+                    if door_state.open {
+                        door = Some(*entity);
+                    }
+                }
+            }
+            if door.is_some() && map.tile_content[idx].len() == 1 {
+                target_door = door;
+            }
+        }
+    }
+
+    let mut gamelog = gs.resources.get_mut::<GameLog>().unwrap();
+    match target_door {
+        None => {
+            gamelog
+                .entries
+                .push("There is no open door here to close.".to_string());
+        }
+        Some(entity) => {
+            if let Some(mut door) = gs.world.get_component_mut::<Door>(entity) {
+                door.open = false;
+            }
+            gs.world
+                .add_tag(entity, BlocksVisibility {})
+                .expect("Cannot add tag");
+            gs.world
+                .add_tag(entity, BlocksTile {})
+                .expect("Cannot add tag");
+            gs.resources.get_mut::<FlowField>().unwrap().invalidate();
+            if let Some(mut glyph) = gs.world.get_component_mut::<Renderable>(entity) {
+                glyph.glyph = rltk::to_cp437('+');
+            }
+            if let Some(mut viewshed) = gs.world.get_component_mut::<Viewshed>(player_entity) {
+                viewshed.dirty = true;
+            }
+            gamelog.entries.push("You close the door.".to_string());
+        }
+    }
+
+    RunState::PlayerTurn
+}
+
+fn pick_lock(gs: &mut State) -> RunState {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+    let player_pos = *gs.resources.get::<Point>().unwrap();
+    let mut gamelog = gs.resources.get_mut::<GameLog>().unwrap();
+
+    // Find something locked next to (or underneath) the player
+    let mut target = None;
+    let query = <(Read<Position>, Read<Lock>)>::query();
+    for (entity, (pos, _lock)) in query.iter_entities(&gs.world) {
+        if (pos.x - player_pos.x).abs() <= 1 && (pos.y - player_pos.y).abs() <= 1 {
+            target = Some(entity);
+        }
+    }
+    let target = match target {
+        None => {
+            gamelog
+                .entries
+                .push("There is nothing here to pick.".to_string());
+            return RunState::PlayerTurn;
+        }
+        Some(target) => target,
+    };
+
+    // Use the best tool the player carries
+    let mut tool: Option<(Entity, Lockpick)> = None;
+    let query = <(Read<Lockpick>, Read<InBackpack>)>::query();
+    for (entity, (lockpick, backpack)) in query.iter_entities(&gs.world) {
+        if backpack.owner == player_entity
+            && tool.map_or(true, |(_, best)| lockpick.bonus > best.bonus)
+        {
+            tool = Some((entity, *lockpick));
+        }
+    }
+    let (tool, lockpick) = match tool {
+        None => {
+            gamelog
+                .entries
+                .push("You have nothing to pick the lock with.".to_string());
+            return RunState::PlayerTurn;
+        }
+        Some(tool) => tool,
+    };
+
+    let difficulty = gs.world.get_component::<Lock>(target).unwrap().difficulty;
+    let quickness_bonus = match gs.world.get_component::<Attributes>(player_entity) {
+        Some(attributes) => attributes.quickness.bonus,
+        None => 0,
+    };
+    let lockpicking_bonus = match gs.world.get_component::<Skills>(player_entity) {
+        Some(skills) => skill_bonus(Skill::Lockpicking, &*skills),
+        None => -4,
+    };
+    let target_name = match gs.world.get_component::<Name>(target) {
+        Some(name) => name.name.clone(),
+        None => "-Unknown-".to_string(),
+    };
+    let tool_name = match gs.world.get_component::<Name>(tool) {
+        Some(name) => name.name.clone(),
+        None => "-Unknown-".to_string(),
+    };
+
+    let mut rng = gs.resources.get_mut::<RandomNumberGenerator>().unwrap();
+    let roll = rng.roll_dice(1, 20) + quickness_bonus + lockpicking_bonus + lockpick.bonus;
+    if roll >= difficulty {
+        gs.world
+            .remove_component::<Lock>(target)
+            .expect("Cannot remove component");
+        gamelog
+            .entries
+            .push(format!("You pick the lock on the {}.", target_name));
+    } else {
+        gamelog
+            .entries
+            .push(format!("You fail to pick the lock on the {}.", target_name));
+        if rng.roll_dice(1, 100) <= lockpick.break_chance {
+            gamelog.entries.push(format!("Your {} break!", tool_name));
+            gs.world.delete(tool);
+        }
+    }
+
+    RunState::PlayerTurn
+}
+
+fn search(gs: &mut State) -> RunState {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+    let mut map = gs.resources.get_mut::<Map>().unwrap();
+    let mut rng = gs.resources.get_mut::<RandomNumberGenerator>().unwrap();
+    let mut gamelog = gs.resources.get_mut::<GameLog>().unwrap();
+
+    let perception = match (
+        gs.world.get_component::<Attributes>(player_entity),
+        gs.world.get_component::<Skills>(player_entity),
+    ) {
+        (Some(attributes), Some(skills)) => perception_bonus(&attributes, &skills),
+        _ => 0,
+    };
+
+    let mut found = Vec::new();
+    if let Some(viewshed) = gs.world.get_component::<Viewshed>(player_entity) {
+        for tile in viewshed.visible_tiles.iter() {
+            let idx = map.xy_idx(tile.x, tile.y);
+            for entity in map.tile_content[idx].iter() {
+                if gs.world.get_tag::<Hidden>(*entity).is_some()
+                    && rng.roll_dice(1, 20) + perception >= 10
+                {
+                    found.push((*entity, idx));
+                }
+            }
+        }
+    }
+
+    if found.is_empty() {
+        gamelog
+            .entries
+            .push("You search, but find nothing.".to_string());
+    }
+    for (entity, idx) in found.iter() {
+        if let Some(name) = gs.world.get_component::<Name>(*entity) {
+            gamelog.entries.push(format!("You found a {}.", &name.name));
+        }
+        // Secret doors stop being walls once found
+        if gs.world.get_component::<Door>(*entity).is_some() {
+            map.tiles[*idx] = TileType::Floor;
+        }
+        gs.world
+            .remove_tag::<Hidden>(*entity)
+            .expect("Cannot remove Hidden tag");
+    }
+
+    RunState::PlayerTurn
+}
+
+/// Whether a vendor has shut up shop for the night, telling the player so if they have
+fn vendor_closed(gs: &State, vendor: Entity) -> bool {
+    if gs.resources.get::<GameClock>().unwrap().is_business_hours() {
+        return false;
+    }
+    if let Some(name) = gs.world.get_component::<Name>(vendor) {
+        gs.resources
+            .get_mut::<GameLog>()
+            .unwrap()
+            .entries
+            .push(format!(
+                "{} says \"We're closed, come back in the morning.\"",
+                name.name
+            ));
+    }
+    true
+}
+
+fn talk(gs: &mut State) -> RunState {
+    let player_entity = *gs.resources.get::<Entity>().unwrap();
+    let player_pos = *gs.resources.get::<Point>().unwrap();
+
+    // Find a townsperson next to the player
+    let mut target = None;
+    let query =
+        <(Read<Position>, Read<Name>)>::query().filter(tag::<Bystander>() | tag::<Vendor>());
+    for (entity, (pos, name)) in query.iter_entities(&gs.world) {
+        if (pos.x - player_pos.x).abs() <= 1 && (pos.y - player_pos.y).abs() <= 1 {
+            target = Some((entity, name.name.clone()));
+        }
+    }
+    let (target, name) = match target {
+        None => {
+            let mut gamelog = gs.resources.get_mut::<GameLog>().unwrap();
+            gamelog
+                .entries
+                .push("There is nobody here to talk to.".to_string());
+            return RunState::PlayerTurn;
+        }
+        Some(target) => target,
+    };
+
+    if gs.world.get_tag::<Vendor>(target).is_some() && vendor_closed(gs, target) {
+        return RunState::PlayerTurn;
+    }
+
+    // Anyone with a script does their own talking, starting from its first node
+    if get_dialogue(&PREFABS.lock().unwrap(), &name).is_some() {
+        return RunState::ShowDialogue {
+            speaker: target,
+            node: 0,
+        };
+    }
+
+    // Finished business comes first, then new work
+    if let Some(quest) = quest_from(&gs.world, player_entity, &name, QuestStatus::Complete) {
+        turn_in_quest(&mut gs.world, &gs.resources, &quest);
+        return RunState::PlayerTurn;
+    }
+    if quest_on_offer(&gs.world, player_entity, &name).is_some() {
+        return RunState::ShowQuestOffer { giver: target };
+    }
+    if gs.world.get_tag::<Vendor>(target).is_some() {
+        return RunState::ShowVendor {
+            vendor: target,
+            mode: gui::VendorMode::Buy,
+        };
+    }
+
+    let mut gamelog = gs.resources.get_mut::<GameLog>().unwrap();
+    if let Some(quest) = quest_from(&gs.world, player_entity, &name, QuestStatus::Active) {
+        gamelog
+            .entries
+            .push(format!("{} says \"How goes {}?\"", name, quest));
+    } else {
+        gamelog
+            .entries
+            .push(format!("{} has nothing to say to you.", name));
+    }
+
+    RunState::PlayerTurn
+}
+
 fn skip_turn(gs: &mut State) -> RunState {
     let player_entity = gs.resources.get::<Entity>().unwrap();
     let map = gs.resources.get::<Map>().unwrap();
 
     let mut can_heal = true;
     {
+        // No resting while anything hostile is in view
+        let pm = PREFABS.lock().unwrap();
         let viewshed = gs.world.get_component::<Viewshed>(*player_entity).unwrap();
+        let reputation = gs.world.get_component::<Reputation>(*player_entity);
+        let modifiers = gs.world.get_component::<Modifiers>(*player_entity);
         for tile in viewshed.visible_tiles.iter() {
             let idx = map.xy_idx(tile.x, tile.y);
             for entity in map.tile_content[idx].iter() {
-                if let Some(_monster) = gs.world.get_tag::<Monster>(*entity) {
-                    can_heal = false;
+                if let Some(faction) = gs.world.get_component::<Faction>(*entity) {
+                    if reaction_towards(
+                        &faction.name,
+                        "Player",
+                        reputation.as_deref(),
+                        modifiers.as_deref(),
+                        &pm,
+                    ) == Reaction::Attack
+                    {
+                        can_heal = false;
+                    }
                 }
             }
         }
@@ -207,6 +587,23 @@ pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
             return use_consumable_hotkey(gs, key - 1);
         }
     }
+    if ctx.control && ctx.key.is_some() {
+        let key = match ctx.key.unwrap() {
+            VirtualKeyCode::Key1 => Some(1),
+            VirtualKeyCode::Key2 => Some(2),
+            VirtualKeyCode::Key3 => Some(3),
+            VirtualKeyCode::Key4 => Some(4),
+            VirtualKeyCode::Key5 => Some(5),
+            VirtualKeyCode::Key6 => Some(6),
+            VirtualKeyCode::Key7 => Some(7),
+            VirtualKeyCode::Key8 => Some(8),
+            VirtualKeyCode::Key9 => Some(9),
+            _ => None,
+        };
+        if let Some(key) = key {
+            return spell_system::begin_casting(&mut gs.world, &gs.resources, key as usize - 1);
+        }
+    }
 
     // Player movement
     match ctx.key {
@@ -230,10 +627,18 @@ pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
             VirtualKeyCode::Numpad3 | VirtualKeyCode::N => try_move_player(1, 1, gs),
             VirtualKeyCode::Numpad1 | VirtualKeyCode::B => try_move_player(-1, 1, gs),
 
-            VirtualKeyCode::G => get_item(gs),
+            VirtualKeyCode::G => return get_item(gs),
             VirtualKeyCode::I => return RunState::ShowInventory,
             VirtualKeyCode::D => return RunState::ShowDropItem,
             VirtualKeyCode::R => return RunState::ShowRemoveItem,
+            VirtualKeyCode::C => return close_door(gs),
+            VirtualKeyCode::P => return pick_lock(gs),
+            VirtualKeyCode::S => return search(gs),
+            VirtualKeyCode::T => return talk(gs),
+            VirtualKeyCode::Q => return RunState::ShowQuestJournal,
+            VirtualKeyCode::X => return RunState::ShowSkills,
+            VirtualKeyCode::F => return RunState::ShowPerks { selection: 0 },
+            VirtualKeyCode::Z => return RunState::ShowSpells,
 
             VirtualKeyCode::Escape => return RunState::SaveGame,
 
@@ -243,6 +648,11 @@ pub fn player_input(gs: &mut State, ctx: &mut Rltk) -> RunState {
                     return RunState::NextLevel;
                 }
             }
+            VirtualKeyCode::Comma => {
+                if try_previous_level(&mut gs.resources) {
+                    return RunState::PreviousLevel;
+                }
+            }
 
             // Skip turn
             VirtualKeyCode::Numpad5 | VirtualKeyCode::Space => return skip_turn(gs),
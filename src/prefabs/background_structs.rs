@@ -0,0 +1,13 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+pub struct Background {
+    pub name: String,
+    pub description: String,
+    pub skills: Option<HashMap<String, i32>>,
+    pub equipped: Option<Vec<String>>,
+    pub carried: Option<Vec<String>>,
+    pub spells: Option<Vec<String>>,
+    pub gold: Option<i32>,
+}
@@ -0,0 +1,39 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Dialogue {
+    pub speaker: String,
+    pub nodes: Vec<DialogueNode>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DialogueNode {
+    pub name: String,
+    pub text: String,
+    pub choices: Vec<DialogueChoice>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct DialogueChoice {
+    pub text: String,
+    pub next: Option<String>,
+    pub conditions: Option<Vec<DialogueCondition>>,
+    pub effects: Option<Vec<DialogueEffect>>,
+}
+
+#[derive(Deserialize, Debug)]
+pub enum DialogueCondition {
+    Attribute { attribute: String, at_least: i32 },
+    HasItem(String),
+    Quest { quest: String, status: String },
+    Reaction { faction: String, reaction: String },
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum DialogueEffect {
+    GiveItem(String),
+    StartQuest(String),
+    TurnInQuest(String),
+    SetReaction { faction: String, reaction: String },
+    Trade,
+}
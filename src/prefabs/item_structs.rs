@@ -6,8 +6,16 @@ pub struct Item {
     pub name: String,
     pub renderable: Option<Renderable>,
     pub consumable: Option<Consumable>,
+    pub scroll: Option<bool>,
     pub weapon: Option<Weapon>,
     pub wearable: Option<Wearable>,
+    pub container: Option<Container>,
+    pub key: Option<bool>,
+    pub lockpick: Option<Lockpick>,
+    pub light: Option<Light>,
+    pub macguffin: Option<bool>,
+    pub base_value: Option<i32>,
+    pub vendor_category: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -18,6 +26,12 @@ pub struct Renderable {
     pub order: i32,
 }
 
+#[derive(Deserialize, Debug)]
+pub struct Light {
+    pub range: i32,
+    pub color: String,
+}
+
 #[derive(Deserialize, Debug)]
 pub struct Consumable {
     pub effects: HashMap<String, String>,
@@ -29,6 +43,7 @@ pub struct Weapon {
     pub attribute: String,
     pub base_damage: String,
     pub hit_bonus: i32,
+    pub category: Option<String>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -36,3 +51,15 @@ pub struct Wearable {
     pub armor_class: f32,
     pub slot: String,
 }
+
+#[derive(Deserialize, Debug)]
+pub struct Container {
+    pub loot_table: Option<String>,
+    pub rolls: Option<i32>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Lockpick {
+    pub bonus: i32,
+    pub break_chance: i32,
+}
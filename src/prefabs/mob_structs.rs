@@ -8,7 +8,12 @@ pub struct Mob {
     pub renderable: Option<Renderable>,
     pub blocks_tile: bool,
     pub vision_range: i32,
+    pub darkvision: Option<i32>,
     pub ai: String,
+    pub behavior: Option<String>,
+    pub faction: Option<String>,
+    pub morale: Option<i32>,
+    pub pack_tactics: Option<bool>,
     pub quips: Option<Vec<String>>,
     pub attributes: MobAttributes,
     pub skills: Option<HashMap<String, i32>>,
@@ -17,7 +22,10 @@ pub struct Mob {
     pub mana: Option<i32>,
     pub equipped: Option<Vec<String>>,
     pub natural: Option<MobNatural>,
+    pub abilities: Option<Vec<MobAbility>>,
     pub loot_table: Option<String>,
+    pub vendor: Option<Vec<String>>,
+    pub unique: Option<bool>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -40,3 +48,15 @@ pub struct NaturalAttack {
     pub hit_bonus: i32,
     pub damage: String,
 }
+
+#[derive(Deserialize, Debug)]
+pub struct MobAbility {
+    pub name: String,
+    pub kind: Option<String>,
+    pub range: f32,
+    pub min_range: Option<f32>,
+    pub mana: Option<i32>,
+    pub cooldown: Option<i32>,
+    pub damage: Option<String>,
+    pub confusion: Option<i32>,
+}
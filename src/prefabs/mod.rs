@@ -13,6 +13,21 @@ mod spawn_table_structs;
 use spawn_table_structs::*;
 mod loot_structs;
 use loot_structs::*;
+mod faction_structs;
+pub use faction_structs::Reaction;
+use faction_structs::*;
+mod quest_structs;
+use quest_structs::*;
+pub use quest_structs::{Quest, QuestObjective};
+mod dialogue_structs;
+use dialogue_structs::*;
+pub use dialogue_structs::{Dialogue, DialogueChoice, DialogueCondition, DialogueEffect};
+mod background_structs;
+pub use background_structs::Background;
+mod perk_structs;
+pub use perk_structs::{Perk, PerkEffect, PerkRequirements};
+mod spell_structs;
+pub use spell_structs::Spell;
 
 #[derive(Deserialize, Debug)]
 pub struct Prefabs {
@@ -21,6 +36,12 @@ pub struct Prefabs {
     pub mobs: Vec<Mob>,
     pub props: Vec<Prop>,
     pub loot_tables: Vec<LootTable>,
+    pub faction_table: Vec<FactionInfo>,
+    pub quests: Vec<Quest>,
+    pub dialogues: Vec<Dialogue>,
+    pub backgrounds: Vec<Background>,
+    pub perks: Vec<Perk>,
+    pub spells: Vec<Spell>,
 }
 
 lazy_static! {
@@ -0,0 +1,26 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+#[derive(Deserialize, Debug)]
+pub struct Perk {
+    pub name: String,
+    pub description: String,
+    pub requires: Option<PerkRequirements>,
+    pub effects: Vec<PerkEffect>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct PerkRequirements {
+    pub level: Option<i32>,
+    pub attributes: Option<HashMap<String, i32>>,
+    pub perks: Option<Vec<String>>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+pub enum PerkEffect {
+    MeleeHit { category: String, bonus: i32 },
+    KeepScroll(i32),
+    SlowerHunger(i32),
+    LightRadius(i32),
+    Reaction { faction: String, reaction: String },
+}
@@ -1,10 +1,17 @@
-use super::{Prefabs, SpawnTableEntry};
-use crate::{attr_bonus, components::*, mana_at_level, npc_hp, random_table::RandomTable};
+use super::{
+    Background, Dialogue, DialogueCondition, DialogueEffect, Perk, PerkEffect, Prefabs, Quest,
+    QuestObjective, Reaction, SpawnTableEntry, Spell,
+};
+use crate::{
+    attr_bonus, components::*, mana_at_level, npc_hp, random_table::RandomTable,
+    spawner::ATTRIBUTE_NAMES,
+};
 use legion::prelude::*;
 use regex::Regex;
 use std::collections::{HashMap, HashSet};
 
 const BASE_ATTRIBUTE: i32 = 11;
+const DEFAULT_MORALE: i32 = 10;
 
 pub fn parse_dice_string(dice: &str) -> (i32, i32, i32) {
     lazy_static! {
@@ -31,6 +38,7 @@ pub enum SpawnType {
     AtPosition { x: i32, y: i32 },
     Equipped { by: Entity },
     Carried { by: Entity },
+    Contained { by: Entity },
 }
 pub struct PrefabMaster {
     prefabs: Prefabs,
@@ -38,6 +46,11 @@ pub struct PrefabMaster {
     mob_index: HashMap<String, usize>,
     prop_index: HashMap<String, usize>,
     loot_index: HashMap<String, usize>,
+    faction_index: HashMap<String, HashMap<String, Reaction>>,
+    quest_index: HashMap<String, usize>,
+    dialogue_index: HashMap<String, usize>,
+    perk_index: HashMap<String, usize>,
+    spell_index: HashMap<String, usize>,
 }
 
 impl PrefabMaster {
@@ -49,11 +62,22 @@ impl PrefabMaster {
                 mobs: Vec::new(),
                 props: Vec::new(),
                 loot_tables: Vec::new(),
+                faction_table: Vec::new(),
+                quests: Vec::new(),
+                dialogues: Vec::new(),
+                backgrounds: Vec::new(),
+                perks: Vec::new(),
+                spells: Vec::new(),
             },
             item_index: HashMap::new(),
             mob_index: HashMap::new(),
             prop_index: HashMap::new(),
             loot_index: HashMap::new(),
+            faction_index: HashMap::new(),
+            quest_index: HashMap::new(),
+            dialogue_index: HashMap::new(),
+            perk_index: HashMap::new(),
+            spell_index: HashMap::new(),
         }
     }
 
@@ -96,6 +120,191 @@ impl PrefabMaster {
             self.loot_index.insert(loot.name.clone(), i);
         }
 
+        for faction in self.prefabs.faction_table.iter() {
+            let mut reactions: HashMap<String, Reaction> = HashMap::new();
+            for (other, response) in faction.responses.iter() {
+                reactions.insert(other.clone(), parse_reaction(response));
+            }
+            self.faction_index.insert(faction.name.clone(), reactions);
+        }
+
+        for (i, quest) in self.prefabs.quests.iter().enumerate() {
+            if !self.mob_index.contains_key(&quest.giver) {
+                rltk::console::log(format!(
+                    "WARNING - Quest [{}] is given by unspecified mob [{}]",
+                    quest.name, quest.giver
+                ));
+            }
+            let mut items = quest.reward.items.clone().unwrap_or_default();
+            if let QuestObjective::Retrieve { item, .. } = &quest.objective {
+                items.push(item.clone());
+            }
+            for item in items.iter() {
+                if !self.item_index.contains_key(item) {
+                    rltk::console::log(format!(
+                        "WARNING - Quest [{}] references unspecified item [{}]",
+                        quest.name, item
+                    ));
+                }
+            }
+            self.quest_index.insert(quest.name.clone(), i);
+        }
+
+        for (i, dialogue) in self.prefabs.dialogues.iter().enumerate() {
+            if !self.mob_index.contains_key(&dialogue.speaker) {
+                rltk::console::log(format!(
+                    "WARNING - Dialogue for unspecified mob [{}]",
+                    dialogue.speaker
+                ));
+            }
+            for choice in dialogue.nodes.iter().flat_map(|node| node.choices.iter()) {
+                if let Some(next) = &choice.next {
+                    if dialogue_node(dialogue, next).is_none() {
+                        rltk::console::log(format!(
+                            "WARNING - Dialogue for [{}] leads to unspecified node [{}]",
+                            dialogue.speaker, next
+                        ));
+                    }
+                }
+                let mut quests = Vec::new();
+                let mut items = Vec::new();
+                for condition in choice.conditions.iter().flatten() {
+                    match condition {
+                        DialogueCondition::HasItem(item) => items.push(item),
+                        DialogueCondition::Quest { quest, .. } => quests.push(quest),
+                        _ => {}
+                    }
+                }
+                for effect in choice.effects.iter().flatten() {
+                    match effect {
+                        DialogueEffect::GiveItem(item) => items.push(item),
+                        DialogueEffect::StartQuest(quest) | DialogueEffect::TurnInQuest(quest) => {
+                            quests.push(quest)
+                        }
+                        _ => {}
+                    }
+                }
+                for quest in quests {
+                    if !self.quest_index.contains_key(quest) {
+                        rltk::console::log(format!(
+                            "WARNING - Dialogue for [{}] references unspecified quest [{}]",
+                            dialogue.speaker, quest
+                        ));
+                    }
+                }
+                for item in items {
+                    if !self.item_index.contains_key(item) {
+                        rltk::console::log(format!(
+                            "WARNING - Dialogue for [{}] references unspecified item [{}]",
+                            dialogue.speaker, item
+                        ));
+                    }
+                }
+            }
+            self.dialogue_index.insert(dialogue.speaker.clone(), i);
+        }
+
+        for background in self.prefabs.backgrounds.iter() {
+            for skill in background.skills.iter().flat_map(|skills| skills.keys()) {
+                if string_to_skill(skill).is_none() {
+                    rltk::console::log(format!(
+                        "WARNING - Background [{}] references unknown skill [{}]",
+                        background.name, skill
+                    ));
+                }
+            }
+            let equipped = background.equipped.iter().flatten();
+            for item in equipped.chain(background.carried.iter().flatten()) {
+                if !self.item_index.contains_key(item) {
+                    rltk::console::log(format!(
+                        "WARNING - Background [{}] references unspecified item [{}]",
+                        background.name, item
+                    ));
+                }
+            }
+        }
+
+        for (i, perk) in self.prefabs.perks.iter().enumerate() {
+            self.perk_index.insert(perk.name.clone(), i);
+        }
+        for perk in self.prefabs.perks.iter() {
+            if let Some(requires) = &perk.requires {
+                for attribute in requires.attributes.iter().flat_map(|attrs| attrs.keys()) {
+                    if !ATTRIBUTE_NAMES
+                        .iter()
+                        .any(|name| name.eq_ignore_ascii_case(attribute))
+                    {
+                        rltk::console::log(format!(
+                            "WARNING - Perk [{}] requires unknown attribute [{}]",
+                            perk.name, attribute
+                        ));
+                    }
+                }
+                for required in requires.perks.iter().flatten() {
+                    if !self.perk_index.contains_key(required) {
+                        rltk::console::log(format!(
+                            "WARNING - Perk [{}] requires unspecified perk [{}]",
+                            perk.name, required
+                        ));
+                    }
+                }
+            }
+            for effect in perk.effects.iter() {
+                if let PerkEffect::MeleeHit { category, .. } = effect {
+                    if string_to_weapon_category(category).is_none() {
+                        rltk::console::log(format!(
+                            "WARNING - Perk [{}] references unknown weapon category [{}]",
+                            perk.name, category
+                        ));
+                    }
+                }
+            }
+        }
+
+        for (i, spell) in self.prefabs.spells.iter().enumerate() {
+            self.spell_index.insert(spell.name.clone(), i);
+        }
+        for item in self.prefabs.items.iter() {
+            if let Some(spell) = item
+                .consumable
+                .as_ref()
+                .and_then(|consumable| consumable.effects.get("teach_spell"))
+            {
+                if !self.spell_index.contains_key(spell) {
+                    rltk::console::log(format!(
+                        "WARNING - Item [{}] teaches unspecified spell [{}]",
+                        item.name, spell
+                    ));
+                }
+            }
+        }
+        for background in self.prefabs.backgrounds.iter() {
+            for spell in background.spells.iter().flatten() {
+                if !self.spell_index.contains_key(spell) {
+                    rltk::console::log(format!(
+                        "WARNING - Background [{}] knows unspecified spell [{}]",
+                        background.name, spell
+                    ));
+                }
+            }
+        }
+
+        for mob in self.prefabs.mobs.iter() {
+            for category in mob.vendor.iter().flatten() {
+                if !self
+                    .prefabs
+                    .items
+                    .iter()
+                    .any(|item| item.vendor_category.as_ref() == Some(category))
+                {
+                    rltk::console::log(format!(
+                        "WARNING - Vendor [{}] sells category [{}], but no item belongs to it",
+                        mob.name, category
+                    ));
+                }
+            }
+        }
+
         for spawn in self.prefabs.spawn_table.iter() {
             if !used_names.contains(&spawn.name) {
                 rltk::console::log(format!(
@@ -104,6 +313,18 @@ impl PrefabMaster {
                 ));
             }
         }
+
+        if !self
+            .prefabs
+            .items
+            .iter()
+            .any(|item| item.macguffin == Some(true))
+        {
+            rltk::console::log(
+                "WARNING - No item is marked as the macguffin, so the game cannot be won"
+                    .to_string(),
+            );
+        }
     }
 }
 
@@ -147,6 +368,37 @@ fn spawn_position(world: &mut World, entity: Entity, pos: SpawnType, tag: &str,
                 .add_component(entity, Equipped { owner: by, slot })
                 .expect("Cannot add component");
         }
+        SpawnType::Contained { by } => {
+            world
+                .add_component(entity, InContainer { owner: by })
+                .expect("Cannot add component");
+        }
+    }
+}
+
+fn spawn_container(world: &mut World, entity: Entity, container: &super::item_structs::Container) {
+    let loot_rolls = if let Some(table) = &container.loot_table {
+        world
+            .add_component(
+                entity,
+                LootTable {
+                    table: table.clone(),
+                },
+            )
+            .expect("Cannot add component");
+        container.rolls.unwrap_or(1)
+    } else {
+        0
+    };
+    world
+        .add_component(entity, Container { loot_rolls })
+        .expect("Cannot add component");
+}
+
+fn get_light_component(light: &super::item_structs::Light) -> LightSource {
+    LightSource {
+        color: rltk::RGB::from_hex(&light.color).expect("Invalid RGB"),
+        range: light.range,
     }
 }
 
@@ -159,6 +411,132 @@ fn get_renderable_component(renderable: &super::item_structs::Renderable) -> Ren
     }
 }
 
+/// Reads the number an effect is given, warning about anything that isn't one
+fn parse_effect_value(effect: &str, value: &str) -> Option<i32> {
+    match value.parse() {
+        Ok(number) => Some(number),
+        Err(_) => {
+            rltk::console::log(format!(
+                "WARNING - effect [{}] has a value that isn't a number [{}]",
+                effect, value
+            ));
+            None
+        }
+    }
+}
+
+/// Adds the components for a set of effects, as used by items, traps and spells
+fn spawn_effects(world: &mut World, entity: Entity, effects: &HashMap<String, String>) {
+    for (effect, value) in effects.iter() {
+        match effect.as_str() {
+            "provides_healing" => {
+                if let Some(heal_amount) = parse_effect_value(effect, value) {
+                    world
+                        .add_component(entity, ProvidesHealing { heal_amount })
+                        .expect("Cannot add component");
+                }
+            }
+            "ranged" => {
+                if let Some(range) = parse_effect_value(effect, value) {
+                    world
+                        .add_component(entity, Ranged { range })
+                        .expect("Cannot add component");
+                }
+            }
+            "damage" => {
+                if let Some(damage) = parse_effect_value(effect, value) {
+                    world
+                        .add_component(entity, InflictsDamage { damage })
+                        .expect("Cannot add component");
+                }
+            }
+            "area_of_effect" => {
+                if let Some(radius) = parse_effect_value(effect, value) {
+                    world
+                        .add_component(entity, AreaOfEffect { radius })
+                        .expect("Cannot add component");
+                }
+            }
+            "confusion" => {
+                if let Some(turns) = parse_effect_value(effect, value) {
+                    world
+                        .add_component(entity, Confusion { turns })
+                        .expect("Cannot add component");
+                }
+            }
+            "magic_mapping" => {
+                world
+                    .add_tag(entity, MagicMapper {})
+                    .expect("Cannot add tag");
+            }
+            "food" => {
+                world
+                    .add_tag(entity, ProvidesFood {})
+                    .expect("Cannot add tag");
+            }
+            "teach_spell" => {
+                world
+                    .add_component(
+                        entity,
+                        TeachesSpell {
+                            spell: value.clone(),
+                        },
+                    )
+                    .expect("Cannot add component");
+            }
+            "single_activation" => {
+                world
+                    .add_tag(entity, SingleActivation {})
+                    .expect("Cannot add tag");
+            }
+            "teleport" => {
+                world
+                    .add_tag(entity, TeleportsEntity {})
+                    .expect("Cannot add tag");
+            }
+            "trapdoor" => {
+                world.add_tag(entity, Trapdoor {}).expect("Cannot add tag");
+            }
+            "alarm" => {
+                if let Some(radius) = parse_effect_value(effect, value) {
+                    world
+                        .add_component(entity, Alarm { radius })
+                        .expect("Cannot add component");
+                }
+            }
+            "confusion_gas" => {
+                if let Some(potency) = parse_effect_value(effect, value) {
+                    world
+                        .add_component(
+                            entity,
+                            SpawnsGas {
+                                gas: GasType::Confusion,
+                                potency,
+                            },
+                        )
+                        .expect("Cannot add component");
+                }
+            }
+            "poison_gas" => {
+                if let Some(potency) = parse_effect_value(effect, value) {
+                    world
+                        .add_component(
+                            entity,
+                            SpawnsGas {
+                                gas: GasType::Poison,
+                                potency,
+                            },
+                        )
+                        .expect("Cannot add component");
+                }
+            }
+            effect_name => {
+                rltk::console::log(format!("Warning: effect {} not implemented.", effect_name));
+            }
+        }
+    }
+}
+
 pub fn spawn_named_entity(
     pm: &PrefabMaster,
     world: &mut World,
@@ -197,6 +575,26 @@ pub fn string_to_slot(slot: &str) -> EquipmentSlot {
     }
 }
 
+pub fn string_to_weapon_category(category: &str) -> Option<WeaponCategory> {
+    match category.to_lowercase().as_str() {
+        "blade" => Some(WeaponCategory::Blade),
+        "axe" => Some(WeaponCategory::Axe),
+        "blunt" => Some(WeaponCategory::Blunt),
+        _ => None,
+    }
+}
+
+pub fn string_to_skill(skill: &str) -> Option<Skill> {
+    match skill.to_lowercase().as_str() {
+        "melee" => Some(Skill::Melee),
+        "defense" => Some(Skill::Defense),
+        "magic" => Some(Skill::Magic),
+        "lockpicking" => Some(Skill::Lockpicking),
+        "perception" => Some(Skill::Perception),
+        _ => None,
+    }
+}
+
 pub fn spawn_named_item(
     pm: &PrefabMaster,
     world: &mut World,
@@ -225,77 +623,7 @@ pub fn spawn_named_item(
             world
                 .add_tag(entity, Consumable {})
                 .expect("Cannot add tag");
-
-            for (effect, value) in consumable.effects.iter() {
-                match effect.as_str() {
-                    "provides_healing" => {
-                        world
-                            .add_component(
-                                entity,
-                                ProvidesHealing {
-                                    heal_amount: value.parse().unwrap(),
-                                },
-                            )
-                            .expect("Cannot add component");
-                    }
-                    "ranged" => {
-                        world
-                            .add_component(
-                                entity,
-                                Ranged {
-                                    range: value.parse().unwrap(),
-                                },
-                            )
-                            .expect("Cannot add component");
-                    }
-                    "damage" => {
-                        world
-                            .add_component(
-                                entity,
-                                InflictsDamage {
-                                    damage: value.parse().unwrap(),
-                                },
-                            )
-                            .expect("Cannot add component");
-                    }
-                    "area_of_effect" => {
-                        world
-                            .add_component(
-                                entity,
-                                AreaOfEffect {
-                                    radius: value.parse().unwrap(),
-                                },
-                            )
-                            .expect("Cannot add component");
-                    }
-                    "confusion" => {
-                        world
-                            .add_component(
-                                entity,
-                                Confusion {
-                                    turns: value.parse().unwrap(),
-                                },
-                            )
-                            .expect("Cannot add component");
-                    }
-                    "magic_mapping" => {
-                        world
-                            .add_tag(entity, MagicMapper {})
-                            .expect("Cannot add tag");
-                    }
-                    "food" => {
-                        world
-                            .add_tag(entity, ProvidesFood {})
-                            .expect("Cannot add tag");
-                    }
-                    effect_name => {
-                        rltk::console::log(format!(
-                            "Warning: consumable effect {} not implemented.",
-                            effect_name
-                        ));
-                    }
-                }
-            }
+            spawn_effects(world, entity, &consumable.effects);
         }
 
         // Weapon
@@ -317,6 +645,7 @@ pub fn spawn_named_item(
                         damage_die_type: die_type,
                         damage_bonus: die_bonus,
                         hit_bonus: weapon.hit_bonus,
+                        category: None,
                     };
                     match weapon.attribute.to_lowercase().as_str() {
                         "quickness" => {
@@ -332,6 +661,15 @@ pub fn spawn_named_item(
                             ));
                         }
                     }
+                    if let Some(category) = &weapon.category {
+                        wpn.category = string_to_weapon_category(category);
+                        if wpn.category.is_none() {
+                            rltk::console::log(format!(
+                                "Warning: weapon category {} not implemented.",
+                                category
+                            ));
+                        }
+                    }
                     world
                         .add_component(entity, wpn)
                         .expect("Cannot add component");
@@ -362,6 +700,42 @@ pub fn spawn_named_item(
                 .expect("Cannot add component");
         }
 
+        // Bags and other carried containers
+        if let Some(container) = &item_template.container {
+            spawn_container(world, entity, container);
+        }
+
+        if let Some(key) = item_template.key {
+            if key {
+                // The key id is set when the level is populated
+                world
+                    .add_component(entity, Key { key_id: 0 })
+                    .expect("Cannot add component");
+            }
+        }
+        if let Some(light) = &item_template.light {
+            world
+                .add_component(entity, get_light_component(light))
+                .expect("Cannot add component");
+        }
+        if let Some(true) = item_template.scroll {
+            world.add_tag(entity, Scroll {}).expect("Cannot add tag");
+        }
+        if let Some(true) = item_template.macguffin {
+            world.add_tag(entity, MacGuffin {}).expect("Cannot add tag");
+        }
+        if let Some(lockpick) = &item_template.lockpick {
+            world
+                .add_component(
+                    entity,
+                    Lockpick {
+                        bonus: lockpick.bonus,
+                        break_chance: lockpick.break_chance,
+                    },
+                )
+                .expect("Cannot add component");
+        }
+
         return Some(entity);
     }
 
@@ -392,9 +766,20 @@ pub fn spawn_named_mob(
 
         spawn_position(world, entity, pos, key, pm);
 
+        if let Some(range) = mob_template.darkvision {
+            world
+                .add_component(entity, Darkvision { range })
+                .expect("Cannot add component");
+        }
+
         // AI Type
         match mob_template.ai.to_lowercase().as_str() {
-            "melee" => world.add_tag(entity, Monster {}).expect("Cannot add tag"),
+            "melee" => {
+                world.add_tag(entity, Monster {}).expect("Cannot add tag");
+                world
+                    .add_component(entity, MonsterMemory::new())
+                    .expect("Cannot add component");
+            }
             "bystander" => world.add_tag(entity, Bystander {}).expect("Cannot add tag"),
             "vendor" => world.add_tag(entity, Vendor {}).expect("Cannot add tag"),
             "carnivore" => world.add_tag(entity, Carnivore {}).expect("Cannot add tag"),
@@ -404,6 +789,68 @@ pub fn spawn_named_mob(
             }
         }
 
+        // Any mob can lose its nerve; melee mobs always can
+        let morale = match mob_template.ai.to_lowercase().as_str() {
+            "melee" => Some(mob_template.morale.unwrap_or(DEFAULT_MORALE)),
+            _ => mob_template.morale,
+        };
+        if let Some(morale) = morale {
+            world
+                .add_component(
+                    entity,
+                    Morale {
+                        max: morale,
+                        current: morale,
+                    },
+                )
+                .expect("Cannot add component");
+        }
+
+        // Pack hunters remember their prey and join up with their group, whatever their AI
+        if mob_template.pack_tactics == Some(true)
+            && world.get_component::<MonsterMemory>(entity).is_none()
+        {
+            world
+                .add_component(entity, MonsterMemory::new())
+                .expect("Cannot add component");
+        }
+
+        // Who the mob fights, flees from or ignores
+        let faction = match &mob_template.faction {
+            Some(faction) => faction.clone(),
+            None => "Mindless".to_string(),
+        };
+        world
+            .add_component(entity, Faction { name: faction })
+            .expect("Cannot add component");
+
+        // What the mob gets up to before it notices the player
+        if let Some(behavior) = &mob_template.behavior {
+            match behavior.to_lowercase().as_str() {
+                "asleep" => {
+                    if let Some(mut memory) = world.get_component_mut::<MonsterMemory>(entity) {
+                        memory.state = AiState::Asleep;
+                    }
+                }
+                "wander" => world
+                    .add_component(entity, Wandering { destination: None })
+                    .expect("Cannot add component"),
+                "guard" => {
+                    let post = world
+                        .get_component::<Position>(entity)
+                        .map(|pos| rltk::Point::new(pos.x, pos.y));
+                    if let Some(post) = post {
+                        world
+                            .add_component(entity, Guarding { post })
+                            .expect("Cannot add component");
+                    }
+                }
+                behavior => {
+                    rltk::console::log(format!("Warning: Behavior {} not implemented.", behavior));
+                }
+            }
+        }
+
         if let Some(renderable) = &mob_template.renderable {
             world
                 .add_component(entity, get_renderable_component(renderable))
@@ -490,6 +937,7 @@ pub fn spawn_named_mob(
                 Pools {
                     level,
                     experience: 0,
+                    gold: 0,
                     hit_points: Pool {
                         current: hit_points,
                         max: hit_points,
@@ -520,6 +968,12 @@ pub fn spawn_named_mob(
                     "magic" => {
                         skills.skills.insert(Skill::Melee, *sk.1);
                     }
+                    "lockpicking" => {
+                        skills.skills.insert(Skill::Lockpicking, *sk.1);
+                    }
+                    "perception" => {
+                        skills.skills.insert(Skill::Perception, *sk.1);
+                    }
                     _ => {
                         rltk::console::log(format!("Unknown skill referenced: \"{}\"", sk.0));
                     }
@@ -572,6 +1026,48 @@ pub fn spawn_named_mob(
                 .expect("Cannot add component");
         }
 
+        if let Some(abilities) = &mob_template.abilities {
+            let mut special = SpecialAbilities {
+                abilities: Vec::new(),
+            };
+            for ability in abilities.iter() {
+                let (damage_n_dice, damage_die_type, damage_bonus) = match &ability.damage {
+                    Some(damage) => parse_dice_string(damage),
+                    None => (0, 0, 0),
+                };
+                // Anything that costs mana is a spell, unless the prefab says otherwise
+                let kind = match ability.kind.as_deref().map(|kind| kind.to_lowercase()) {
+                    Some(kind) if kind == "missile" => AbilityKind::Missile,
+                    Some(kind) if kind == "spell" => AbilityKind::Spell,
+                    Some(kind) => {
+                        rltk::console::log(format!(
+                            "Warning: ability kind {} not implemented.",
+                            kind
+                        ));
+                        AbilityKind::Missile
+                    }
+                    None if ability.mana.unwrap_or(0) > 0 => AbilityKind::Spell,
+                    None => AbilityKind::Missile,
+                };
+                special.abilities.push(SpecialAbility {
+                    name: ability.name.clone(),
+                    kind,
+                    range: ability.range,
+                    min_range: ability.min_range.unwrap_or(0.0),
+                    mana_cost: ability.mana.unwrap_or(0),
+                    cooldown: ability.cooldown.unwrap_or(0),
+                    cooldown_left: 0,
+                    damage_n_dice,
+                    damage_die_type,
+                    damage_bonus,
+                    confusion_turns: ability.confusion.unwrap_or(0),
+                });
+            }
+            world
+                .add_component(entity, special)
+                .expect("Cannot add component");
+        }
+
         if mob_template.blocks_tile {
             world
                 .add_tag(entity, BlocksTile {})
@@ -636,31 +1132,27 @@ pub fn spawn_named_prop(
             world
                 .add_tag(entity, EntryTrigger {})
                 .expect("Cannot add tag");
-            for (effect, value) in entry_trigger.effects.iter() {
-                match effect.as_str() {
-                    "damage" => {
-                        world
-                            .add_component(
-                                entity,
-                                InflictsDamage {
-                                    damage: value.parse().unwrap(),
-                                },
-                            )
-                            .expect("Cannot add component");
-                    }
-                    "single_activation" => {
-                        world
-                            .add_tag(entity, SingleActivation {})
-                            .expect("Cannot add tag");
-                    }
-                    effect_name => {
-                        rltk::console::log(format!(
-                            "Warning: consumable effect {} not implemented.",
-                            effect_name
-                        ));
-                    }
-                }
-            }
+            spawn_effects(world, entity, &entry_trigger.effects);
+        }
+        if let Some(container) = &prop_template.container {
+            spawn_container(world, entity, container);
+        }
+        if let Some(light) = &prop_template.light {
+            world
+                .add_component(entity, get_light_component(light))
+                .expect("Cannot add component");
+        }
+        if let Some(lock) = &prop_template.lock {
+            // The key id is set when the level is populated
+            world
+                .add_component(
+                    entity,
+                    Lock {
+                        key_id: 0,
+                        difficulty: lock.difficulty,
+                    },
+                )
+                .expect("Cannot add component");
         }
 
         return Some(entity);
@@ -689,6 +1181,15 @@ pub fn get_spawn_table_for_depth(pm: &PrefabMaster, depth: i32) -> RandomTable {
     rt
 }
 
+pub fn spawn_group_size(pm: &PrefabMaster, name: &str) -> Option<(i32, i32, i32)> {
+    pm.prefabs
+        .spawn_table
+        .iter()
+        .find(|entry| entry.name == name)
+        .and_then(|entry| entry.group_size.as_ref())
+        .map(|dice| parse_dice_string(dice))
+}
+
 pub fn get_item_drop(
     pm: &PrefabMaster,
     rng: &mut rltk::RandomNumberGenerator,
@@ -705,3 +1206,127 @@ pub fn get_item_drop(
 
     None
 }
+
+pub fn parse_reaction(response: &str) -> Reaction {
+    match response.to_lowercase().as_str() {
+        "attack" => Reaction::Attack,
+        "flee" => Reaction::Flee,
+        _ => Reaction::Ignore,
+    }
+}
+
+pub fn faction_reaction(my_faction: &str, their_faction: &str, pm: &PrefabMaster) -> Reaction {
+    if let Some(mine) = pm.faction_index.get(my_faction) {
+        if let Some(reaction) = mine.get(their_faction) {
+            return *reaction;
+        } else if let Some(reaction) = mine.get("Default") {
+            return *reaction;
+        }
+    }
+    Reaction::Ignore
+}
+
+/// As `faction_reaction`, but letting the player's reputation override how a faction treats them
+pub fn reaction_towards(
+    my_faction: &str,
+    their_faction: &str,
+    reputation: Option<&Reputation>,
+    modifiers: Option<&Modifiers>,
+    pm: &PrefabMaster,
+) -> Reaction {
+    // Deals struck in conversation come first, then perks, then the faction table
+    if let Some(reaction) = reputation.and_then(|reputation| reputation.reactions.get(my_faction)) {
+        return *reaction;
+    }
+    match modifiers.and_then(|modifiers| modifiers.reactions.get(my_faction)) {
+        Some(reaction) => *reaction,
+        None => faction_reaction(my_faction, their_faction, pm),
+    }
+}
+
+pub fn get_quest<'a>(pm: &'a PrefabMaster, name: &str) -> Option<&'a Quest> {
+    pm.quest_index.get(name).map(|idx| &pm.prefabs.quests[*idx])
+}
+
+pub fn quests_offered_by<'a>(pm: &'a PrefabMaster, giver: &str) -> Vec<&'a Quest> {
+    pm.prefabs
+        .quests
+        .iter()
+        .filter(|quest| quest.giver == giver)
+        .collect()
+}
+
+pub fn get_dialogue<'a>(pm: &'a PrefabMaster, speaker: &str) -> Option<&'a Dialogue> {
+    pm.dialogue_index
+        .get(speaker)
+        .map(|idx| &pm.prefabs.dialogues[*idx])
+}
+
+pub fn dialogue_node(dialogue: &Dialogue, name: &str) -> Option<usize> {
+    dialogue.nodes.iter().position(|node| node.name == name)
+}
+
+/// Bosses and the macguffin exist at most once per run
+pub fn is_unique(pm: &PrefabMaster, name: &str) -> bool {
+    if let Some(idx) = pm.item_index.get(name) {
+        return pm.prefabs.items[*idx].macguffin == Some(true);
+    }
+    if let Some(idx) = pm.mob_index.get(name) {
+        return pm.prefabs.mobs[*idx].unique == Some(true);
+    }
+    false
+}
+
+/// Everything a vendor sells, with its price
+pub fn get_vendor_items(pm: &PrefabMaster, vendor: &str) -> Vec<(String, i32)> {
+    let categories = match pm.mob_index.get(vendor) {
+        Some(idx) => pm.prefabs.mobs[*idx].vendor.clone().unwrap_or_default(),
+        None => return Vec::new(),
+    };
+    pm.prefabs
+        .items
+        .iter()
+        .filter(|item| {
+            item.vendor_category
+                .as_ref()
+                .map_or(false, |category| categories.contains(category))
+        })
+        .map(|item| (item.name.clone(), item.base_value.unwrap_or(0)))
+        .collect()
+}
+
+/// What an item is worth, if anything
+pub fn item_value(pm: &PrefabMaster, name: &str) -> Option<i32> {
+    pm.item_index
+        .get(name)
+        .and_then(|idx| pm.prefabs.items[*idx].base_value)
+}
+
+pub fn backgrounds(pm: &PrefabMaster) -> &[Background] {
+    &pm.prefabs.backgrounds
+}
+
+pub fn get_perk<'a>(pm: &'a PrefabMaster, name: &str) -> Option<&'a Perk> {
+    pm.perk_index.get(name).map(|idx| &pm.prefabs.perks[*idx])
+}
+
+pub fn perks(pm: &PrefabMaster) -> &[Perk] {
+    &pm.prefabs.perks
+}
+
+pub fn get_spell<'a>(pm: &'a PrefabMaster, name: &str) -> Option<&'a Spell> {
+    pm.spell_index.get(name).map(|idx| &pm.prefabs.spells[*idx])
+}
+
+/// Spells are cast as a one-off item that only exists until its effects are applied
+pub fn spawn_named_spell(pm: &PrefabMaster, world: &mut World, key: &str) -> Option<Entity> {
+    let spell_template = get_spell(pm, key)?;
+    let entity = world.insert(
+        (Consumable,),
+        vec![(Name {
+            name: spell_template.name.clone(),
+        },)],
+    )[0];
+    spawn_effects(world, entity, &spell_template.effects);
+    Some(entity)
+}
@@ -1,4 +1,4 @@
-use super::Renderable;
+use super::{Container, Light, Renderable};
 use serde::Deserialize;
 use std::collections::HashMap;
 
@@ -11,6 +11,14 @@ pub struct Prop {
     pub blocks_visibility: Option<bool>,
     pub door_open: Option<bool>,
     pub entry_trigger: Option<EntryTrigger>,
+    pub container: Option<Container>,
+    pub lock: Option<Lock>,
+    pub light: Option<Light>,
+}
+
+#[derive(Deserialize, Debug)]
+pub struct Lock {
+    pub difficulty: i32,
 }
 
 #[derive(Deserialize, Debug)]
@@ -0,0 +1,24 @@
+use serde::Deserialize;
+
+#[derive(Deserialize, Debug)]
+pub struct Quest {
+    pub name: String,
+    pub giver: String,
+    pub description: String,
+    pub objective: QuestObjective,
+    pub reward: QuestReward,
+}
+
+#[derive(Deserialize, Debug)]
+pub enum QuestObjective {
+    Kill { mob: String, count: i32 },
+    Retrieve { item: String, depth: i32 },
+    Explore { depth: i32 },
+}
+
+#[derive(Deserialize, Debug)]
+pub struct QuestReward {
+    pub xp: Option<i32>,
+    pub gold: Option<i32>,
+    pub items: Option<Vec<String>>,
+}
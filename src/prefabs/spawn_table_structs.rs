@@ -7,4 +7,5 @@ pub struct SpawnTableEntry {
     pub min_depth: i32,
     pub max_depth: i32,
     pub add_map_depth_to_weight: Option<bool>,
+    pub group_size: Option<String>,
 }
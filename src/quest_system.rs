@@ -0,0 +1,288 @@
+use super::{
+    damage_system::{gain_experience, level_up_particles},
+    gamelog::GameLog,
+    particle_system::ParticleBuilder,
+    Attributes, Equipped, InBackpack, Name, Pools, QuestLog, QuestProgress, QuestStatus,
+    UnspentPoints,
+};
+use crate::prefabs::{
+    get_quest, quests_offered_by, spawn_named_item, Quest, QuestObjective, SpawnType, PREFABS,
+};
+use legion::prelude::*;
+use rltk::Point;
+
+/// Something the player did that might move a quest along
+pub enum QuestEvent {
+    Kill(String),
+    Pickup(String),
+    Depth(i32),
+}
+
+pub struct QuestEvents {
+    events: Vec<QuestEvent>,
+}
+
+impl QuestEvents {
+    pub fn new() -> Self {
+        QuestEvents { events: Vec::new() }
+    }
+
+    pub fn record(&mut self, event: QuestEvent) {
+        self.events.push(event);
+    }
+}
+
+pub fn build() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("quest")
+        .write_resource::<QuestEvents>()
+        .read_resource::<Entity>()
+        .write_resource::<GameLog>()
+        .write_component::<QuestLog>()
+        .build(|_, world, (quest_events, player_entity, log), _| {
+            if quest_events.events.is_empty() {
+                return;
+            }
+
+            let pm = PREFABS.lock().unwrap();
+            let mut quest_log = match world.get_component_mut::<QuestLog>(**player_entity) {
+                Some(quest_log) => quest_log,
+                None => {
+                    quest_events.events.clear();
+                    return;
+                }
+            };
+
+            for event in quest_events.events.drain(..) {
+                for progress in quest_log
+                    .quests
+                    .iter_mut()
+                    .filter(|progress| progress.status == QuestStatus::Active)
+                {
+                    let quest = match get_quest(&pm, &progress.name) {
+                        Some(quest) => quest,
+                        None => continue,
+                    };
+
+                    let done = match (&quest.objective, &event) {
+                        (QuestObjective::Kill { mob, count }, QuestEvent::Kill(name))
+                            if mob == name =>
+                        {
+                            progress.progress += 1;
+                            if progress.progress < *count {
+                                log.entries.push(format!(
+                                    "{}: {}/{} {} slain.",
+                                    quest.name, progress.progress, count, mob
+                                ));
+                            }
+                            progress.progress >= *count
+                        }
+                        (QuestObjective::Retrieve { item, .. }, QuestEvent::Pickup(name)) => {
+                            item == name
+                        }
+                        (QuestObjective::Explore { depth }, QuestEvent::Depth(reached)) => {
+                            reached >= depth
+                        }
+                        _ => false,
+                    };
+
+                    if done {
+                        progress.status = QuestStatus::Complete;
+                        log.entries.push(format!(
+                            "Quest complete: {}. Return to the {} for your reward.",
+                            quest.name, quest.giver
+                        ));
+                    }
+                }
+            }
+        })
+}
+
+pub fn describe_objective(quest: &Quest) -> String {
+    match &quest.objective {
+        QuestObjective::Kill { mob, count } => format!("Slay {} x {}", mob, count),
+        QuestObjective::Retrieve { item, depth } => {
+            format!("Bring back the {} from depth {}", item, depth)
+        }
+        QuestObjective::Explore { depth } => format!("Reach depth {}", depth),
+    }
+}
+
+pub fn describe_reward(quest: &Quest) -> String {
+    let mut reward = Vec::new();
+    if let Some(xp) = quest.reward.xp {
+        reward.push(format!("{} xp", xp));
+    }
+    if let Some(gold) = quest.reward.gold {
+        reward.push(format!("{} gold", gold));
+    }
+    if let Some(items) = &quest.reward.items {
+        reward.extend(items.iter().cloned());
+    }
+    reward.join(", ")
+}
+
+/// The quest with the given status that the player holds from this giver, if any
+pub fn quest_from(
+    world: &World,
+    player: Entity,
+    giver: &str,
+    status: QuestStatus,
+) -> Option<String> {
+    let pm = PREFABS.lock().unwrap();
+    let quest_log = world.get_component::<QuestLog>(player)?;
+    quest_log
+        .quests
+        .iter()
+        .filter(|progress| progress.status == status)
+        .find(|progress| match get_quest(&pm, &progress.name) {
+            Some(quest) => quest.giver == giver,
+            None => false,
+        })
+        .map(|progress| progress.name.clone())
+}
+
+/// The next quest this giver has that the player hasn't taken yet
+pub fn quest_on_offer(world: &World, player: Entity, giver: &str) -> Option<String> {
+    let pm = PREFABS.lock().unwrap();
+    let quest_log = world.get_component::<QuestLog>(player)?;
+    quests_offered_by(&pm, giver)
+        .iter()
+        .find(|quest| {
+            !quest_log
+                .quests
+                .iter()
+                .any(|progress| progress.name == quest.name)
+        })
+        .map(|quest| quest.name.clone())
+}
+
+/// Items that active quests expect to be found on this level
+pub fn quest_items_for_depth(world: &World, player: Entity, depth: i32) -> Vec<String> {
+    let pm = PREFABS.lock().unwrap();
+    let quest_log = match world.get_component::<QuestLog>(player) {
+        Some(quest_log) => quest_log,
+        None => return Vec::new(),
+    };
+
+    quest_log
+        .quests
+        .iter()
+        .filter(|progress| progress.status == QuestStatus::Active)
+        .filter_map(|progress| get_quest(&pm, &progress.name))
+        .filter_map(|quest| match &quest.objective {
+            QuestObjective::Retrieve { item, depth: at } if *at == depth => Some(item.clone()),
+            _ => None,
+        })
+        .collect()
+}
+
+pub fn accept_quest(world: &mut World, resources: &Resources, name: &str) {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    if let Some(mut quest_log) = world.get_component_mut::<QuestLog>(player_entity) {
+        quest_log.quests.push(QuestProgress {
+            name: name.to_string(),
+            progress: 0,
+            status: QuestStatus::Active,
+        });
+    }
+    resources
+        .get_mut::<GameLog>()
+        .unwrap()
+        .entries
+        .push(format!("Quest accepted: {}.", name));
+}
+
+/// Hands a completed quest back to whoever gave it, collecting the reward
+pub fn turn_in_quest(world: &mut World, resources: &Resources, name: &str) {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let pm = PREFABS.lock().unwrap();
+    let quest = match get_quest(&pm, name) {
+        Some(quest) => quest,
+        None => return,
+    };
+    let mut log = resources.get_mut::<GameLog>().unwrap();
+
+    // Nothing is paid out for something we no longer have
+    let mut handed_over = None;
+    if let QuestObjective::Retrieve { item, .. } = &quest.objective {
+        let query = <(Read<InBackpack>, Read<Name>)>::query();
+        handed_over = query
+            .iter_entities(world)
+            .find(|(_entity, (backpack, item_name))| {
+                backpack.owner == player_entity && item_name.name == *item
+            })
+            .map(|(entity, _)| entity);
+        if handed_over.is_none() {
+            // The item may well be in the player's hands rather than their pack
+            let query = <(Read<Equipped>, Read<Name>)>::query();
+            handed_over = query
+                .iter_entities(world)
+                .find(|(_entity, (equipped, item_name))| {
+                    equipped.owner == player_entity && item_name.name == *item
+                })
+                .map(|(entity, _)| entity);
+            if let Some(entity) = handed_over {
+                world
+                    .remove_component::<Equipped>(entity)
+                    .expect("Cannot remove component");
+                log.entries.push(format!("You unequip the {}.", item));
+            }
+        }
+        if handed_over.is_none() {
+            log.entries.push(format!(
+                "The {} wants the {}, but you aren't carrying it.",
+                quest.giver, item
+            ));
+            return;
+        }
+    }
+
+    if let Some(mut quest_log) = world.get_component_mut::<QuestLog>(player_entity) {
+        for progress in quest_log.quests.iter_mut() {
+            if progress.name == name {
+                progress.status = QuestStatus::TurnedIn;
+            }
+        }
+    }
+    log.entries.push(format!(
+        "You hand in {} to the {}.",
+        quest.name, quest.giver
+    ));
+
+    // The giver keeps whatever they sent us to fetch
+    if let Some(entity) = handed_over {
+        world.delete(entity);
+    }
+
+    if let Some(gold) = quest.reward.gold {
+        if let Some(mut stats) = world.get_component_mut::<Pools>(player_entity) {
+            stats.gold += gold;
+        }
+        log.entries.push(format!("You receive {} gold.", gold));
+    }
+
+    if let Some(items) = &quest.reward.items {
+        for item in items.iter() {
+            spawn_named_item(&pm, world, item, SpawnType::Carried { by: player_entity });
+            log.entries.push(format!("You receive the {}.", item));
+        }
+    }
+
+    if let Some(xp) = quest.reward.xp {
+        log.entries.push(format!("You gain {} experience.", xp));
+        let attributes = *world.get_component::<Attributes>(player_entity).unwrap();
+        let mut points = *world.get_component::<UnspentPoints>(player_entity).unwrap();
+        let mut stats = world.get_component_mut::<Pools>(player_entity).unwrap();
+        if gain_experience(&mut stats, &attributes, &mut points, xp, &mut log) {
+            std::mem::drop(stats);
+            *world
+                .get_component_mut::<UnspentPoints>(player_entity)
+                .unwrap() = points;
+            let player_pos = *resources.get::<Point>().unwrap();
+            level_up_particles(
+                &mut resources.get_mut::<ParticleBuilder>().unwrap(),
+                player_pos,
+            );
+        }
+    }
+}
@@ -0,0 +1,159 @@
+use super::{
+    ability_attack_bonus, armor_class, gamelog::GameLog, particle_system::ParticleBuilder,
+    Attributes, Confusion, Equipped, Name, NaturalAttackDefense, Pools, Position, RunStats, Skills,
+    SpecialAbilities, SufferDamage, WantsToUseAbility, Wearable,
+};
+use legion::prelude::*;
+use rltk::RandomNumberGenerator;
+
+pub fn build() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("ranged_combat")
+        .with_query(<(Read<WantsToUseAbility>, Read<SpecialAbilities>)>::query())
+        .with_query(<(Read<Wearable>, Read<Equipped>)>::query())
+        .read_component::<Name>()
+        .read_component::<Position>()
+        .read_component::<Pools>()
+        .read_component::<Attributes>()
+        .read_component::<Skills>()
+        .read_component::<NaturalAttackDefense>()
+        .write_resource::<GameLog>()
+        .write_resource::<ParticleBuilder>()
+        .write_resource::<RandomNumberGenerator>()
+        .read_resource::<Entity>()
+        .build(
+            |command_buffer,
+             world,
+             (log, particle_builder, rng, player_entity),
+             (query, query_defense)| {
+                for (entity, (wants_ability, abilities)) in query.iter_entities(world) {
+                    command_buffer.remove_component::<WantsToUseAbility>(entity);
+
+                    let ability = match abilities.abilities.get(wants_ability.ability) {
+                        Some(ability) => ability,
+                        None => continue,
+                    };
+                    let target = wants_ability.target;
+                    let target_pos = match world.get_component::<Position>(target) {
+                        Some(target_pos) => *target_pos,
+                        None => continue,
+                    };
+
+                    // Don't waste it on the dead
+                    match world.get_component::<Pools>(target) {
+                        Some(pools) if pools.hit_points.current > 0 => {}
+                        _ => continue,
+                    }
+
+                    let attacker_name = match world.get_component::<Name>(entity) {
+                        Some(name) => name.name.clone(),
+                        None => "-Unnamed-".to_string(),
+                    };
+                    let target_name = match world.get_component::<Name>(target) {
+                        Some(name) => name.name.clone(),
+                        None => "-Unnamed-".to_string(),
+                    };
+
+                    // Roll to hit against the target's defence, just like in melee
+                    let natural_roll = rng.roll_dice(1, 20);
+                    let hit_bonus = match (
+                        world.get_component::<Attributes>(entity),
+                        world.get_component::<Skills>(entity),
+                    ) {
+                        (Some(attributes), Some(skills)) => {
+                            ability_attack_bonus(ability.kind, &attributes, &skills)
+                        }
+                        _ => 0,
+                    };
+                    let target_armor_class = match (
+                        world.get_component::<Attributes>(target),
+                        world.get_component::<Skills>(target),
+                    ) {
+                        (Some(attributes), Some(skills)) => {
+                            let mut worn_armor = 0.0;
+                            for (armor, worn) in query_defense.iter(world) {
+                                if worn.owner == target {
+                                    worn_armor += armor.armor_class;
+                                }
+                            }
+                            let natural_armor = world
+                                .get_component::<NaturalAttackDefense>(target)
+                                .and_then(|nat| nat.armor_class);
+                            armor_class(natural_armor, &attributes, &skills, worn_armor)
+                        }
+                        _ => 10,
+                    };
+                    if natural_roll == 1
+                        || (natural_roll != 20 && natural_roll + hit_bonus <= target_armor_class)
+                    {
+                        log.entries.push(format!(
+                            "{} uses {} on {}, but misses.",
+                            attacker_name, ability.name, target_name
+                        ));
+                        particle_builder.request(
+                            target_pos.x,
+                            target_pos.y,
+                            rltk::RGB::named(rltk::BLUE),
+                            rltk::RGB::named(rltk::BLACK),
+                            rltk::to_cp437('‼'),
+                            200.0,
+                        );
+                        continue;
+                    }
+
+                    if ability.damage_n_dice > 0 {
+                        let damage = i32::max(
+                            0,
+                            rng.roll_dice(ability.damage_n_dice, ability.damage_die_type)
+                                + ability.damage_bonus,
+                        );
+                        SufferDamage::new_damage(
+                            command_buffer,
+                            target,
+                            damage,
+                            entity == **player_entity,
+                        );
+                        log.entries.push(format!(
+                            "{} uses {} on {}, inflicting {} hp.",
+                            attacker_name, ability.name, target_name, damage
+                        ));
+                        if target == **player_entity {
+                            RunStats::hurt_by(
+                                command_buffer,
+                                target,
+                                format!("{}'s {}", attacker_name, ability.name),
+                            );
+                        }
+                        particle_builder.request(
+                            target_pos.x,
+                            target_pos.y,
+                            rltk::RGB::named(rltk::RED),
+                            rltk::RGB::named(rltk::BLACK),
+                            rltk::to_cp437('‼'),
+                            200.0,
+                        );
+                    }
+
+                    if ability.confusion_turns > 0 {
+                        command_buffer.add_component(
+                            target,
+                            Confusion {
+                                turns: ability.confusion_turns,
+                            },
+                        );
+                        log.entries.push(format!(
+                            "{} uses {} on {}, confusing them.",
+                            attacker_name, ability.name, target_name
+                        ));
+                        particle_builder.request(
+                            target_pos.x,
+                            target_pos.y,
+                            rltk::RGB::named(rltk::MAGENTA),
+                            rltk::RGB::named(rltk::BLACK),
+                            rltk::to_cp437('?'),
+                            200.0,
+                        );
+                    }
+                }
+            },
+        )
+}
@@ -1,4 +1,4 @@
-use super::{components::*, Map};
+use super::{clock_system::GameClock, components::*, storage, Map};
 use legion::{
     entity::EntityAllocator,
     prelude::*,
@@ -12,8 +12,8 @@ use serde::{
     Deserialize, Deserializer, Serialize, Serializer,
 };
 use std::{
-    any::TypeId, cell::RefCell, collections::HashMap, fs, iter::FromIterator, marker::PhantomData,
-    path::Path, ptr::NonNull,
+    any::TypeId, cell::RefCell, collections::HashMap, iter::FromIterator, marker::PhantomData,
+    ptr::NonNull,
 };
 use type_uuid::TypeUuid;
 
@@ -374,30 +374,59 @@ impl legion::serialize::de::WorldDeserializer for DeserializeImpl {
 fn get_serializer() -> SerializeImpl {
     let comp_registrations = [
         ComponentRegistration::of::<Map>(),
+        ComponentRegistration::of::<GameClock>(),
         ComponentRegistration::of::<Position>(),
         ComponentRegistration::of::<Renderable>(),
         ComponentRegistration::of::<Viewshed>(),
         ComponentRegistration::of::<Name>(),
+        ComponentRegistration::of::<Faction>(),
         ComponentRegistration::of::<SufferDamage>(),
         ComponentRegistration::of::<Ranged>(),
+        ComponentRegistration::of::<TeachesSpell>(),
         ComponentRegistration::of::<InflictsDamage>(),
         ComponentRegistration::of::<AreaOfEffect>(),
         ComponentRegistration::of::<Confusion>(),
         ComponentRegistration::of::<ProvidesHealing>(),
-        // ComponentRegistration::of::<InBackpack>(), // FIXME: Do not loose player backpack!
         ComponentRegistration::of::<Equippable>(),
-        // ComponentRegistration::of::<Equipped>(), // FIXME: Do not loose player equipment!
         ComponentRegistration::of::<ParticleLifetime>(),
         ComponentRegistration::of::<HungerClock>(),
         ComponentRegistration::of::<Door>(),
+        ComponentRegistration::of::<Lock>(),
+        ComponentRegistration::of::<Key>(),
+        ComponentRegistration::of::<Lockpick>(),
+        ComponentRegistration::of::<Alarm>(),
+        ComponentRegistration::of::<MonsterMemory>(),
+        ComponentRegistration::of::<Morale>(),
+        ComponentRegistration::of::<Pack>(),
+        ComponentRegistration::of::<DailyRoutine>(),
+        ComponentRegistration::of::<Wandering>(),
+        ComponentRegistration::of::<Guarding>(),
+        ComponentRegistration::of::<SpawnsGas>(),
+        ComponentRegistration::of::<Gas>(),
+        ComponentRegistration::of::<Poisoned>(),
+        ComponentRegistration::of::<Darkvision>(),
+        ComponentRegistration::of::<LightSource>(),
         ComponentRegistration::of::<Quips>(),
+        ComponentRegistration::of::<QuestLog>(),
+        ComponentRegistration::of::<Reputation>(),
         ComponentRegistration::of::<Attributes>(),
         ComponentRegistration::of::<Skills>(),
         ComponentRegistration::of::<Pools>(),
+        ComponentRegistration::of::<UnspentPoints>(),
+        ComponentRegistration::of::<Perks>(),
+        ComponentRegistration::of::<SkillProgress>(),
+        ComponentRegistration::of::<RunStats>(),
+        ComponentRegistration::of::<KnownSpells>(),
+        ComponentRegistration::of::<Modifiers>(),
         ComponentRegistration::of::<MeleeWeapon>(),
         ComponentRegistration::of::<Wearable>(),
         ComponentRegistration::of::<NaturalAttackDefense>(),
+        ComponentRegistration::of::<SpecialAbilities>(),
         ComponentRegistration::of::<LootTable>(),
+        ComponentRegistration::of::<Container>(),
+        // Stand-ins for InBackpack, Equipped and InContainer, whose owners are entities
+        ComponentRegistration::of::<SaveId>(),
+        ComponentRegistration::of::<SerializedOwner>(),
     ];
     let tag_registrations = [
         TagRegistration::of::<Player>(),
@@ -405,12 +434,17 @@ fn get_serializer() -> SerializeImpl {
         TagRegistration::of::<BlocksTile>(),
         TagRegistration::of::<Item>(),
         TagRegistration::of::<Consumable>(),
+        TagRegistration::of::<Corpse>(),
         TagRegistration::of::<ProvidesFood>(),
+        TagRegistration::of::<Scroll>(),
+        TagRegistration::of::<MacGuffin>(),
         TagRegistration::of::<MagicMapper>(),
         TagRegistration::of::<Hidden>(),
         TagRegistration::of::<EntryTrigger>(),
         TagRegistration::of::<SingleActivation>(),
         TagRegistration::of::<BlocksVisibility>(),
+        TagRegistration::of::<TeleportsEntity>(),
+        TagRegistration::of::<Trapdoor>(),
         TagRegistration::of::<Bystander>(),
         TagRegistration::of::<Vendor>(),
         TagRegistration::of::<Carnivore>(),
@@ -452,36 +486,165 @@ fn get_deserializer() -> DeserializeImpl {
     }
 }
 
-#[cfg(not(target_arch = "wasm32"))]
-pub fn save_game(world: &mut World, map: &Map) {
-    // Add Map as an entity
+// Where the save lives in storage
+const SAVE_KEY: &str = "savegame.json";
+
+// Entities can't be serialized, so anything that refers to one is saved by number instead
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "34f9f25c-6df6-4e56-9365-768397949117"]
+struct SaveId {
+    id: u64,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+enum Ownership {
+    Backpack,
+    Equipped(EquipmentSlot),
+    Container,
+}
+
+#[derive(TypeUuid, Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[uuid = "91f0a93a-fb22-452b-934c-12593649bbe1"]
+struct SerializedOwner {
+    owner: u64,
+    ownership: Ownership,
+}
+
+/// Numbers every owner and records who owns what by those numbers
+fn store_owners(world: &mut World) {
+    let mut owned: Vec<(Entity, Entity, Ownership)> = Vec::new();
+    for (entity, pack) in Read::<InBackpack>::query().iter_entities(world) {
+        owned.push((entity, pack.owner, Ownership::Backpack));
+    }
+    for (entity, equipped) in Read::<Equipped>::query().iter_entities(world) {
+        owned.push((entity, equipped.owner, Ownership::Equipped(equipped.slot)));
+    }
+    for (entity, stored) in Read::<InContainer>::query().iter_entities(world) {
+        owned.push((entity, stored.owner, Ownership::Container));
+    }
+
+    let mut ids: HashMap<Entity, u64> = HashMap::new();
+    for (entity, owner, ownership) in owned {
+        let next_id = ids.len() as u64;
+        let owner = *ids.entry(owner).or_insert(next_id);
+        world
+            .add_component(entity, SerializedOwner { owner, ownership })
+            .expect("Cannot add component");
+    }
+    for (entity, id) in ids {
+        world
+            .add_component(entity, SaveId { id })
+            .expect("Cannot add component");
+    }
+}
+
+/// Turns the numbers written by `store_owners` back into ownership components
+fn restore_owners(world: &mut World) {
+    let ids: HashMap<u64, Entity> = Read::<SaveId>::query()
+        .iter_entities(world)
+        .map(|(entity, save_id)| (save_id.id, entity))
+        .collect();
+    let owned: Vec<(Entity, SerializedOwner)> = Read::<SerializedOwner>::query()
+        .iter_entities(world)
+        .map(|(entity, serialized)| (entity, *serialized))
+        .collect();
+
+    for (entity, serialized) in owned {
+        if let Some(owner) = ids.get(&serialized.owner).copied() {
+            match serialized.ownership {
+                Ownership::Backpack => world.add_component(entity, InBackpack { owner }),
+                Ownership::Equipped(slot) => world.add_component(entity, Equipped { owner, slot }),
+                Ownership::Container => world.add_component(entity, InContainer { owner }),
+            }
+            .expect("Cannot add component");
+        }
+    }
+    clear_owners(world);
+}
+
+fn clear_owners(world: &mut World) {
+    let owned: Vec<Entity> = Read::<SerializedOwner>::query()
+        .iter_entities(world)
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in owned {
+        world
+            .remove_component::<SerializedOwner>(entity)
+            .expect("Cannot remove component");
+    }
+    let owners: Vec<Entity> = Read::<SaveId>::query()
+        .iter_entities(world)
+        .map(|(entity, _)| entity)
+        .collect();
+    for entity in owners {
+        world
+            .remove_component::<SaveId>(entity)
+            .expect("Cannot remove component");
+    }
+}
+
+pub fn save_game(world: &mut World, map: &Map, clock: &GameClock) {
+    // Add Map and the time of day as entities
     let map_entity = world.insert((), vec![(map.clone(),)])[0];
+    let clock_entity = world.insert((), vec![(*clock,)])[0];
+    store_owners(world);
 
     let ser_helper = get_serializer();
-    let writer = std::fs::File::create("./savegame.json").unwrap();
     let serializable = legion::serialize::ser::serializable_world(&world, &ser_helper);
-    serde_json::to_writer_pretty(writer, &serializable).expect("Unable to save game");
+    let data = serde_json::to_string_pretty(&serializable).expect("Unable to save game");
+    storage::write(SAVE_KEY, &data);
 
     // Clean up
+    clear_owners(world);
     world.delete(map_entity);
+    world.delete(clock_entity);
 }
 
-#[cfg(target_arch = "wasm32")]
-pub fn save_game(_world: &mut World, _map: &Map) {}
-
 pub fn does_save_exist() -> bool {
-    Path::new("./savegame.json").exists()
+    storage::exists(SAVE_KEY)
 }
 
-pub fn load_game(mut world: &mut World) {
+pub fn load_game(mut world: &mut World, resources: &mut Resources) {
     world.delete_all();
 
     let de_helper = get_deserializer();
-    let data = fs::read_to_string("./savegame.json").unwrap();
+    let data = storage::read(SAVE_KEY).unwrap();
     let mut deserializer = serde_json::Deserializer::from_str(&data);
     legion::serialize::de::deserialize(&mut world, &de_helper, &mut deserializer).unwrap();
+    restore_owners(world);
+
+    // The Map and the time of day were saved as entities; turn them back into resources
+    let maps: Vec<(Entity, Map)> = Read::<Map>::query()
+        .iter_entities(world)
+        .map(|(entity, map)| (entity, (*map).clone()))
+        .collect();
+    for (entity, mut map) in maps {
+        // Tile contents aren't saved, the indexing system fills them in again
+        map.tile_content = vec![Vec::new(); map.tiles.len()];
+        resources.insert(map);
+        world.delete(entity);
+    }
+    let clocks: Vec<(Entity, GameClock)> = Read::<GameClock>::query()
+        .iter_entities(world)
+        .map(|(entity, clock)| (entity, *clock))
+        .collect();
+    for (entity, clock) in clocks {
+        resources.insert(clock);
+        world.delete(entity);
+    }
+
+    // The player came back as a new entity
+    let player = Read::<Position>::query()
+        .filter(tag::<Player>())
+        .iter_entities(world)
+        .map(|(entity, pos)| (entity, *pos))
+        .next();
+    if let Some((player_entity, pos)) = player {
+        resources.insert(player_entity);
+        resources.insert(rltk::Point::new(pos.x, pos.y));
+    }
 }
 
 pub fn delete_save() {
-    let _ = fs::remove_file("./savegame.json");
+    storage::remove(SAVE_KEY);
 }
@@ -0,0 +1,92 @@
+use super::{gamelog::GameLog, level_up::SKILLS, Pools, Skill, SkillProgress, Skills};
+use legion::prelude::*;
+
+// Practice needed to reach rank 1; every rank after that costs this much more
+const PRACTICE_PER_RANK: i32 = 10;
+// Practice can't push a skill more than this many ranks past the character's level
+const SKILL_CAP_OVER_LEVEL: i32 = 2;
+
+/// Skills put to good use this turn, each of which counts as practice
+pub struct SkillUses {
+    uses: Vec<(Entity, Skill)>,
+}
+
+impl SkillUses {
+    pub fn new() -> Self {
+        SkillUses { uses: Vec::new() }
+    }
+
+    pub fn record(&mut self, entity: Entity, skill: Skill) {
+        self.uses.push((entity, skill));
+    }
+}
+
+/// How much practice it takes to raise a skill from the given rank
+pub fn practice_needed(rank: i32) -> i32 {
+    PRACTICE_PER_RANK * i32::max(1, rank + 1)
+}
+
+/// The highest rank practice alone can take a skill to at this level
+pub fn skill_cap(level: i32) -> i32 {
+    level + SKILL_CAP_OVER_LEVEL
+}
+
+pub fn skill_name(skill: Skill) -> &'static str {
+    SKILLS
+        .iter()
+        .find(|(known, _name)| *known == skill)
+        .map_or("Unknown", |(_skill, name)| name)
+}
+
+pub fn build() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("skill_training")
+        .write_resource::<SkillUses>()
+        .read_resource::<Entity>()
+        .write_resource::<GameLog>()
+        .read_component::<Pools>()
+        .write_component::<Skills>()
+        .write_component::<SkillProgress>()
+        .build(|_, world, (skill_uses, player_entity, log), _| {
+            for (entity, skill) in skill_uses.uses.drain(..) {
+                let level = match world.get_component::<Pools>(entity) {
+                    Some(stats) => stats.level,
+                    None => continue,
+                };
+                let rank = match world.get_component::<Skills>(entity) {
+                    Some(skills) => *skills.skills.get(&skill).unwrap_or(&0),
+                    None => continue,
+                };
+                if rank >= skill_cap(level) {
+                    continue;
+                }
+
+                // Only those keeping track of their progress (the player) can improve
+                let improved = match world.get_component_mut::<SkillProgress>(entity) {
+                    Some(mut progress) => {
+                        let practice = progress.practice.entry(skill).or_insert(0);
+                        *practice += 1;
+                        if *practice >= practice_needed(rank) {
+                            *practice = 0;
+                            true
+                        } else {
+                            false
+                        }
+                    }
+                    None => continue,
+                };
+
+                if improved {
+                    if let Some(mut skills) = world.get_component_mut::<Skills>(entity) {
+                        skills.skills.insert(skill, rank + 1);
+                    }
+                    if entity == **player_entity {
+                        log.entries.push(format!(
+                            "Practice pays off: your {} skill rises to {}.",
+                            skill_name(skill),
+                            rank + 1
+                        ));
+                    }
+                }
+            }
+        })
+}
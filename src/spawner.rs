@@ -7,15 +7,114 @@ use std::collections::HashMap;
 const MAX_MONSTERS: i32 = 4;
 const BASE_ATTRIBUTE: i32 = 11;
 
+// Attribute point-buy: everything starts at the base, and going above 13 costs double
+pub const POINT_BUY_POOL: i32 = 6;
+pub const MIN_ATTRIBUTE: i32 = 8;
+pub const MAX_ATTRIBUTE: i32 = 15;
+pub const ATTRIBUTE_NAMES: [&str; 4] = ["Might", "Fitness", "Quickness", "Intelligence"];
+
+/// Everything chosen on the character creation screen
+pub struct CharacterSheet {
+    pub name: String,
+    pub scores: [i32; 4],
+    pub background: usize,
+    pub rolled: bool,
+}
+
+impl CharacterSheet {
+    pub fn new() -> Self {
+        CharacterSheet {
+            name: String::new(),
+            scores: [BASE_ATTRIBUTE; 4],
+            background: 0,
+            rolled: false,
+        }
+    }
+
+    fn cost(score: i32) -> i32 {
+        if score <= 13 {
+            score - BASE_ATTRIBUTE
+        } else {
+            (13 - BASE_ATTRIBUTE) + (score - 13) * 2
+        }
+    }
+
+    pub fn points_left(&self) -> i32 {
+        let spent: i32 = self.scores.iter().map(|score| Self::cost(*score)).sum();
+        POINT_BUY_POOL - spent
+    }
+
+    pub fn raise(&mut self, attribute: usize) {
+        let score = self.scores[attribute];
+        if !self.rolled
+            && score < MAX_ATTRIBUTE
+            && Self::cost(score + 1) - Self::cost(score) <= self.points_left()
+        {
+            self.scores[attribute] += 1;
+        }
+    }
+
+    pub fn lower(&mut self, attribute: usize) {
+        if !self.rolled && self.scores[attribute] > MIN_ATTRIBUTE {
+            self.scores[attribute] -= 1;
+        }
+    }
+
+    /// Rolls 4d6 for each attribute, keeping the best three dice
+    pub fn roll(&mut self, rng: &mut RandomNumberGenerator) {
+        for score in self.scores.iter_mut() {
+            let mut dice: Vec<i32> = (0..4).map(|_| rng.roll_dice(1, 6)).collect();
+            dice.sort();
+            *score = dice[1..].iter().sum();
+        }
+        self.rolled = true;
+    }
+
+    /// Goes back to point-buy from the base values
+    pub fn reset(&mut self) {
+        self.scores = [BASE_ATTRIBUTE; 4];
+        self.rolled = false;
+    }
+
+    pub fn player_name(&self) -> String {
+        if self.name.trim().is_empty() {
+            "Player".to_string()
+        } else {
+            self.name.trim().to_string()
+        }
+    }
+}
+
+fn attribute(score: i32) -> Attribute {
+    Attribute {
+        base: score,
+        modifiers: 0,
+        bonus: attr_bonus(score),
+    }
+}
+
 // Spawns the player and returns the entity object.
-pub fn player(world: &mut World, x: i32, y: i32) -> Entity {
+pub fn player(world: &mut World, x: i32, y: i32, sheet: &CharacterSheet) -> Entity {
+    let pm = PREFABS.lock().unwrap();
+    let background = backgrounds(&pm).get(sheet.background);
+
     let mut skills = Skills {
         skills: HashMap::new(),
     };
     skills.skills.insert(Skill::Melee, 1);
     skills.skills.insert(Skill::Defense, 1);
     skills.skills.insert(Skill::Magic, 1);
+    skills.skills.insert(Skill::Lockpicking, 1);
+    skills.skills.insert(Skill::Perception, 1);
+    if let Some(trained) = background.and_then(|b| b.skills.as_ref()) {
+        for (skill, level) in trained.iter() {
+            if let Some(skill) = string_to_skill(skill) {
+                skills.skills.insert(skill, *level);
+            }
+        }
+    }
 
+    let [might, fitness, quickness, intelligence] = sheet.scores;
     let player = world.insert(
         (Player, BlocksTile),
         vec![(
@@ -32,87 +131,114 @@ pub fn player(world: &mut World, x: i32, y: i32) -> Entity {
                 dirty: true,
             },
             Name {
-                name: "Player".to_string(),
+                name: sheet.player_name(),
             },
             HungerClock {
                 state: HungerState::WellFed,
                 duration: 20,
             },
             Attributes {
-                might: Attribute {
-                    base: 11,
-                    modifiers: 0,
-                    bonus: attr_bonus(BASE_ATTRIBUTE),
-                },
-                fitness: Attribute {
-                    base: BASE_ATTRIBUTE,
-                    modifiers: 0,
-                    bonus: attr_bonus(BASE_ATTRIBUTE),
-                },
-                quickness: Attribute {
-                    base: BASE_ATTRIBUTE,
-                    modifiers: 0,
-                    bonus: attr_bonus(BASE_ATTRIBUTE),
-                },
-                intelligence: Attribute {
-                    base: BASE_ATTRIBUTE,
-                    modifiers: 0,
-                    bonus: attr_bonus(BASE_ATTRIBUTE),
-                },
+                might: attribute(might),
+                fitness: attribute(fitness),
+                quickness: attribute(quickness),
+                intelligence: attribute(intelligence),
             },
             skills,
             Pools {
                 hit_points: Pool {
-                    current: player_hp_at_level(BASE_ATTRIBUTE, 1),
-                    max: player_hp_at_level(BASE_ATTRIBUTE, 1),
+                    current: player_hp_at_level(fitness, 1),
+                    max: player_hp_at_level(fitness, 1),
                 },
                 mana: Pool {
-                    current: mana_at_level(BASE_ATTRIBUTE, 1),
-                    max: mana_at_level(BASE_ATTRIBUTE, 1),
+                    current: mana_at_level(intelligence, 1),
+                    max: mana_at_level(intelligence, 1),
                 },
                 experience: 0,
                 level: 1,
+                gold: background.and_then(|b| b.gold).unwrap_or(0),
             },
         )],
     )[0];
+    world
+        .add_component(
+            player,
+            Faction {
+                name: "Player".to_string(),
+            },
+        )
+        .expect("Cannot add component");
+    world
+        .add_component(player, QuestLog { quests: Vec::new() })
+        .expect("Cannot add component");
+    world
+        .add_component(
+            player,
+            UnspentPoints {
+                attributes: 0,
+                skills: 0,
+                perks: 0,
+            },
+        )
+        .expect("Cannot add component");
+    world
+        .add_component(player, Perks { taken: Vec::new() })
+        .expect("Cannot add component");
+    world
+        .add_component(player, RunStats::new())
+        .expect("Cannot add component");
+    world
+        .add_component(
+            player,
+            SkillProgress {
+                practice: HashMap::new(),
+            },
+        )
+        .expect("Cannot add component");
+    world
+        .add_component(player, Modifiers::new())
+        .expect("Cannot add component");
 
-    // Starting equipment
-    spawn_named_entity(
-        &PREFABS.lock().unwrap(),
-        world,
-        "Rusty Longsword",
-        SpawnType::Equipped { by: player },
-    );
+    // Some backgrounds come with a spell or two already learned
+    let spells = background
+        .iter()
+        .flat_map(|background| background.spells.iter().flatten())
+        .filter_map(|name| get_spell(&pm, name))
+        .map(|spell| KnownSpell {
+            name: spell.name.clone(),
+            mana_cost: spell.mana_cost,
+        })
+        .collect();
+    world
+        .add_component(player, KnownSpells { spells })
+        .expect("Cannot add component");
+    world
+        .add_component(
+            player,
+            Reputation {
+                reactions: HashMap::new(),
+            },
+        )
+        .expect("Cannot add component");
+
+    // Starting equipment from the chosen background
+    if let Some(background) = background {
+        for item in background.equipped.iter().flatten() {
+            spawn_named_entity(&pm, world, item, SpawnType::Equipped { by: player });
+        }
+        for item in background.carried.iter().flatten() {
+            spawn_named_entity(&pm, world, item, SpawnType::Carried { by: player });
+        }
+    }
+
+    // Everybody packs supplies
     spawn_named_entity(
-        &PREFABS.lock().unwrap(),
+        &pm,
         world,
         "Dried Sausage",
         SpawnType::Carried { by: player },
     );
-    spawn_named_entity(
-        &PREFABS.lock().unwrap(),
-        world,
-        "Beer",
-        SpawnType::Carried { by: player },
-    );
-    spawn_named_entity(
-        &PREFABS.lock().unwrap(),
-        world,
-        "Stained Tunic",
-        SpawnType::Equipped { by: player },
-    );
-    spawn_named_entity(
-        &PREFABS.lock().unwrap(),
-        world,
-        "Torn Trousers",
-        SpawnType::Equipped { by: player },
-    );
-    spawn_named_entity(
-        &PREFABS.lock().unwrap(),
-        world,
-        "Old Boots",
-        SpawnType::Equipped { by: player },
-    );
+    spawn_named_entity(&pm, world, "Beer", SpawnType::Carried { by: player });
+    spawn_named_entity(&pm, world, "Lantern", SpawnType::Carried { by: player });
 
     player
 }
@@ -127,6 +253,7 @@ pub fn spawn_room(
     room: &Rect,
     map_depth: i32,
     spawn_list: &mut Vec<(usize, String)>,
+    spawn_groups: &mut Vec<Vec<usize>>,
 ) {
     let mut possible_targets = Vec::new();
 
@@ -142,15 +269,23 @@ pub fn spawn_room(
         }
     }
 
-    spawn_region(map, rng, &possible_targets, map_depth, spawn_list);
+    spawn_region(
+        map,
+        rng,
+        &possible_targets,
+        map_depth,
+        spawn_list,
+        spawn_groups,
+    );
 }
 
 pub fn spawn_region(
-    _map: &Map,
+    map: &Map,
     rng: &mut RandomNumberGenerator,
     area: &[usize],
     map_depth: i32,
     spawn_list: &mut Vec<(usize, String)>,
+    spawn_groups: &mut Vec<Vec<usize>>,
 ) {
     let spawn_table = room_table(map_depth);
     let mut spawn_points = HashMap::new();
@@ -165,14 +300,49 @@ pub fn spawn_region(
     }
 
     for _i in 0..num_spawns {
-        let array_index = if area.len() == 1 {
+        // Packs may have used up the space already
+        if areas.is_empty() {
+            break;
+        }
+        let array_index = if areas.len() == 1 {
             0
         } else {
             (rng.roll_dice(1, areas.len() as i32) - 1) as usize
         };
         let map_idx = areas[array_index];
-        spawn_points.insert(map_idx, spawn_table.roll(rng));
+        let spawn = spawn_table.roll(rng);
         areas.remove(array_index);
+
+        // Some things come in groups, gathered on the closest free tiles
+        if let Some(name) = &spawn {
+            if let Some((n_dice, die_type, bonus)) =
+                spawn_group_size(&PREFABS.lock().unwrap(), name)
+            {
+                let size = rng.roll_dice(n_dice, die_type) + bonus;
+                let leader =
+                    rltk::Point::new(map_idx as i32 % map.width, map_idx as i32 / map.width);
+                areas.sort_by(|a, b| {
+                    let a_pos = rltk::Point::new(*a as i32 % map.width, *a as i32 / map.width);
+                    let b_pos = rltk::Point::new(*b as i32 % map.width, *b as i32 / map.width);
+                    let a_distance = rltk::DistanceAlg::Pythagoras.distance2d(leader, a_pos);
+                    let b_distance = rltk::DistanceAlg::Pythagoras.distance2d(leader, b_pos);
+                    a_distance.partial_cmp(&b_distance).unwrap()
+                });
+
+                let mut group = vec![map_idx];
+                for _member in 1..size {
+                    if areas.is_empty() {
+                        break;
+                    }
+                    let member_idx = areas.remove(0);
+                    spawn_points.insert(member_idx, spawn.clone());
+                    group.push(member_idx);
+                }
+                spawn_groups.push(group);
+            }
+        }
+
+        spawn_points.insert(map_idx, spawn);
     }
 
     // Actually spawn the monsters
@@ -184,7 +354,7 @@ pub fn spawn_region(
 }
 
 // Spawn a named entity at the location
-pub fn spawn_entity(world: &mut World, map: &Map, idx: &usize, name: &str) {
+pub fn spawn_entity(world: &mut World, map: &Map, idx: &usize, name: &str) -> Option<Entity> {
     let x = *idx as i32 % map.width;
     let y = *idx as i32 / map.width;
 
@@ -194,9 +364,17 @@ pub fn spawn_entity(world: &mut World, map: &Map, idx: &usize, name: &str) {
         name,
         SpawnType::AtPosition { x, y },
     );
-    if item_result.is_some() {
-        return;
+    if let Some(entity) = item_result {
+        // Locks and keys are cut per level
+        if let Some(mut lock) = world.get_component_mut::<Lock>(entity) {
+            lock.key_id = map.depth;
+        }
+        if let Some(mut key) = world.get_component_mut::<Key>(entity) {
+            key.key_id = map.depth;
+        }
+        return Some(entity);
     }
 
     rltk::console::log(format!("WARNING: Don't know how to spawn [{}]!", name));
+    None
 }
@@ -0,0 +1,137 @@
+use super::{
+    clock_system::GameClock, gamelog::GameLog, skill_bonus, skill_system::SkillUses, Attributes,
+    InflictsDamage, KnownSpells, Player, Pools, ProvidesHealing, RunState, Skill, Skills,
+    WantsToUseItem,
+};
+use crate::prefabs::{get_spell, spawn_named_spell, PREFABS};
+use legion::prelude::*;
+use rltk::{Point, RandomNumberGenerator};
+
+// Rounds between each point of the player's mana coming back; mobs get theirs back while resting
+const MANA_REGEN_TURNS: i32 = 4;
+
+pub fn build() -> Box<(dyn Schedulable + 'static)> {
+    SystemBuilder::new("mana_regen")
+        .read_resource::<GameClock>()
+        .read_resource::<RunState>()
+        .with_query(Write::<Pools>::query().filter(tag::<Player>()))
+        .build(|_, world, (clock, runstate), query| {
+            if **runstate != RunState::MonsterTurn || clock.turn % MANA_REGEN_TURNS != 0 {
+                return;
+            }
+            for mut stats in query.iter_mut(world) {
+                stats.mana.current = i32::min(stats.mana.max, stats.mana.current + 1);
+            }
+        })
+}
+
+/// Starts casting one of the player's known spells, asking for a target if it needs one
+pub fn begin_casting(world: &mut World, resources: &Resources, index: usize) -> RunState {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let spell = match world
+        .get_component::<KnownSpells>(player_entity)
+        .and_then(|known| known.spells.get(index).cloned())
+    {
+        Some(spell) => spell,
+        None => return RunState::AwaitingInput,
+    };
+
+    let mana = world
+        .get_component::<Pools>(player_entity)
+        .map_or(0, |stats| stats.mana.current);
+    if mana < spell.mana_cost {
+        resources
+            .get_mut::<GameLog>()
+            .unwrap()
+            .entries
+            .push(format!(
+                "You don't have enough mana to cast {}.",
+                spell.name
+            ));
+        return RunState::AwaitingInput;
+    }
+
+    let range = {
+        let pm = PREFABS.lock().unwrap();
+        get_spell(&pm, &spell.name)
+            .and_then(|spell| spell.effects.get("ranged"))
+            .and_then(|range| range.parse().ok())
+    };
+    match range {
+        Some(range) => RunState::ShowSpellTargeting {
+            range,
+            spell: index,
+        },
+        None => cast_spell(world, resources, index, None),
+    }
+}
+
+/// Spends the mana and rolls to see if the spell works, and how well
+pub fn cast_spell(
+    world: &mut World,
+    resources: &Resources,
+    index: usize,
+    target: Option<Point>,
+) -> RunState {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let spell = match world
+        .get_component::<KnownSpells>(player_entity)
+        .and_then(|known| known.spells.get(index).cloned())
+    {
+        Some(spell) => spell,
+        None => return RunState::AwaitingInput,
+    };
+    let pm = PREFABS.lock().unwrap();
+    let difficulty = match get_spell(&pm, &spell.name) {
+        Some(template) => template.difficulty,
+        None => return RunState::AwaitingInput,
+    };
+
+    match world.get_component_mut::<Pools>(player_entity) {
+        Some(mut stats) if stats.mana.current >= spell.mana_cost => {
+            stats.mana.current -= spell.mana_cost;
+        }
+        _ => return RunState::AwaitingInput,
+    }
+
+    // Casting is a Magic check, and the same bonus makes the spell hit harder
+    let intelligence = world
+        .get_component::<Attributes>(player_entity)
+        .map_or(0, |attributes| attributes.intelligence.bonus);
+    let magic = world
+        .get_component::<Skills>(player_entity)
+        .map_or(-4, |skills| skill_bonus(Skill::Magic, &skills));
+    let power = intelligence + magic;
+    let natural_roll = resources
+        .get_mut::<RandomNumberGenerator>()
+        .unwrap()
+        .roll_dice(1, 20);
+    if natural_roll == 1 || natural_roll + power < difficulty {
+        resources
+            .get_mut::<GameLog>()
+            .unwrap()
+            .entries
+            .push(format!("Your {} spell fizzles.", spell.name));
+        return RunState::PlayerTurn;
+    }
+    resources
+        .get_mut::<SkillUses>()
+        .unwrap()
+        .record(player_entity, Skill::Magic);
+
+    let cast = match spawn_named_spell(&pm, world, &spell.name) {
+        Some(cast) => cast,
+        None => return RunState::PlayerTurn,
+    };
+    if let Some(mut damage) = world.get_component_mut::<InflictsDamage>(cast) {
+        damage.damage = i32::max(1, damage.damage + power);
+    }
+    if let Some(mut healing) = world.get_component_mut::<ProvidesHealing>(cast) {
+        healing.heal_amount = i32::max(1, healing.heal_amount + power);
+    }
+    world
+        .add_component(player_entity, WantsToUseItem { item: cast, target })
+        .expect("Unable to insert intent");
+
+    RunState::PlayerTurn
+}
@@ -0,0 +1,75 @@
+// Somewhere to keep small text files between runs: the working directory on native
+// builds, and the browser's local storage on the web.
+
+#[cfg(not(target_arch = "wasm32"))]
+mod native {
+    use std::{fs, path::Path};
+
+    fn path(key: &str) -> String {
+        format!("./{}", key)
+    }
+
+    pub fn exists(key: &str) -> bool {
+        Path::new(&path(key)).exists()
+    }
+
+    pub fn read(key: &str) -> Option<String> {
+        fs::read_to_string(path(key)).ok()
+    }
+
+    pub fn write(key: &str, contents: &str) {
+        if let Err(err) = fs::write(path(key), contents) {
+            rltk::console::log(format!("Unable to write {}: {}", key, err));
+        }
+    }
+
+    pub fn remove(key: &str) {
+        let _ = fs::remove_file(path(key));
+    }
+
+    /// Seconds since the Unix epoch
+    pub fn now() -> u64 {
+        std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_secs())
+    }
+}
+
+#[cfg(target_arch = "wasm32")]
+mod web {
+    fn local_storage() -> Option<web_sys::Storage> {
+        web_sys::window()?.local_storage().ok()?
+    }
+
+    pub fn exists(key: &str) -> bool {
+        read(key).is_some()
+    }
+
+    pub fn read(key: &str) -> Option<String> {
+        local_storage()?.get_item(key).ok()?
+    }
+
+    pub fn write(key: &str, contents: &str) {
+        if let Some(storage) = local_storage() {
+            if storage.set_item(key, contents).is_err() {
+                rltk::console::log(format!("Unable to write {}", key));
+            }
+        }
+    }
+
+    pub fn remove(key: &str) {
+        if let Some(storage) = local_storage() {
+            let _ = storage.remove_item(key);
+        }
+    }
+
+    /// Seconds since the Unix epoch
+    pub fn now() -> u64 {
+        (js_sys::Date::now() / 1000.0) as u64
+    }
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use native::*;
+#[cfg(target_arch = "wasm32")]
+pub use web::*;
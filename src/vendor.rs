@@ -0,0 +1,75 @@
+use super::{gamelog::GameLog, InBackpack, InContainer, Name, Pools};
+use crate::prefabs::{item_value, spawn_named_item, SpawnType, PREFABS};
+use legion::prelude::*;
+
+// Vendors buy things back for a fraction of what they sell them for
+const RESALE_DIVISOR: i32 = 2;
+
+/// What a vendor will pay for an item worth `value`
+pub fn resale_price(value: i32) -> i32 {
+    i32::max(1, value / RESALE_DIVISOR)
+}
+
+/// Everything in the player's backpack a vendor would buy, with what they'd pay for it
+pub fn sellable_items(world: &World, player: Entity) -> Vec<(Entity, String, i32)> {
+    let pm = PREFABS.lock().unwrap();
+    <(Read<InBackpack>, Read<Name>)>::query()
+        .iter_entities(world)
+        .filter(|(_entity, (pack, _name))| pack.owner == player)
+        .filter_map(|(entity, (_pack, name))| {
+            item_value(&pm, &name.name)
+                .map(|value| (entity, name.name.clone(), resale_price(value)))
+        })
+        .collect()
+}
+
+pub fn buy(world: &mut World, resources: &Resources, item: &str, price: i32) {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let mut log = resources.get_mut::<GameLog>().unwrap();
+
+    match world.get_component_mut::<Pools>(player_entity) {
+        Some(mut stats) if stats.gold >= price => stats.gold -= price,
+        _ => {
+            log.entries.push(format!("You can't afford the {}.", item));
+            return;
+        }
+    }
+    spawn_named_item(
+        &PREFABS.lock().unwrap(),
+        world,
+        item,
+        SpawnType::Carried { by: player_entity },
+    );
+    log.entries
+        .push(format!("You buy the {} for {} gold.", item, price));
+}
+
+pub fn sell(world: &mut World, resources: &Resources, item: Entity) {
+    let player_entity = *resources.get::<Entity>().unwrap();
+    let mut log = resources.get_mut::<GameLog>().unwrap();
+    let name = match world.get_component::<Name>(item) {
+        Some(name) => name.name.clone(),
+        None => return,
+    };
+    let price = match item_value(&PREFABS.lock().unwrap(), &name) {
+        Some(value) => resale_price(value),
+        None => return,
+    };
+
+    // Nobody wants a bag with someone else's things still in it
+    if Read::<InContainer>::query()
+        .iter(world)
+        .any(|stored| stored.owner == item)
+    {
+        log.entries
+            .push(format!("You should empty the {} first.", name));
+        return;
+    }
+
+    world.delete(item);
+    if let Some(mut stats) = world.get_component_mut::<Pools>(player_entity) {
+        stats.gold += price;
+    }
+    log.entries
+        .push(format!("You sell the {} for {} gold.", name, price));
+}
@@ -1,22 +1,34 @@
 use super::{
-    field_of_view, gamelog::GameLog, BlocksVisibility, Hidden, Map, Name, Player, Position,
+    field_of_view, gamelog::GameLog, lighting_system::is_lit, perception_bonus, Attributes,
+    BlocksVisibility, Darkvision, Door, Hidden, Map, Name, Player, Position, Skills, TileType,
     Viewshed,
 };
 use legion::prelude::*;
-use rltk::Point;
+use rltk::{DistanceAlg, Point};
 use std::collections::HashSet;
 
+// Under the open sky, even the night is not pitch black
+const STARLIGHT_RANGE: f32 = 4.0;
+
 pub fn build() -> Box<(dyn Schedulable + 'static)> {
     SystemBuilder::new("visibility_system")
         .write_resource::<Map>()
         .write_resource::<rltk::RandomNumberGenerator>()
         .write_resource::<GameLog>()
+        .read_resource::<Entity>()
         .with_query(<(Write<Viewshed>, Read<Position>)>::query())
         .with_query(Read::<Position>::query().filter(tag::<BlocksVisibility>()))
         .read_component::<Player>()
         .read_component::<Name>()
+        .read_component::<Attributes>()
+        .read_component::<Skills>()
+        .read_component::<Door>()
+        .read_component::<Darkvision>()
         .build(
-            |command_buffer, world, (map, rng, log), (viewshed_query, view_blocked_query)| {
+            |command_buffer,
+             world,
+             (map, rng, log, player_entity),
+             (viewshed_query, view_blocked_query)| {
                 map.view_blocked.clear();
                 for position in view_blocked_query.iter(world) {
                     let idx = map.xy_idx(position.x, position.y);
@@ -38,6 +50,7 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
 
                     let viewsheds = &mut chunk.components_mut::<Viewshed>().unwrap();
                     let positions = &chunk.components::<Position>().unwrap();
+                    let darkvision = chunk.components::<Darkvision>();
 
                     for (i, pos) in positions.iter().enumerate() {
                         let viewshed = &mut viewsheds[i];
@@ -49,6 +62,23 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                                 viewshed.range as usize,
                                 &**map,
                             );
+
+                            // Dark tiles can only be seen up close, or with darkvision
+                            let origin = Point::new(pos.x, pos.y);
+                            let dark_range = match &darkvision {
+                                Some(darkvision) => f32::max(1.5, darkvision[i].range as f32),
+                                None => 1.5,
+                            };
+                            let dark_range = if map.outdoors {
+                                f32::max(dark_range, STARLIGHT_RANGE)
+                            } else {
+                                dark_range
+                            };
+                            viewshed.visible_tiles.retain(|tile| {
+                                is_lit(&**map, map.xy_idx(tile.x, tile.y))
+                                    || DistanceAlg::Pythagoras.distance2d(origin, *tile)
+                                        <= dark_range
+                            });
                         }
 
                         // If this is the player, reveal what they can see
@@ -63,19 +93,37 @@ pub fn build() -> Box<(dyn Schedulable + 'static)> {
                     }
                 }
 
+                // Passive perception check
+                let perception = match (
+                    world.get_component::<Attributes>(**player_entity),
+                    world.get_component::<Skills>(**player_entity),
+                ) {
+                    (Some(attributes), Some(skills)) => perception_bonus(&attributes, &skills),
+                    _ => 0,
+                };
+
+                let mut found_doors = Vec::new();
                 for idx in seen_tiles.iter() {
                     // Chance to reveal hidden things
                     for e in map.tile_content[*idx].iter() {
                         if let Some(_hidden) = world.get_tag::<Hidden>(*e) {
-                            if rng.roll_dice(1, 24) == 1 {
+                            if rng.roll_dice(1, 24) + perception >= 24 {
                                 if let Some(name) = world.get_component::<Name>(*e) {
                                     log.entries.push(format!("You spotted a {}.", &name.name));
                                 }
                                 command_buffer.remove_tag::<Hidden>(*e);
+                                if world.get_component::<Door>(*e).is_some() {
+                                    found_doors.push(*idx);
+                                }
                             }
                         }
                     }
                 }
+
+                // Secret doors stop being walls once found
+                for idx in found_doors.iter() {
+                    map.tiles[*idx] = TileType::Floor;
+                }
             },
         )
 }